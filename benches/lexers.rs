@@ -4,10 +4,69 @@ extern crate test;
 extern crate luthor;
 
 use test::Bencher;
-use luthor::lexers::json::lex;
+use luthor::lexers;
+use luthor::token::Token;
+
+// The test data is tiny, so it's repeated to give
+// the lexers enough work to produce a useful throughput.
+const REPETITIONS: usize = 100;
+
+fn bench_lex(b: &mut Bencher, lex: fn(&str) -> Vec<Token>, data: &str) {
+    let data = data.repeat(REPETITIONS);
+    b.bytes = data.len() as u64;
+    b.iter(|| lex(&data));
+}
+
+#[bench]
+fn bench_coffeescript(b: &mut Bencher) {
+    bench_lex(b, lexers::coffeescript::lex, include_str!("../test_data/data.coffee"));
+}
+
+#[bench]
+fn bench_default(b: &mut Bencher) {
+    bench_lex(b, lexers::default::lex, include_str!("../test_data/data.txt"));
+}
+
+#[bench]
+fn bench_html_erb(b: &mut Bencher) {
+    bench_lex(b, lexers::html_erb::lex, include_str!("../test_data/html_erb.html.erb"));
+}
+
+#[bench]
+fn bench_javascript(b: &mut Bencher) {
+    bench_lex(b, lexers::javascript::lex, include_str!("../test_data/data.js"));
+}
 
 #[bench]
 fn bench_json(b: &mut Bencher) {
-    let data = include_str!("../test_data/data.json");
-    b.iter(|| lex(data));
+    bench_lex(b, lexers::json::lex, include_str!("../test_data/data.json"));
+}
+
+#[bench]
+fn bench_json_borrowed(b: &mut Bencher) {
+    let data = include_str!("../test_data/data.json").repeat(REPETITIONS);
+    b.bytes = data.len() as u64;
+    b.iter(|| lexers::json::lex_borrowed(&data).count());
+}
+
+#[bench]
+fn bench_ruby(b: &mut Bencher) {
+    bench_lex(b, lexers::ruby::lex, include_str!("../test_data/ruby.rb"));
+}
+
+#[bench]
+fn bench_rust(b: &mut Bencher) {
+    bench_lex(b, lexers::rust::lex, include_str!("../test_data/rust.rs"));
+}
+
+#[bench]
+fn bench_rust_borrowed(b: &mut Bencher) {
+    let data = include_str!("../test_data/rust.rs").repeat(REPETITIONS);
+    b.bytes = data.len() as u64;
+    b.iter(|| lexers::rust::lex_borrowed(&data).count());
+}
+
+#[bench]
+fn bench_xml(b: &mut Bencher) {
+    bench_lex(b, lexers::xml::lex, include_str!("../test_data/data.xml"));
 }
@@ -4,18 +4,49 @@ extern crate test;
 extern crate luthor;
 
 use test::Bencher;
-use luthor::tokenizer::new;
+use luthor::Tokenizer;
+use luthor::token::Category;
 
 #[bench]
 fn bench_current_char(b: &mut Bencher) {
     let data = include_str!("../test_data/data.json");
-    let tokenizer = new(data);
+    let tokenizer = Tokenizer::new(data);
     b.iter(|| tokenizer.current_char());
 }
 
 #[bench]
-fn bench_starts_with(b: &mut Bencher) {
+fn bench_has_prefix(b: &mut Bencher) {
     let data = include_str!("../test_data/data.json");
-    let tokenizer = new(data);
-    b.iter(|| tokenizer.starts_with("something"));
+    let tokenizer = Tokenizer::new(data);
+    b.iter(|| tokenizer.has_prefix("something"));
+}
+
+#[bench]
+fn bench_starts_with_lexeme(b: &mut Bencher) {
+    let data = include_str!("../test_data/data.json");
+    let tokenizer = Tokenizer::new(data);
+    b.iter(|| tokenizer.starts_with_lexeme("{"));
+}
+
+#[bench]
+fn bench_advance_and_tokenize(b: &mut Bencher) {
+    let data = include_str!("../test_data/ruby.rb").repeat(100);
+    b.bytes = data.len() as u64;
+    b.iter(|| {
+        let mut tokenizer = Tokenizer::new(&data);
+        while tokenizer.current_char().is_some() {
+            tokenizer.advance();
+            tokenizer.advance();
+            tokenizer.tokenize(Category::Text);
+        }
+        tokenizer.tokens()
+    });
+}
+
+#[bench]
+fn bench_remaining_tokens(b: &mut Bencher) {
+    let data = include_str!("../test_data/ruby.rb").repeat(100);
+    let tokenizer = Tokenizer::new(&data);
+    b.bytes = data.len() as u64;
+    b.iter(|| tokenizer.tokens());
 }
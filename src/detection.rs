@@ -0,0 +1,334 @@
+//! Language detection, used to choose a lexer for data based on its file
+//! name and content. Evidence is gathered from well-known file names, file
+//! extensions, `#!` interpreter lines, vim/emacs modelines, and finally from
+//! the lexers themselves, which can recognize their languages' content.
+//!
+//! See `detect` for the primary entry point, or `lexers::Registry::detect`
+//! to detect languages using a registry that includes other lexers.
+//!
+//! Some well-known file names aren't recognized because luthor has no lexer
+//! for their format; notably `Cargo.lock` and `Cargo.toml`, which are TOML.
+
+use std::path::Path;
+use lexers::{self, Lexer};
+
+// The confidence ascribed to each type of evidence. Modelines and
+// well-known file names are explicit, whereas extensions are conventional.
+const MODELINE: f32 = 1.0;
+const FILENAME: f32 = 1.0;
+const SHEBANG: f32 = 0.9;
+const COMPOUND_EXTENSION: f32 = 0.85;
+const EXTENSION: f32 = 0.8;
+
+// Modelines are only honoured near the start or end of a document.
+const MODELINE_LINES: usize = 5;
+
+/// A lexer chosen for some data, along with a confidence
+/// between 0 (a guess) and 1 (explicitly declared).
+pub struct Detection<'a> {
+    pub lexer: &'a dyn Lexer,
+    pub confidence: f32,
+}
+
+/// The facts about some data that are relevant to detecting its language,
+/// gathered once so that they can be compared against many lexers.
+pub(crate) struct Evidence<'a> {
+    file_name: Option<String>,
+    interpreter: Option<&'a str>,
+    modeline: Option<&'a str>,
+    content: &'a str,
+}
+
+impl<'a> Evidence<'a> {
+    pub(crate) fn gather(path: Option<&Path>, content: &'a str) -> Evidence<'a> {
+        Evidence{
+            file_name: path
+                .and_then(|path| path.file_name())
+                .and_then(|name| name.to_str())
+                .map(|name| name.to_lowercase()),
+            interpreter: shebang_interpreter(content),
+            modeline: modeline(content),
+            content,
+        }
+    }
+
+    /// How likely it is that the lexer is appropriate for the data, from 0 to 1.
+    pub(crate) fn confidence(&self, lexer: &dyn Lexer) -> f32 {
+        let mut confidence = lexer.analyse(self.content).clamp(0.0, 1.0);
+
+        if let Some(mode) = self.modeline {
+            if names(lexer).any(|name| name.eq_ignore_ascii_case(mode)) {
+                confidence = confidence.max(MODELINE);
+            }
+        }
+
+        if let Some(ref file_name) = self.file_name {
+            if lexer.filenames().iter().any(|name| name.eq_ignore_ascii_case(file_name)) {
+                confidence = confidence.max(FILENAME);
+            }
+
+            for extension in lexer.extensions() {
+                let suffix = format!(".{}", extension.to_lowercase());
+                if file_name.ends_with(&suffix) && file_name.len() > suffix.len() {
+                    let score = if extension.contains('.') { COMPOUND_EXTENSION } else { EXTENSION };
+                    confidence = confidence.max(score);
+                }
+            }
+        }
+
+        if let Some(interpreter) = self.interpreter {
+            if lexer.interpreters().contains(&interpreter) {
+                confidence = confidence.max(SHEBANG);
+            }
+        }
+
+        confidence
+    }
+}
+
+/// Detects the language of the data using luthor's lexers, returning the
+/// best match along with a confidence, or `None` if there's no evidence.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use luthor::detection::detect;
+///
+/// let detection = detect(Some(Path::new("Gemfile")), "gem 'luthor'").unwrap();
+///
+/// assert_eq!(detection.lexer.name(), "Ruby");
+/// assert_eq!(detection.confidence, 1.0);
+/// ```
+pub fn detect(path: Option<&Path>, content: &str) -> Option<Detection<'static>> {
+    lexers::shared_registry().detect(path, content)
+}
+
+// A lexer's name, followed by its aliases.
+fn names(lexer: &dyn Lexer) -> impl Iterator<Item = &str> {
+    Some(lexer.name()).into_iter().chain(lexer.aliases().iter().cloned())
+}
+
+/// Finds the interpreter named on a `#!` line at the start of the data,
+/// looking past `env` and removing any version number from its name.
+///
+/// # Examples
+///
+/// ```
+/// use luthor::detection::shebang_interpreter;
+///
+/// assert_eq!(shebang_interpreter("#!/usr/bin/ruby -w\nputs 1"), Some("ruby"));
+/// assert_eq!(shebang_interpreter("#!/usr/bin/env -S node2.1 --harmony"), Some("node"));
+/// assert_eq!(shebang_interpreter("puts 1"), None);
+/// ```
+pub fn shebang_interpreter(data: &str) -> Option<&str> {
+    if !data.starts_with("#!") {
+        return None
+    }
+
+    let line = data[2..].lines().next().unwrap_or("");
+    let mut arguments = line.split_whitespace();
+    let mut program = basename(arguments.next()?);
+
+    // When using env, the interpreter is the first non-option argument.
+    if program == "env" {
+        program = basename(arguments.find(|argument| !argument.starts_with('-'))?);
+    }
+
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    if name.is_empty() { None } else { Some(name) }
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Finds the language named by a vim or emacs modeline in the first or last
+/// few lines of the data, recognizing `vim: set ft=ruby:`, `vi: filetype=ruby`,
+/// `-*- ruby -*-` and `-*- mode: ruby -*-` forms.
+///
+/// # Examples
+///
+/// ```
+/// use luthor::detection::modeline;
+///
+/// assert_eq!(modeline("# -*- mode: ruby; coding: utf-8 -*-\n"), Some("ruby"));
+/// assert_eq!(modeline("data\n// vim: set ts=4 ft=javascript:\n"), Some("javascript"));
+/// assert_eq!(modeline("data\n"), None);
+/// ```
+pub fn modeline(data: &str) -> Option<&str> {
+    let lines: Vec<&str> = data.lines().collect();
+    let tail_start = lines.len().saturating_sub(MODELINE_LINES).max(MODELINE_LINES);
+    let candidates = lines.iter().take(MODELINE_LINES).chain(lines.iter().skip(tail_start));
+
+    for line in candidates {
+        if let Some(mode) = emacs_modeline(line).or_else(|| vim_modeline(line)) {
+            return Some(mode)
+        }
+    }
+
+    None
+}
+
+fn emacs_modeline(line: &str) -> Option<&str> {
+    let start = line.find("-*-")? + 3;
+    let length = line[start..].find("-*-")?;
+    let variables = line[start..start + length].trim();
+
+    if !variables.contains(':') {
+        return non_empty(variables)
+    }
+
+    for variable in variables.split(';') {
+        // Variables without values (e.g. a bare `foo`) are skipped.
+        let (name, value) = match variable.split_once(':') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => continue,
+        };
+        if name.eq_ignore_ascii_case("mode") {
+            return non_empty(value)
+        }
+    }
+
+    None
+}
+
+fn vim_modeline(line: &str) -> Option<&str> {
+    for marker in ["vim:", "vi:", "ex:"] {
+        let start = match line.find(marker) {
+            Some(start) => start,
+            None => continue,
+        };
+
+        // The marker must start the line or follow whitespace.
+        if start > 0 && !line[..start].ends_with(char::is_whitespace) {
+            continue
+        }
+
+        let options = line[start + marker.len()..]
+            .split(|c: char| c.is_whitespace() || c == ':');
+        for option in options {
+            let mut parts = option.splitn(2, '=');
+            let (name, value) = (parts.next()?, parts.next());
+            match (name, value) {
+                ("ft", Some(value)) | ("filetype", Some(value)) |
+                ("syn", Some(value)) | ("syntax", Some(value)) => return non_empty(value),
+                _ => (),
+            }
+        }
+    }
+
+    None
+}
+
+fn non_empty(value: &str) -> Option<&str> {
+    if value.is_empty() { None } else { Some(value) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use lexers::registry;
+    use super::{modeline, shebang_interpreter};
+
+    fn detect(path: Option<&str>, content: &str) -> Option<(String, f32)> {
+        registry()
+            .detect(path.map(Path::new), content)
+            .map(|detection| (detection.lexer.name().to_string(), detection.confidence))
+    }
+
+    #[test]
+    fn it_detects_languages_using_extensions() {
+        assert_eq!(detect(Some("src/main.rs"), ""), Some(("Rust".to_string(), 0.8)));
+        assert_eq!(detect(Some("app.JS"), ""), Some(("JavaScript".to_string(), 0.8)));
+    }
+
+    #[test]
+    fn it_prefers_compound_extensions() {
+        assert_eq!(
+            detect(Some("views/index.html.erb"), "<p></p>"),
+            Some(("HTML+ERB".to_string(), 0.85))
+        );
+    }
+
+    #[test]
+    fn it_detects_languages_using_well_known_file_names() {
+        assert_eq!(detect(Some("Gemfile"), ""), Some(("Ruby".to_string(), 1.0)));
+        assert_eq!(detect(Some("/project/Rakefile"), ""), Some(("Ruby".to_string(), 1.0)));
+    }
+
+    #[test]
+    fn it_does_not_detect_file_names_without_lexers() {
+        // Cargo.lock is TOML, for which luthor has no lexer.
+        assert_eq!(detect(Some("Cargo.lock"), "version = 3\n"), None);
+    }
+
+    #[test]
+    fn it_does_not_treat_file_names_as_extensions() {
+        assert_eq!(detect(Some("rs"), ""), None);
+    }
+
+    #[test]
+    fn it_detects_languages_using_shebangs() {
+        assert_eq!(
+            detect(Some("bin/server"), "#!/usr/bin/env node\n"),
+            Some(("JavaScript".to_string(), 0.9))
+        );
+    }
+
+    #[test]
+    fn it_prefers_modelines_to_extensions() {
+        assert_eq!(
+            detect(Some("template.txt"), "<%= name %>\n<%# vim: set ft=eruby: %>\n"),
+            Some(("HTML+ERB".to_string(), 1.0))
+        );
+    }
+
+    #[test]
+    fn it_detects_languages_using_content() {
+        assert_eq!(detect(None, "<?xml version=\"1.0\"?>\n<a/>").unwrap().0, "XML");
+        assert_eq!(detect(None, "  {\"key\": \"value\"}\n").unwrap().0, "JSON");
+        assert_eq!(detect(None, "<p><%= name %></p>").unwrap().0, "HTML+ERB");
+        assert_eq!(detect(None, "fn main() {\n}\n").unwrap().0, "Rust");
+    }
+
+    #[test]
+    fn detect_uses_luthors_lexers() {
+        let detection = super::detect(Some(Path::new("app.rb")), "").unwrap();
+
+        assert_eq!(detection.lexer.name(), "Ruby");
+        assert_eq!(detection.confidence, 0.8);
+    }
+
+    #[test]
+    fn it_returns_nothing_without_evidence() {
+        assert_eq!(detect(None, "plain old words"), None);
+    }
+
+    #[test]
+    fn shebang_interpreter_ignores_empty_lines() {
+        assert_eq!(shebang_interpreter("#!\nputs 1"), None);
+    }
+
+    #[test]
+    fn modeline_checks_the_end_of_the_data() {
+        let data = format!("{}# vim: ft=ruby\n", "line\n".repeat(20));
+        assert_eq!(modeline(&data), Some("ruby"));
+    }
+
+    #[test]
+    fn modeline_ignores_the_middle_of_the_data() {
+        let data = format!("{}# vim: ft=ruby\n{}", "line\n".repeat(10), "line\n".repeat(10));
+        assert_eq!(modeline(&data), None);
+    }
+
+    #[test]
+    fn modeline_skips_emacs_variables_without_values() {
+        assert_eq!(modeline("# -*- foo; mode: ruby -*-\n"), Some("ruby"));
+    }
+
+    #[test]
+    fn modeline_requires_vim_markers_to_follow_whitespace() {
+        assert_eq!(modeline("nvim: ft=ruby"), None);
+    }
+}
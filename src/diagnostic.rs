@@ -0,0 +1,43 @@
+//! Diagnostics, describing malformed input encountered by lexers.
+
+use token::Span;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The primary means of classifying the problems reported by lexers.
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "snake_case"))]
+pub enum Kind {
+    UnterminatedString,
+    UnterminatedComment,
+    UnexpectedCharacter,
+    InvalidEscape,
+}
+
+/// A problem with the lexed data, such as a string missing its closing quote.
+/// The span covers the malformed data (e.g. the string, up to the end of the
+/// data), and the message describes the problem in a human-readable form.
+///
+/// # Examples
+///
+/// ```
+/// use luthor::diagnostic::Kind;
+///
+/// let (tokens, diagnostics) = luthor::lexers::json::lex_with_diagnostics("[\"open");
+/// assert_eq!(tokens[1].lexeme, "\"open");
+///
+/// assert_eq!(diagnostics[0].kind, Kind::UnterminatedString);
+/// assert_eq!(diagnostics[0].span.range(), 1..6);
+/// assert_eq!(diagnostics[0].message, "unterminated string");
+/// ```
+///
+/// With the `serde` feature enabled, diagnostics implement `Serialize` and
+/// `Deserialize`. Kinds are represented in snake case (e.g. `"unterminated_string"`),
+/// and spans as described in the `token` module.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Diagnostic {
+    pub kind: Kind,
+    pub span: Span,
+    pub message: String,
+}
@@ -0,0 +1,493 @@
+//! Incremental lexing, which keeps a document's tokens up to date as it's
+//! edited, without lexing the entire document again.
+//!
+//! While lexing, checkpoints are recorded between tokens, capturing the
+//! tokenizer's state function, state stack, and the lexer's custom state
+//! (see `UserState`). When the document is edited,
+//! lexing resumes from the nearest checkpoint unaffected by the edit, and
+//! stops as soon as it reaches a checkpoint beyond the edit in the same state
+//! as before; the tokens that follow are reused, with their spans adjusted.
+//!
+//! Only lexers that provide an initial state function (see `Lexer`) can be
+//! resumed; others are run in full after every edit.
+//!
+//! # Examples
+//!
+//! ```
+//! use luthor::incremental::Document;
+//! use luthor::lexers::json::JsonLexer;
+//!
+//! let mut document = Document::new(&JsonLexer, "[true, false]");
+//! let changed = document.edit(1..5, "null");
+//!
+//! assert_eq!(document.data(), "[null, false]");
+//! assert_eq!(document.tokens()[changed][0].lexeme, "null");
+//! ```
+
+use std::ops::Range;
+use lexers::Lexer;
+use token::{Position, Token};
+use tokenizer::{StateFunction, Tokenizer, UserState};
+
+// The minimum distance, in bytes, between checkpoints.
+const CHECKPOINT_INTERVAL: usize = 128;
+
+/// A snapshot of a lexer's state between two tokens, from which lexing can be resumed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    /// The position at which lexing resumes.
+    pub position: Position,
+
+    /// The index of the first token produced when lexing resumes.
+    pub token_index: usize,
+
+    /// The state function with which lexing resumes.
+    pub state: StateFunction,
+
+    /// The tokenizer's state stack at this point.
+    pub states: Vec<StateFunction>,
+
+    /// The lexer's custom state at this point, if it has any.
+    pub user_state: Option<Box<dyn UserState>>,
+
+    /// The furthest byte offset (exclusive) examined by the lexer before
+    /// reaching this point. Edits at or beyond it don't affect the tokens
+    /// preceding the checkpoint, or the state captured by it.
+    pub lookahead: usize,
+}
+
+/// A lexed document that can be edited, re-lexing as little as possible.
+pub struct Document<'a> {
+    lexer: &'a dyn Lexer,
+    data: String,
+    tokens: Vec<Token>,
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl<'a> Document<'a> {
+    /// Lexes the data, recording checkpoints for later edits.
+    pub fn new(lexer: &'a dyn Lexer, data: &str) -> Document<'a> {
+        let mut document = Document{
+            lexer,
+            data: data.to_string(),
+            tokens: vec![],
+            checkpoints: vec![],
+        };
+
+        match lexer.initial_state() {
+            Some(state) => {
+                let start = Checkpoint{
+                    position: Position::default(),
+                    token_index: 0,
+                    state,
+                    states: vec![],
+                    user_state: None,
+                    lookahead: 0,
+                };
+                let run = run(&document.data, &start, None);
+                document.tokens = run.tokens;
+                document.checkpoints = vec![start];
+                document.checkpoints.extend(run.checkpoints);
+            },
+            None => document.tokens = lexer.lex(data),
+        }
+
+        document
+    }
+
+    /// The document's current data.
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+
+    /// The document's current tokens.
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// The checkpoints from which lexing can be resumed, in document order.
+    /// Empty if the lexer cannot be resumed.
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+
+    /// Replaces the specified byte range of the data and re-lexes the
+    /// affected region, returning the range of tokens that changed. Tokens
+    /// outside of the returned range are unchanged, other than their spans
+    /// being moved to account for the edit. An empty range indicates that
+    /// tokens were only removed, at that index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range doesn't lie on character boundaries within the data.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use luthor::incremental::Document;
+    /// use luthor::lexers::ruby::RubyLexer;
+    ///
+    /// let mut document = Document::new(&RubyLexer, "x = 1\ny = 2\n");
+    /// let changed = document.edit(10..11, "3");
+    ///
+    /// assert_eq!(document.data(), "x = 1\ny = 3\n");
+    /// assert_eq!(document.tokens()[changed][0].lexeme, "3");
+    /// ```
+    pub fn edit(&mut self, range: Range<usize>, replacement: &str) -> Range<usize> {
+        self.data.replace_range(range.clone(), replacement);
+
+        if self.checkpoints.is_empty() {
+            return self.relex_all()
+        }
+
+        // Resume from the last checkpoint that didn't examine the edited data;
+        // the first checkpoint hasn't examined anything, so there's always one.
+        let restart_index = self.checkpoints.iter()
+            .rposition(|checkpoint| checkpoint.lookahead <= range.start)
+            .unwrap_or(0);
+        let restart = self.checkpoints[restart_index].clone();
+        let resync = Resync{
+            checkpoints: &self.checkpoints[restart_index + 1..],
+            start: range.start + replacement.len(),
+            removed: range.len(),
+            inserted: replacement.len(),
+        };
+        let run = run(&self.data, &restart, Some(resync));
+
+        // Work out where the re-lexed tokens diverge from the originals.
+        let old_end = match run.resync {
+            Some(ref resync) => resync.token_index,
+            None => self.tokens.len(),
+        };
+        let changed = changed_range(&run.tokens, &self.tokens[restart.token_index..old_end]);
+        let changed = restart.token_index + changed.start..restart.token_index + changed.end;
+
+        // Move the tokens and checkpoints following the resynchronization
+        // point, and combine them with the re-lexed tokens and checkpoints.
+        let mut tail = vec![];
+        if let Some(resync) = run.resync {
+            let (from, to) = (resync.original_position, resync.position);
+            for token in self.tokens[old_end..].iter_mut() {
+                token.span.start = token.span.start.rebase(from, to);
+                token.span.end = token.span.end.rebase(from, to);
+            }
+
+            let old_index = restart_index + 1 + resync.index;
+            let new_end = restart.token_index + run.tokens.len();
+            tail = self.checkpoints.drain(old_index..).map(|mut checkpoint| {
+                checkpoint.lookahead = (checkpoint.lookahead + resync.position.offset - from.offset)
+                    .max(resync.lookahead);
+                checkpoint.position = checkpoint.position.rebase(from, to);
+                checkpoint.token_index = checkpoint.token_index - old_end + new_end;
+                checkpoint
+            }).collect();
+        }
+        self.tokens.splice(restart.token_index..old_end, run.tokens);
+        self.checkpoints.truncate(restart_index + 1);
+        self.checkpoints.extend(run.checkpoints);
+        self.checkpoints.extend(tail);
+
+        changed
+    }
+
+    // Lexes the entire document again, returning the range of tokens
+    // that differ (ignoring spans) from those previously produced.
+    fn relex_all(&mut self) -> Range<usize> {
+        let tokens = self.lexer.lex(&self.data);
+        let changed = changed_range(&tokens, &self.tokens);
+        self.tokens = tokens;
+
+        changed
+    }
+}
+
+// Compares re-lexed tokens with those they replace, returning the range of
+// re-lexed tokens that differ. Leading tokens must be identical, whereas
+// trailing tokens may have been moved by the edit, and so spans are ignored.
+fn changed_range(new: &[Token], old: &[Token]) -> Range<usize> {
+    let prefix = new.iter()
+        .zip(old.iter())
+        .take_while(|&(new, old)| new == old)
+        .count();
+    let suffix = new[prefix..].iter().rev()
+        .zip(old[prefix..].iter().rev())
+        .take_while(|&(new, old)| new.lexeme == old.lexeme && new.category == old.category)
+        .count();
+
+    prefix..new.len() - suffix
+}
+
+// The information needed to find a point at which the tokens produced by
+// re-lexing an edited document match the tokens produced prior to the edit.
+struct Resync<'a> {
+    // Checkpoints following the one from which lexing resumed.
+    checkpoints: &'a [Checkpoint],
+
+    // The offset following the replacement data, before which we can't resync.
+    start: usize,
+
+    // The length of the replaced and replacement data, respectively.
+    removed: usize,
+    inserted: usize,
+}
+
+impl<'a> Resync<'a> {
+    // Finds an original checkpoint matching the state of the tokenizer
+    // (in the edited document), returning its index.
+    fn find(&self, tokenizer: &Tokenizer, state: StateFunction) -> Option<usize> {
+        let offset = tokenizer.position().offset;
+        if offset < self.start {
+            return None
+        }
+
+        let original_offset = offset + self.removed - self.inserted;
+        let index = self.checkpoints
+            .binary_search_by_key(&original_offset, |checkpoint| checkpoint.position.offset)
+            .ok()?;
+        let checkpoint = &self.checkpoints[index];
+
+        if checkpoint.state == state && checkpoint.states == tokenizer.states &&
+           tokenizer.has_user_state(&checkpoint.user_state) {
+            Some(index)
+        } else {
+            None
+        }
+    }
+}
+
+// The point at which re-lexing matched an original checkpoint.
+struct Resynced {
+    // The index of the matching checkpoint, relative to those searched.
+    index: usize,
+
+    // The matching checkpoint's original position and token index.
+    original_position: Position,
+    token_index: usize,
+
+    // The position and lookahead of the re-lexed data at this point.
+    position: Position,
+    lookahead: usize,
+}
+
+// The tokens and checkpoints produced by resuming a lexer's state machine.
+struct Run {
+    tokens: Vec<Token>,
+    checkpoints: Vec<Checkpoint>,
+    resync: Option<Resynced>,
+}
+
+// Runs a lexer's state functions from the specified checkpoint until the end
+// of the data or, if specified, until the lexer's state matches that of an
+// original checkpoint after an edit.
+fn run(data: &str, start: &Checkpoint, resync: Option<Resync>) -> Run {
+    let mut tokenizer = Tokenizer::resume(
+        data,
+        start.position,
+        start.states.clone(),
+        start.user_state.clone(),
+    );
+    let mut state = start.state;
+    let mut checkpoints = vec![];
+    let mut last_offset = start.position.offset;
+
+    loop {
+        if !tokenizer.has_pending_data() {
+            let position = tokenizer.position();
+
+            if let Some(ref resync) = resync {
+                if let Some(index) = resync.find(&tokenizer, state) {
+                    let lookahead = tokenizer.lookahead().max(start.lookahead);
+                    return Run{
+                        tokens: tokenizer.into_tokens().into_iter().map(Token::from).collect(),
+                        checkpoints,
+                        resync: Some(Resynced{
+                            index,
+                            original_position: resync.checkpoints[index].position,
+                            token_index: resync.checkpoints[index].token_index,
+                            position,
+                            lookahead,
+                        }),
+                    }
+                }
+            }
+
+            if position.offset >= last_offset + CHECKPOINT_INTERVAL {
+                checkpoints.push(Checkpoint{
+                    position,
+                    token_index: start.token_index + tokenizer.token_count(),
+                    state,
+                    states: tokenizer.states.clone(),
+                    user_state: tokenizer.saved_user_state(),
+                    lookahead: tokenizer.lookahead().max(start.lookahead),
+                });
+                last_offset = position.offset;
+            }
+        }
+
+        let StateFunction(actual_function) = state;
+        match actual_function(&mut tokenizer).or_else(|| tokenizer.states.pop()) {
+            Some(f) => state = f,
+            None => {
+                return Run{ tokens: tokenizer.tokens(), checkpoints, resync: None }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lexers::{self, Lexer};
+    use token::{Category, Token};
+    use tokenizer::{StateFunction, Tokenizer};
+    use super::Document;
+
+    // A lexer for strings delimited by any number of hashes (e.g. ##"a"##),
+    // which relies on its custom state to remember the number of hashes.
+    struct HashStringLexer;
+
+    impl Lexer for HashStringLexer {
+        fn name(&self) -> &str { "Hash strings" }
+        fn lex(&self, data: &str) -> Vec<Token> {
+            Tokenizer::new(data).run(StateFunction(initial_state)).map(Token::from).collect()
+        }
+        fn initial_state(&self) -> Option<StateFunction> { Some(StateFunction(initial_state)) }
+    }
+
+    fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+        if let Some(length) = tokenizer.match_regex(regex!("#*\"")) {
+            tokenizer.set_user_state(length - 1);
+            tokenizer.advance_bytes(length);
+            tokenizer.tokenize(Category::String);
+            return Some(StateFunction(hash_string))
+        }
+
+        tokenizer.current_char()?;
+        tokenizer.tokenize_next(1, Category::Text);
+        Some(StateFunction(initial_state))
+    }
+
+    fn hash_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+        let terminator = format!("\"{}", "#".repeat(*tokenizer.user_state::<usize>()));
+        if let Some(length) = tokenizer.match_literal(&[&terminator]) {
+            tokenizer.advance_bytes(length);
+            tokenizer.tokenize(Category::String);
+            return Some(StateFunction(initial_state))
+        }
+
+        tokenizer.current_char()?;
+        tokenizer.tokenize_next(1, Category::String);
+        Some(StateFunction(hash_string))
+    }
+
+    // Applies edits at every character boundary in the data, ensuring that
+    // the document's tokens match those produced by lexing it from scratch.
+    fn assert_edits_match_full_lexing(lexer: &dyn Lexer, data: &str, replacement: &str) {
+        let boundaries: Vec<usize> = data.char_indices().map(|(index, _)| index).collect();
+
+        for (i, &start) in boundaries.iter().enumerate() {
+            let end = boundaries.get(i + 1).cloned().unwrap_or(data.len());
+
+            // Replace the character at the boundary.
+            let mut document = Document::new(lexer, data);
+            let changed = document.edit(start..end, replacement);
+            let expected = lexer.lex(document.data());
+            assert_eq!(document.tokens(), &expected[..]);
+            assert!(changed.end <= expected.len());
+
+            // Insert before the character, and then undo the insertion.
+            let mut document = Document::new(lexer, data);
+            document.edit(start..start, replacement);
+            assert_eq!(document.tokens(), &lexer.lex(document.data())[..]);
+            document.edit(start..start + replacement.len(), "");
+            assert_eq!(document.data(), data);
+            assert_eq!(document.tokens(), &lexer.lex(data)[..]);
+        }
+    }
+
+    #[test]
+    fn edits_match_full_lexing_for_every_resumable_lexer() {
+        let samples = [
+            ("Ruby", include_str!("../test_data/ruby.rb")),
+            ("Rust", include_str!("../test_data/rust.rs")),
+            ("JSON", include_str!("../test_data/data.json")),
+            ("XML", include_str!("../test_data/data.xml")),
+            ("JavaScript", include_str!("../test_data/data.js")),
+            ("CoffeeScript", include_str!("../test_data/data.coffee")),
+            ("Text", include_str!("../test_data/data.txt")),
+        ];
+        let registry = lexers::registry();
+
+        for &(name, data) in samples.iter() {
+            let lexer = registry.find_by_name(name).unwrap();
+            for replacement in ["\"", "é", " = ", "\n"] {
+                assert_edits_match_full_lexing(lexer, data, replacement);
+            }
+        }
+    }
+
+    #[test]
+    fn edits_match_full_lexing_for_lexers_with_custom_state() {
+        let data = "##\"".to_string() + &"x\"#".repeat(60) + "\"## y #\"z\"#";
+
+        assert_edits_match_full_lexing(&HashStringLexer, &data, "#");
+    }
+
+    #[test]
+    fn edits_to_custom_state_prevent_resynchronization() {
+        let data = "##\"".to_string() + &"x\"#".repeat(100) + "\"##";
+        let mut document = Document::new(&HashStringLexer, &data);
+        assert!(document.checkpoints().iter().any(|checkpoint| checkpoint.user_state.is_some()));
+
+        // With a single hash, the string is terminated early.
+        let changed = document.edit(0..1, "");
+        assert_eq!(document.tokens(), &HashStringLexer.lex(document.data())[..]);
+        assert_eq!(document.tokens()[2].lexeme, "\"#");
+        assert_eq!(changed.end, document.tokens().len());
+    }
+
+    #[test]
+    fn edits_to_lexers_that_cannot_be_resumed_match_full_lexing() {
+        let lexer = lexers::html_erb::HtmlErbLexer;
+        let data = include_str!("../test_data/html_erb.html.erb");
+
+        assert!(lexer.initial_state().is_none());
+        assert_edits_match_full_lexing(&lexer, data, "%>");
+    }
+
+    #[test]
+    fn edit_only_relexes_the_affected_region() {
+        let data = "[\n".to_string() + &"  \"value\",\n".repeat(1000) + "  \"value\"\n]\n";
+        let mut document = Document::new(&lexers::json::JsonLexer, &data);
+        let checkpoints = document.checkpoints().len();
+
+        // Replace the string on the line in the middle of the document.
+        let start = data.match_indices("\"value\"").nth(500).unwrap().0;
+        let changed = document.edit(start..start + 7, "true");
+        let tokens = document.tokens();
+
+        assert_eq!(changed.len(), 1);
+        assert_eq!(tokens[changed.start].lexeme, "true");
+        assert_eq!(tokens[changed.start].span.start.line, 501);
+        assert_eq!(tokens, &lexers::json::lex(document.data())[..]);
+        assert!(document.checkpoints().len() >= checkpoints - 1);
+    }
+
+    #[test]
+    fn edit_relexes_the_remainder_of_the_document_when_required() {
+        let data = "[\n".to_string() + &"  \"value\",\n".repeat(100) + "]";
+        let mut document = Document::new(&lexers::json::JsonLexer, &data);
+        let changed = document.edit(2..2, "\"");
+
+        assert_eq!(changed.end, document.tokens().len());
+        assert_eq!(document.tokens(), &lexers::json::lex(document.data())[..]);
+    }
+
+    #[test]
+    fn edit_reports_removed_tokens_with_an_empty_range() {
+        let mut document = Document::new(&lexers::json::JsonLexer, "[true, false]");
+        let changed = document.edit(5..12, "");
+
+        assert_eq!(document.data(), "[true]");
+        assert_eq!(changed, 2..2);
+    }
+}
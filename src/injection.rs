@@ -0,0 +1,821 @@
+//! Language injection, for lexing documents containing regions written in
+//! other languages, such as Ruby embedded in an HTML template.
+//!
+//! A `Host` breaks the data into segments: tokens of its own (e.g. the
+//! delimiters surrounding a region), and regions written in other languages,
+//! which are lexed using the lexers registered for them. All of a language's
+//! regions are lexed together, as though they were a single document (one
+//! line per region), so that lexing state persists from one region to the
+//! next: a string or block opened in one region can be closed in another.
+//! Tokens spanning several regions are split at their boundaries. Regions
+//! are lexed lazily, as their tokens are requested.
+//!
+//! Two hosts are provided: `Delimited`, for regions enclosed by delimiters,
+//! and `States`, for `Tokenizer` state functions that hand data in certain
+//! states off to other languages.
+//!
+//! # Examples
+//!
+//! ```
+//! use luthor::injection::{self, Delimited};
+//! use luthor::lexers;
+//! use luthor::token::Category;
+//!
+//! let mut host = Delimited::new("xml");
+//! host.add(&["<%"], "%>", "ruby");
+//!
+//! let data = "<% if admin %><b>Hi</b><% end %>";
+//! let tokens = injection::lex(&lexers::registry(), &host, data);
+//! let keywords: Vec<_> = tokens.iter()
+//!     .filter(|token| token.category == Category::Keyword)
+//!     .map(|token| token.lexeme.as_str())
+//!     .collect();
+//!
+//! assert_eq!(keywords, ["<%", "if", "%>", "<%", "end", "%>"]);
+//! ```
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::{iter, mem, vec};
+use diagnostic::Diagnostic;
+use lexers::Registry;
+use token::{BorrowedToken, Category, Position, Span, Token};
+use tokenizer::{StateFunction, Tokenizer, UserState};
+
+/// A piece of a document, as identified by a `Host`.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Segment<'a> {
+    /// A token produced by the host itself, such as a delimiter.
+    Token(BorrowedToken<'a>),
+
+    /// A region written in the named language, which is
+    /// looked up using `Registry::find_by_name`.
+    Region(&'static str, Span),
+}
+
+/// Breaks a document into its own tokens and regions written in other languages.
+pub trait Host {
+    /// Segments the data. The segments must be in order, and cover all of it.
+    fn segment<'a>(&self, data: &'a str) -> Vec<Segment<'a>>;
+}
+
+/// A host in which regions are enclosed by delimiters (e.g. `<%` and `%>`),
+/// and the data outside of them is written in the host's language. The
+/// delimiters themselves are tokenized as keywords.
+pub struct Delimited {
+    language: &'static str,
+    delimiters: Vec<(&'static [&'static str], &'static str, &'static str)>,
+}
+
+impl Delimited {
+    /// Creates a host for data written in the named language.
+    pub fn new(language: &'static str) -> Delimited {
+        Delimited{ language, delimiters: Vec::new() }
+    }
+
+    /// Adds regions written in the named language, opened using any of the
+    /// specified delimiters (the longest of which is preferred), and closed
+    /// by another. Regions missing their closing delimiter end with the data.
+    pub fn add(&mut self, open: &'static [&'static str], close: &'static str, language: &'static str) {
+        self.delimiters.push((open, close, language));
+    }
+}
+
+impl Host for Delimited {
+    fn segment<'a>(&self, data: &'a str) -> Vec<Segment<'a>> {
+        let mut segments = Vec::new();
+        let mut position = Position::default();
+
+        // The next occurrence of each opening delimiter, alongside the
+        // index of its region type. These are only searched for again
+        // once we've moved past them, so that the data is scanned once.
+        let mut openings: Vec<_> = self.delimiters.iter().enumerate()
+            .flat_map(|(index, &(open, _, _))| open.iter().map(move |&open| (index, open)))
+            .map(|(index, open)| (index, open, data.find(open)))
+            .collect();
+
+        loop {
+            let mut nearest: Option<(usize, usize, &str)> = None;
+            for &mut (index, open, ref mut next) in openings.iter_mut() {
+                if next.is_some_and(|offset| offset < position.offset) {
+                    *next = data[position.offset..].find(open).map(|offset| offset + position.offset);
+                }
+
+                if let Some(offset) = *next {
+                    let nearer = nearest.is_none_or(|(nearest_offset, _, nearest_open)| {
+                        offset < nearest_offset ||
+                        (offset == nearest_offset && open.len() > nearest_open.len())
+                    });
+                    if nearer {
+                        nearest = Some((offset, index, open));
+                    }
+                }
+            }
+
+            let (offset, index, open) = match nearest {
+                Some(opening) => opening,
+                None => {
+                    push_region(&mut segments, data, &mut position, data.len(), self.language);
+                    return segments
+                }
+            };
+            let (_, close, language) = self.delimiters[index];
+
+            push_region(&mut segments, data, &mut position, offset, self.language);
+            push_token(&mut segments, data, &mut position, offset + open.len());
+
+            match data[position.offset..].find(close) {
+                Some(length) => {
+                    let offset = position.offset + length;
+                    push_region(&mut segments, data, &mut position, offset, language);
+                    push_token(&mut segments, data, &mut position, offset + close.len());
+                },
+                None => {
+                    push_region(&mut segments, data, &mut position, data.len(), language);
+                    return segments
+                }
+            }
+        }
+    }
+}
+
+/// A host built from `Tokenizer` state functions, in which the data tokenized
+/// in certain states is written in other languages. Tokens produced in other
+/// states are passed through unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use luthor::{StateFunction, Tokenizer};
+/// use luthor::injection::{self, States};
+/// use luthor::lexers;
+/// use luthor::token::Category;
+///
+/// // Lexes data within backticks as Ruby.
+/// fn text(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+///     if tokenizer.has_prefix("`") {
+///         tokenizer.tokenize(Category::Text);
+///         tokenizer.tokenize_next(1, Category::Punctuation);
+///         return Some(StateFunction(code))
+///     }
+///
+///     tokenizer.current_char()?;
+///     tokenizer.advance();
+///     Some(StateFunction(text))
+/// }
+///
+/// fn code(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+///     if tokenizer.has_prefix("`") {
+///         tokenizer.tokenize(Category::Text);
+///         return Some(StateFunction(closing_backtick))
+///     }
+///
+///     tokenizer.current_char()?;
+///     tokenizer.advance();
+///     Some(StateFunction(code))
+/// }
+///
+/// fn closing_backtick(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+///     tokenizer.tokenize_next(1, Category::Punctuation);
+///     Some(StateFunction(text))
+/// }
+///
+/// let mut host = States::new(StateFunction(text));
+/// host.add(StateFunction(code), "ruby");
+///
+/// let tokens = injection::lex(&lexers::registry(), &host, "Use `nil`.");
+/// assert_eq!(tokens[2].lexeme, "nil");
+/// assert_eq!(tokens[2].category, Category::Constant);
+/// assert_eq!(tokens[4].lexeme, ".");
+/// ```
+pub struct States {
+    initial_state: StateFunction,
+    languages: Vec<(StateFunction, &'static str)>,
+}
+
+impl States {
+    /// Creates a host whose lexing begins with the specified state function.
+    pub fn new(initial_state: StateFunction) -> States {
+        States{ initial_state, languages: Vec::new() }
+    }
+
+    /// Treats data tokenized by the specified state function as being written
+    /// in the named language. To keep delimiters out of the region, they
+    /// should be tokenized by another state.
+    pub fn add(&mut self, state: StateFunction, language: &'static str) {
+        self.languages.push((state, language));
+    }
+}
+
+impl Host for States {
+    fn segment<'a>(&self, data: &'a str) -> Vec<Segment<'a>> {
+        let mut tokens = Tokenizer::new(data).run(self.initial_state);
+        let mut segments = Vec::new();
+
+        while let Some(state) = tokens.step() {
+            let language = self.languages.iter()
+                .find(|&&(region_state, _)| region_state == state)
+                .map(|&(_, language)| language);
+
+            segments.extend(tokens.drain_buffer().map(|token| match language {
+                Some(language) => Segment::Region(language, token.span),
+                None => Segment::Token(token),
+            }));
+        }
+
+        segments
+    }
+}
+
+/// Lexes the data lazily using the host, deferring to the registry's lexers
+/// for its regions; those written in languages missing from the registry are
+/// tokenized as text. The data is segmented up front, and each language's
+/// regions are gathered into a single source, but the sources are only lexed
+/// as their tokens are requested. Lexers that don't provide an initial state
+/// function (see `Lexer`) can't be resumed, and are run in full up front.
+pub fn lex_borrowed<'a>(registry: &Registry, host: &dyn Host, data: &'a str) -> Injection<'a> {
+    let segments = host.segment(data);
+
+    // Gather each language's regions into a single source.
+    let mut sources: Vec<Source> = Vec::new();
+    for (index, segment) in segments.iter().enumerate() {
+        if let Segment::Region(language, span) = *segment {
+            let source = match sources.iter().position(|source| source.language == language) {
+                Some(source) => &mut sources[source],
+                None => {
+                    sources.push(Source::new(language));
+                    sources.last_mut().unwrap()
+                }
+            };
+
+            source.add(index, &data[span.range()], span);
+        }
+    }
+
+    let mut diagnostics = Vec::new();
+    for source in sources.iter_mut() {
+        source.start(registry, &mut diagnostics);
+    }
+
+    Injection{
+        data,
+        segments: segments.into_iter().enumerate(),
+        sources,
+        region: None,
+        diagnostics,
+    }
+}
+
+/// Lexes the data using the host, deferring to the registry's lexers
+/// for its regions. See `lex_borrowed` for details.
+pub fn lex(registry: &Registry, host: &dyn Host, data: &str) -> Vec<Token> {
+    lex_borrowed(registry, host, data).map(Token::from).collect()
+}
+
+/// Lexes the data using the host, reporting any malformed input found in its
+/// regions (see `Lexer::lex_with_diagnostics`), with spans relative to the data.
+/// Diagnostics spanning several regions are limited to the regions' data.
+pub fn lex_with_diagnostics(registry: &Registry, host: &dyn Host, data: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+    let mut injection = lex_borrowed(registry, host, data);
+    let tokens = injection.by_ref().map(Token::from).collect();
+    let mut diagnostics = injection.into_diagnostics();
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start.offset);
+
+    (tokens, diagnostics)
+}
+
+/// A lazy token iterator, produced by `lex_borrowed`.
+pub struct Injection<'a> {
+    data: &'a str,
+    segments: iter::Enumerate<vec::IntoIter<Segment<'a>>>,
+    sources: Vec<Source>,
+    region: Option<(usize, usize)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Iterator for Injection<'a> {
+    type Item = BorrowedToken<'a>;
+
+    fn next(&mut self) -> Option<BorrowedToken<'a>> {
+        loop {
+            // Continue with the region being lexed, identified by
+            // the indices of its source and its fragment therein.
+            if let Some((source, fragment)) = self.region {
+                let source = &mut self.sources[source];
+                match source.next_token(fragment, &mut self.diagnostics) {
+                    Some((category, span, scope)) => return Some(BorrowedToken{
+                        lexeme: &self.data[span.range()],
+                        category,
+                        span,
+                        scope,
+                    }),
+                    None => {
+                        source.fragment += 1;
+                        self.region = None;
+                    }
+                }
+            }
+
+            match self.segments.next()? {
+                (_, Segment::Token(token)) => return Some(token),
+                (index, Segment::Region(language, _)) => {
+                    let source = self.sources.iter().position(|source| source.language == language).unwrap();
+                    let fragment = self.sources[source].fragment;
+
+                    // Regions joined to the previous one don't have fragments of their own.
+                    if self.sources[source].fragments.get(fragment).is_some_and(|fragment| fragment.segment == index) {
+                        self.region = Some((source, fragment));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Injection<'a> {
+    /// The problems reported by the lexers for the tokens produced to date.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Consumes the iterator, returning the problems reported by
+    /// the lexers. Typically used once all tokens have been produced.
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+}
+
+// A token's category, span and scope, without its lexeme.
+type SourceToken = (Category, Span, Option<Cow<'static, str>>);
+
+// A language's regions, joined by newlines to form a single document.
+struct Source {
+    language: &'static str,
+    data: String,
+    end: Position,
+    fragments: Vec<Fragment>,
+
+    // The next fragment to be produced, and the offset
+    // within the source up to which it has been produced.
+    fragment: usize,
+    offset: usize,
+
+    // Tokens that have been lexed but not yet produced, and where
+    // lexing resumes to produce more (if the lexer can be resumed).
+    tokens: VecDeque<SourceToken>,
+    checkpoint: Option<Checkpoint>,
+}
+
+// A region's location in its source and in the document,
+// along with the index of the segment it belongs to.
+struct Fragment {
+    segment: usize,
+    source: Span,
+    span: Span,
+}
+
+// The state of a source's lexer between two tokens.
+struct Checkpoint {
+    position: Position,
+    state: Option<StateFunction>,
+    states: Vec<StateFunction>,
+    user_state: Option<Box<dyn UserState>>,
+}
+
+impl Source {
+    fn new(language: &'static str) -> Source {
+        Source{
+            language,
+            data: String::new(),
+            end: Position::default(),
+            fragments: Vec::new(),
+            fragment: 0,
+            offset: 0,
+            tokens: VecDeque::new(),
+            checkpoint: None,
+        }
+    }
+
+    fn add(&mut self, segment: usize, data: &str, span: Span) {
+        // Regions immediately following the previous one (e.g. those
+        // produced by a state function in several tokens) are joined to it.
+        let adjacent = self.fragments.last()
+            .is_some_and(|fragment| fragment.span.end.offset == span.start.offset);
+
+        if !adjacent && !self.fragments.is_empty() {
+            self.data.push('\n');
+            self.end.advance('\n');
+        }
+
+        let start = self.end;
+        self.data.push_str(data);
+        for c in data.chars() {
+            self.end.advance(c);
+        }
+
+        match self.fragments.last_mut() {
+            Some(fragment) if adjacent => {
+                fragment.source.end = self.end;
+                fragment.span.end = span.end;
+            },
+            _ => self.fragments.push(Fragment{ segment, source: Span{ start, end: self.end }, span }),
+        }
+    }
+
+    // Prepares the source for lexing, lexing it in full if
+    // its lexer can't be resumed part way through the data.
+    fn start(&mut self, registry: &Registry, diagnostics: &mut Vec<Diagnostic>) {
+        let lexer = match registry.find_by_name(self.language) {
+            Some(lexer) => lexer,
+            None => {
+                let fragments = self.fragments.iter().map(|fragment| (Category::Text, fragment.source, None));
+                self.tokens.extend(fragments);
+
+                return
+            }
+        };
+
+        match lexer.initial_state() {
+            Some(state) => self.checkpoint = Some(Checkpoint{
+                position: Position::default(),
+                state: Some(state),
+                states: Vec::new(),
+                user_state: None,
+            }),
+            None => {
+                let (tokens, source_diagnostics) = lexer.lex_with_diagnostics(&self.data);
+                self.tokens.extend(tokens.into_iter().map(|token| (token.category, token.span, token.scope)));
+                self.report(source_diagnostics, diagnostics);
+            }
+        }
+    }
+
+    // Produces the next token within the fragment, with a span relative to the
+    // document. Tokens spanning several fragments are split at their boundaries,
+    // leaving out the newlines used to separate them.
+    fn next_token(&mut self, fragment: usize, diagnostics: &mut Vec<Diagnostic>) -> Option<SourceToken> {
+        let Fragment{ source: bounds, span: region, .. } = self.fragments[fragment];
+
+        loop {
+            if self.offset >= bounds.end.offset {
+                return None
+            }
+            if self.tokens.is_empty() && !self.lex(diagnostics) {
+                return None
+            }
+
+            let (ref category, span, ref scope) = self.tokens[0];
+            if span.end.offset <= bounds.start.offset {
+                self.tokens.pop_front();
+                continue
+            } else if span.start.offset >= bounds.end.offset {
+                return None
+            }
+
+            let start = if span.start.offset > bounds.start.offset { span.start } else { bounds.start };
+            let end = if span.end.offset < bounds.end.offset { span.end } else { bounds.end };
+            let token = (category.clone(), Span{
+                start: start.rebase(bounds.start, region.start),
+                end: end.rebase(bounds.start, region.start),
+            }, scope.clone());
+
+            self.offset = end.offset;
+            if span.end.offset <= bounds.end.offset {
+                self.tokens.pop_front();
+            }
+
+            return Some(token)
+        }
+    }
+
+    // Resumes lexing until at least one more token has been produced and
+    // the lexer is between tokens, returning whether there were any more.
+    fn lex(&mut self, diagnostics: &mut Vec<Diagnostic>) -> bool {
+        let checkpoint = match self.checkpoint {
+            Some(ref mut checkpoint) => checkpoint,
+            None => return false,
+        };
+        let mut tokenizer = Tokenizer::resume(
+            &self.data,
+            checkpoint.position,
+            mem::take(&mut checkpoint.states),
+            checkpoint.user_state.take(),
+        );
+
+        while let Some(StateFunction(actual_function)) = checkpoint.state {
+            checkpoint.state = actual_function(&mut tokenizer).or_else(|| tokenizer.states.pop());
+
+            let tokens = tokenizer.drain_tokens().map(|token| (token.category, token.span, token.scope));
+            self.tokens.extend(tokens);
+            if checkpoint.state.is_none() {
+                let remainder = tokenizer.remainder().map(|token| (token.category, token.span, token.scope));
+                self.tokens.extend(remainder);
+            } else if !self.tokens.is_empty() && !tokenizer.has_pending_data() {
+                break
+            }
+        }
+
+        checkpoint.position = tokenizer.position();
+        checkpoint.states = mem::take(&mut tokenizer.states);
+        checkpoint.user_state = tokenizer.saved_user_state();
+        let source_diagnostics = tokenizer.diagnostics().to_vec();
+        self.report(source_diagnostics, diagnostics);
+
+        !self.tokens.is_empty()
+    }
+
+    // Adds the source's diagnostics to those of the document.
+    fn report(&self, source_diagnostics: Vec<Diagnostic>, diagnostics: &mut Vec<Diagnostic>) {
+        diagnostics.extend(source_diagnostics.into_iter().map(|mut diagnostic| {
+            diagnostic.span = Span{
+                start: self.translate(diagnostic.span.start),
+                end: self.translate(diagnostic.span.end),
+            };
+            diagnostic
+        }));
+    }
+
+    // Converts a position in the source into one in the document. Positions
+    // between fragments are moved to the end of the preceding fragment.
+    fn translate(&self, position: Position) -> Position {
+        let fragment = self.fragments.iter()
+            .take_while(|fragment| fragment.source.start.offset <= position.offset)
+            .last()
+            .unwrap_or(&self.fragments[0]);
+        let position = if position.offset > fragment.source.end.offset {
+            fragment.source.end
+        } else {
+            position
+        };
+
+        position.rebase(fragment.source.start, fragment.span.start)
+    }
+}
+
+// Tokenizes the data up to the offset as a keyword.
+fn push_token<'a>(segments: &mut Vec<Segment<'a>>, data: &'a str, position: &mut Position, offset: usize) {
+    let span = advance_to(data, position, offset);
+
+    segments.push(Segment::Token(BorrowedToken{
+        lexeme: &data[span.range()],
+        category: Category::Keyword,
+        span,
+        scope: None,
+    }));
+}
+
+// Adds a region ending at the offset, unless it would be empty.
+fn push_region(segments: &mut Vec<Segment>, data: &str, position: &mut Position, offset: usize,
+               language: &'static str) {
+    let span = advance_to(data, position, offset);
+
+    if span.start.offset < span.end.offset {
+        segments.push(Segment::Region(language, span));
+    }
+}
+
+// Moves the position to the offset, returning the span covered in doing so.
+fn advance_to(data: &str, position: &mut Position, offset: usize) -> Span {
+    let start = *position;
+    for c in data[start.offset..offset].chars() {
+        position.advance(c);
+    }
+
+    Span{ start, end: *position }
+}
+
+#[cfg(test)]
+mod tests {
+    use lexers;
+    use token::{Category, Token};
+    use tokenizer::{StateFunction, Tokenizer};
+    use diagnostic::Kind;
+    use super::{lex, lex_with_diagnostics, Delimited, Host, Segment, States};
+
+    fn erb() -> Delimited {
+        let mut host = Delimited::new("xml");
+        host.add(&["<%=", "<%"], "%>", "ruby");
+
+        host
+    }
+
+    fn assert_spans_are_document_relative(data: &str, tokens: &[Token]) {
+        let lexemes: Vec<&str> = tokens.iter().map(|token| token.lexeme.as_str()).collect();
+        assert_eq!(lexemes.concat(), data);
+
+        for token in tokens.iter() {
+            assert_eq!(&data[token.span.range()], token.lexeme);
+        }
+    }
+
+    #[test]
+    fn delimited_prefers_the_longest_opening_delimiter() {
+        let segments = erb().segment("a<%= b %>");
+
+        assert_eq!(segments.len(), 4);
+        match segments[1] {
+            Segment::Token(ref token) => assert_eq!(token.lexeme, "<%="),
+            ref segment => panic!("unexpected segment: {:?}", segment),
+        }
+        match segments[2] {
+            Segment::Region(language, span) => {
+                assert_eq!(language, "ruby");
+                assert_eq!(span.range(), 4..7);
+            },
+            ref segment => panic!("unexpected segment: {:?}", segment),
+        }
+    }
+
+    #[test]
+    fn delimited_regions_without_a_closing_delimiter_end_with_the_data() {
+        let segments = erb().segment("<% a");
+
+        assert_eq!(segments.len(), 2);
+        match segments[1] {
+            Segment::Region(language, span) => {
+                assert_eq!(language, "ruby");
+                assert_eq!(span.range(), 2..4);
+            },
+            ref segment => panic!("unexpected segment: {:?}", segment),
+        }
+    }
+
+    #[test]
+    fn state_persists_across_regions() {
+        let data = "<% x = \"a %><br><% b\" %>\n<a href=\"<%= y %>\">";
+        let tokens = lex(&lexers::registry(), &erb(), data);
+        let expected_tokens = [
+            ("<%", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("x", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("\"a ", Category::String),
+            ("%>", Category::Keyword),
+            ("<", Category::Punctuation),
+            ("br", Category::Identifier),
+            (">", Category::Punctuation),
+            ("<%", Category::Keyword),
+            (" b\"", Category::String),
+            (" ", Category::Whitespace),
+            ("%>", Category::Keyword),
+            ("\n", Category::Whitespace),
+            ("<", Category::Punctuation),
+            ("a", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("href", Category::Attribute),
+            ("=", Category::Punctuation),
+            ("\"", Category::String),
+            ("<%=", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("y", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("%>", Category::Keyword),
+            ("\"", Category::String),
+            (">", Category::Punctuation),
+        ];
+
+        assert_spans_are_document_relative(data, &tokens);
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
+        }
+    }
+
+    #[test]
+    fn regions_produce_document_relative_spans() {
+        let data = "<p>\n  <%= foo\n  bar %>\n</p><% baz %>";
+        let tokens = lex(&lexers::registry(), &erb(), data);
+        assert_spans_are_document_relative(data, &tokens);
+
+        let bar = tokens.iter().find(|token| token.lexeme == "bar").unwrap();
+        assert_eq!(bar.span.start.line, 2);
+        assert_eq!(bar.span.start.column, 2);
+
+        let baz = tokens.iter().find(|token| token.lexeme == "baz").unwrap();
+        assert_eq!(baz.span.start.line, 3);
+        assert_eq!(baz.span.start.column, 7);
+    }
+
+    #[test]
+    fn diagnostics_produce_document_relative_spans() {
+        let data = "<p class=\"a\n<%= b %>\n<% \"c %>";
+        let (tokens, diagnostics) = lex_with_diagnostics(&lexers::registry(), &erb(), data);
+        assert_spans_are_document_relative(data, &tokens);
+
+        // The HTML string is unterminated, but limited to the HTML.
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].kind, Kind::UnterminatedString);
+        assert_eq!(diagnostics[0].span.range(), 9..21);
+        assert_eq!(diagnostics[1].kind, Kind::UnterminatedString);
+        assert_eq!(diagnostics[1].span.range(), 24..27);
+        assert_eq!(diagnostics[1].span.start.line, 2);
+        assert_eq!(diagnostics[1].span.start.column, 3);
+    }
+
+    #[test]
+    fn lexing_with_diagnostics_produces_the_same_tokens() {
+        let samples = [
+            include_str!("../test_data/html_erb.html.erb"),
+            "<% # note %>",
+            "<% # note %>\n<p><% x %>",
+            "<% x = /a %><p><% b/ %>",
+            "<% x = \"a %><br><% b\" %>",
+        ];
+
+        for data in samples.iter() {
+            let (tokens, _) = lex_with_diagnostics(&lexers::registry(), &erb(), data);
+            assert_eq!(lex(&lexers::registry(), &erb(), data), tokens, "{}", data);
+        }
+    }
+
+    #[test]
+    fn regions_in_unregistered_languages_are_text() {
+        let mut host = Delimited::new("xml");
+        host.add(&["{{"], "}}", "handlebars");
+
+        let tokens = lex(&lexers::registry(), &host, "<p>{{ name }}</p>");
+        assert_eq!(tokens[3].lexeme, "{{");
+        assert_eq!(tokens[4].lexeme, " name ");
+        assert_eq!(tokens[4].category, Category::Text);
+        assert_eq!(tokens[5].lexeme, "}}");
+    }
+
+    // Lexes fenced blocks of data as Ruby.
+    fn document(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+        if tokenizer.has_prefix("```") {
+            tokenizer.tokenize(Category::Text);
+            tokenizer.tokenize_next(3, Category::Keyword);
+            return Some(StateFunction(block))
+        }
+
+        tokenizer.current_char()?;
+        tokenizer.advance();
+        Some(StateFunction(document))
+    }
+
+    fn block(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+        if tokenizer.has_prefix("```") {
+            tokenizer.tokenize(Category::Text);
+            return Some(StateFunction(fence))
+        }
+
+        // Tokenize each line separately, producing several adjacent regions.
+        if tokenizer.current_char() == Some('\n') {
+            tokenizer.advance();
+            tokenizer.tokenize(Category::Text);
+            return Some(StateFunction(block))
+        }
+
+        tokenizer.current_char()?;
+        tokenizer.advance();
+        Some(StateFunction(block))
+    }
+
+    fn fence(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+        tokenizer.tokenize_next(3, Category::Keyword);
+        Some(StateFunction(document))
+    }
+
+    #[test]
+    fn states_hand_regions_off_to_other_languages() {
+        let mut host = States::new(StateFunction(document));
+        host.add(StateFunction(block), "ruby");
+
+        let data = "a\n```\nx = true\ny = \"\n```\nb\n```\n\"\n```";
+        let tokens = lex(&lexers::registry(), &host, data);
+        let expected_tokens = [
+            ("a\n", Category::Text),
+            ("```", Category::Keyword),
+            ("\n", Category::Whitespace),
+            ("x", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("true", Category::Boolean),
+            ("\n", Category::Whitespace),
+            ("y", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("\"\n", Category::String),
+            ("```", Category::Keyword),
+            ("\nb\n", Category::Text),
+            ("```", Category::Keyword),
+            ("\n\"", Category::String),
+            ("\n", Category::Whitespace),
+            ("```", Category::Keyword),
+        ];
+
+        assert_spans_are_document_relative(data, &tokens);
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
+        }
+    }
+}
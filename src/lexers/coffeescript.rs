@@ -1,34 +1,36 @@
 //! A lexer for the CoffeeScript programming language.
 
-use token::{Category, Token};
-use tokenizer::{Tokenizer, StateFunction};
+use diagnostic::{Diagnostic, Kind};
+use lexers::{is_unexpected, Lexer};
+use token::{BorrowedToken, Category, Token};
+use tokenizer::{Tokenizer, StateFunction, Tokens};
 
 fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     if tokenizer.starts_with_lexeme("class") {
-        tokenizer.tokenize_next(5, Category::Keyword);
+        tokenizer.tokenize_next_with_scope(5, Category::Keyword, "storage.type.class.coffee");
         tokenizer.consume_whitespace();
         return Some(StateFunction(identifier))
     } else if tokenizer.starts_with_lexeme("if") {
-        tokenizer.tokenize_next(2, Category::Keyword);
+        tokenizer.tokenize_next_with_scope(2, Category::Keyword, "keyword.control.conditional.coffee");
         return Some(StateFunction(whitespace))
     } else if tokenizer.starts_with_lexeme("else") {
-        tokenizer.tokenize_next(4, Category::Keyword);
+        tokenizer.tokenize_next_with_scope(4, Category::Keyword, "keyword.control.conditional.coffee");
         return Some(StateFunction(whitespace))
     } else if tokenizer.starts_with_lexeme("return") {
-        tokenizer.tokenize_next(6, Category::Keyword);
+        tokenizer.tokenize_next_with_scope(6, Category::Keyword, "keyword.control.flow.coffee");
         return Some(StateFunction(whitespace))
     } else if tokenizer.starts_with_lexeme("true") {
-        tokenizer.tokenize_next(4, Category::Boolean);
+        tokenizer.tokenize_next_with_scope(4, Category::Boolean, "constant.language.boolean.coffee");
         return Some(StateFunction(initial_state))
     } else if tokenizer.starts_with_lexeme("false") {
-        tokenizer.tokenize_next(5, Category::Boolean);
+        tokenizer.tokenize_next_with_scope(5, Category::Boolean, "constant.language.boolean.coffee");
         return Some(StateFunction(initial_state))
     } else if tokenizer.starts_with_lexeme("var") {
-        tokenizer.tokenize_next(3, Category::Keyword);
+        tokenizer.tokenize_next_with_scope(3, Category::Keyword, "storage.type.coffee");
         tokenizer.consume_whitespace();
         return Some(StateFunction(identifier))
     } else if tokenizer.has_prefix("###") {
-        tokenizer.tokenize(Category::Text);
+        tokenizer.tokenize(Category::Identifier);
         tokenizer.advance();
         tokenizer.advance();
         tokenizer.advance();
@@ -37,41 +39,58 @@ fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
 
     match tokenizer.current_char() {
         Some('"') => {
-            tokenizer.tokenize(Category::Text);
+            tokenizer.tokenize(Category::Identifier);
             tokenizer.advance();
             Some(StateFunction(inside_string))
         },
         Some('\'') => {
-            tokenizer.tokenize(Category::Text);
+            tokenizer.tokenize(Category::Identifier);
             tokenizer.advance();
             Some(StateFunction(inside_single_quote_string))
         },
-        Some('.') => {
-            tokenizer.tokenize_next(1, Category::Text);
+        Some('.') | Some(';') | Some(',') => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Punctuation);
             Some(StateFunction(initial_state))
         },
         Some('+') => {
-            tokenizer.tokenize_next(1, Category::Operator);
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next_with_scope(1, Category::Operator, "keyword.operator.arithmetic.coffee");
             Some(StateFunction(initial_state))
         },
         Some(' ') | Some('\n') => {
-            match tokenizer.next_non_whitespace_char() {
-                Some('=') => {
-                    tokenizer.tokenize(Category::Identifier);
-                    tokenizer.consume_whitespace();
-                    tokenizer.tokenize_next(1, Category::Text);
-                },
-                _ => tokenizer.consume_whitespace(),
-            }
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.consume_whitespace();
+            Some(StateFunction(initial_state))
+        },
+        Some('(') => {
+            tokenizer.tokenize(Category::Call);
+            tokenizer.tokenize_next(1, Category::Parenthesis);
             Some(StateFunction(initial_state))
         },
-        Some('=') => {
+        Some(')') => {
             tokenizer.tokenize(Category::Identifier);
-            tokenizer.tokenize_next(1, Category::Text);
+            tokenizer.tokenize_next(1, Category::Parenthesis);
+            Some(StateFunction(initial_state))
+        },
+        Some('{') | Some('}') => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Brace);
+            Some(StateFunction(initial_state))
+        },
+        Some('[') | Some(']') => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Bracket);
+            Some(StateFunction(initial_state))
+        },
+        Some('=') | Some('-') | Some('*') | Some('/') | Some('%') | Some('&') |
+        Some('|') | Some('<') | Some('>') | Some('!') | Some('?') | Some('^') | Some('~') => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Operator);
             Some(StateFunction(initial_state))
         },
         Some('#') => {
-            tokenizer.tokenize(Category::Text);
+            tokenizer.tokenize(Category::Identifier);
             tokenizer.advance();
             Some(StateFunction(comment))
         },
@@ -94,6 +113,13 @@ fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
                 Some(StateFunction(symbol))
             }
         },
+        Some(c) if is_unexpected(c) => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.advance();
+            tokenizer.report(Kind::UnexpectedCharacter, "unexpected character");
+            tokenizer.tokenize_with_scope(Category::Error, "invalid.illegal.character.coffee");
+            Some(StateFunction(initial_state))
+        },
         Some(c) => {
             tokenizer.advance();
 
@@ -105,7 +131,7 @@ fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         }
 
         None => {
-            tokenizer.tokenize(Category::Text);
+            tokenizer.tokenize(Category::Identifier);
             None
         }
     }
@@ -118,7 +144,7 @@ fn inside_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
             match c {
                 '"' => {
                     tokenizer.advance();
-                    tokenizer.tokenize(Category::String);
+                    tokenizer.tokenize_with_scope(Category::String, "string.quoted.double.coffee");
                     Some(StateFunction(initial_state))
                 },
                 '\\' => {
@@ -126,6 +152,10 @@ fn inside_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
                     tokenizer.advance();
                     Some(StateFunction(inside_string))
                 }
+                '#' if tokenizer.has_prefix("#{") => {
+                    tokenizer.tokenize_with_scope(Category::String, "string.quoted.double.coffee");
+                    Some(StateFunction(interpolation))
+                }
                 _ => {
                     tokenizer.advance();
                     Some(StateFunction(inside_string))
@@ -134,7 +164,34 @@ fn inside_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         }
 
         None => {
-            tokenizer.tokenize(Category::String);
+            tokenizer.report(Kind::UnterminatedString, "unterminated string");
+            tokenizer.tokenize_with_scope(Category::String, "string.quoted.double.coffee");
+            None
+        }
+    }
+}
+
+fn interpolation(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    match tokenizer.current_char() {
+        Some('}') => {
+            tokenizer.advance();
+
+            // Strings ending with an interpolation are reported with it, as nothing else remains.
+            if tokenizer.current_char().is_none() {
+                tokenizer.report(Kind::UnterminatedString, "unterminated string");
+                tokenizer.tokenize_with_scope(Category::Interpolation, "meta.embedded.line.coffee");
+                return None
+            }
+            tokenizer.tokenize_with_scope(Category::Interpolation, "meta.embedded.line.coffee");
+            Some(StateFunction(inside_string))
+        },
+        Some(_) => {
+            tokenizer.advance();
+            Some(StateFunction(interpolation))
+        },
+        None => {
+            tokenizer.report(Kind::UnterminatedString, "unterminated string");
+            tokenizer.tokenize_with_scope(Category::Interpolation, "meta.embedded.line.coffee");
             None
         }
     }
@@ -146,7 +203,7 @@ fn inside_single_quote_string(tokenizer: &mut Tokenizer) -> Option<StateFunction
             match c {
                 '\'' => {
                     tokenizer.advance();
-                    tokenizer.tokenize(Category::String);
+                    tokenizer.tokenize_with_scope(Category::String, "string.quoted.single.coffee");
                     Some(StateFunction(initial_state))
                 },
                 '\\' => {
@@ -162,7 +219,8 @@ fn inside_single_quote_string(tokenizer: &mut Tokenizer) -> Option<StateFunction
         }
 
         None => {
-            tokenizer.tokenize(Category::String);
+            tokenizer.report(Kind::UnterminatedString, "unterminated string");
+            tokenizer.tokenize_with_scope(Category::String, "string.quoted.single.coffee");
             None
         }
     }
@@ -201,9 +259,28 @@ fn identifier(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
                     tokenizer.tokenize(Category::Identifier);
                     Some(StateFunction(initial_state))
                 },
-                '|' | '(' | ')' | '-' | ';' | '{' | '}' => {
+                _ if is_unexpected(c) => {
+                    tokenizer.tokenize(Category::Identifier);
+                    Some(StateFunction(initial_state))
+                },
+                ';' => {
                     tokenizer.tokenize(Category::Identifier);
-                    tokenizer.tokenize_next(1, Category::Text);
+                    tokenizer.tokenize_next(1, Category::Punctuation);
+                    Some(StateFunction(initial_state))
+                },
+                '(' | ')' => {
+                    tokenizer.tokenize(Category::Identifier);
+                    tokenizer.tokenize_next(1, Category::Parenthesis);
+                    Some(StateFunction(initial_state))
+                },
+                '{' | '}' => {
+                    tokenizer.tokenize(Category::Identifier);
+                    tokenizer.tokenize_next(1, Category::Brace);
+                    Some(StateFunction(initial_state))
+                },
+                '|' | '-' => {
+                    tokenizer.tokenize(Category::Identifier);
+                    tokenizer.tokenize_next(1, Category::Operator);
                     Some(StateFunction(initial_state))
                 },
                 _ => {
@@ -225,7 +302,7 @@ fn comment(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         Some(c) => {
             match c {
                 '\n' => {
-                    tokenizer.tokenize(Category::Comment);
+                    tokenizer.tokenize_with_scope(Category::Comment, "comment.line.number-sign.coffee");
                     Some(StateFunction(initial_state))
                 },
                 _ => {
@@ -236,7 +313,7 @@ fn comment(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         }
 
         None => {
-            tokenizer.tokenize(Category::Whitespace);
+            tokenizer.tokenize_with_scope(Category::Comment, "comment.line.number-sign.coffee");
             None
         }
     }
@@ -247,7 +324,7 @@ fn multi_line_comment(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         tokenizer.advance();
         tokenizer.advance();
         tokenizer.advance();
-        tokenizer.tokenize(Category::Comment);
+        tokenizer.tokenize_with_scope(Category::Comment, "comment.block.coffee");
         return Some(StateFunction(initial_state))
     }
 
@@ -257,7 +334,8 @@ fn multi_line_comment(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
             Some(StateFunction(multi_line_comment))
         },
         None => {
-            tokenizer.tokenize(Category::Comment);
+            tokenizer.report(Kind::UnterminatedComment, "unterminated comment");
+            tokenizer.tokenize_with_scope(Category::Comment, "comment.block.coffee");
             None
         }
     }
@@ -270,13 +348,13 @@ fn integer(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
                 tokenizer.advance();
                 Some(StateFunction(integer))
             } else {
-                tokenizer.tokenize(Category::Integer);
+                tokenizer.tokenize_with_scope(Category::Integer, "constant.numeric.integer.coffee");
                 Some(StateFunction(initial_state))
             }
         }
 
         None => {
-            tokenizer.tokenize(Category::Integer);
+            tokenizer.tokenize_with_scope(Category::Integer, "constant.numeric.integer.coffee");
             None
         }
     }
@@ -301,66 +379,96 @@ fn symbol(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     }
 }
 
+/// Lexes the data lazily, producing tokens that borrow their lexemes
+/// from the data, rather than allocating a copy for each of them.
+pub fn lex_borrowed(data: &str) -> Tokens<'_> {
+    Tokenizer::new(data).run(StateFunction(initial_state))
+}
+
+/// Lexes the data lazily, running the lexer's
+/// state functions only as tokens are requested.
+pub fn lex_iter(data: &str) -> impl Iterator<Item=Token> + '_ {
+    lex_borrowed(data).map(Token::from)
+}
+
 /// Lexes a CoffeeScript document.
 pub fn lex(data: &str) -> Vec<Token> {
-    let mut tokenizer = Tokenizer::new(data);
-    let mut state_function = StateFunction(initial_state);
-    loop {
-        let StateFunction(actual_function) = state_function;
-        match actual_function(&mut tokenizer) {
-            Some(f) => state_function = f,
-            None => {
-                match tokenizer.states.pop() {
-                    Some(f) => state_function = f,
-                    None => return tokenizer.tokens(),
-                }
-            }
-        }
+    lex_iter(data).collect()
+}
+
+/// Lexes a CoffeeScript document, reporting any malformed input it contains.
+pub fn lex_with_diagnostics(data: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+    let mut tokens = lex_borrowed(data);
+    let lexed = tokens.by_ref().map(Token::from).collect();
+
+    (lexed, tokens.into_diagnostics())
+}
+
+/// The CoffeeScript lexer, exposed through the common `Lexer` interface.
+pub struct CoffeeScriptLexer;
+
+impl Lexer for CoffeeScriptLexer {
+    fn name(&self) -> &str { "CoffeeScript" }
+    fn aliases(&self) -> &[&str] { &["coffeescript", "coffee"] }
+    fn extensions(&self) -> &[&str] { &["coffee"] }
+    fn mime_types(&self) -> &[&str] { &["text/coffeescript", "text/x-coffeescript"] }
+    fn filenames(&self) -> &[&str] { &["Cakefile"] }
+    fn interpreters(&self) -> &[&str] { &["coffee"] }
+    fn lex(&self, data: &str) -> Vec<Token> { lex(data) }
+    fn lex_with_diagnostics(&self, data: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+        lex_with_diagnostics(data)
+    }
+    fn lex_iter<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=Token> + 'a> { Box::new(lex_iter(data)) }
+    fn lex_borrowed<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=BorrowedToken<'a>> + 'a> {
+        Box::new(lex_borrowed(data))
     }
+    fn initial_state(&self) -> Option<StateFunction> { Some(StateFunction(initial_state)) }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::lex;
-    use token::Token;
+    use super::{lex, lex_with_diagnostics};
+    use diagnostic::Kind;
     use token::Category;
 
     #[test]
     fn it_works() {
         let data = include_str!("../../test_data/data.coffee");
         let tokens = lex(data);
-        let expected_tokens = vec![
-            Token{ lexeme: "class".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "Coffee".to_string(), category: Category::Identifier },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "data".to_string(), category: Category::Identifier },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "=".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "\"string\"".to_string(), category: Category::String },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "@data".to_string(), category: Category::Identifier },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "=".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "'string'".to_string(), category: Category::String },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "key:".to_string(), category: Category::Literal },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "# comment".to_string(), category: Category::Comment },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "###\n  multi-line comment\n  ###".to_string(), category: Category::Comment },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "$".to_string(), category: Category::Keyword },
-            Token{ lexeme: "(".to_string(), category: Category::Text },
-            Token{ lexeme: "'.class'".to_string(), category: Category::String },
-            Token{ lexeme: ")".to_string(), category: Category::Text },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
+        let expected_tokens = [
+            ("class", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("Coffee", Category::Identifier),
+            ("\n  ", Category::Whitespace),
+            ("data", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("\"string\"", Category::String),
+            ("\n  ", Category::Whitespace),
+            ("@data", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("'string'", Category::String),
+            ("\n  ", Category::Whitespace),
+            ("key:", Category::Literal),
+            ("\n  ", Category::Whitespace),
+            ("# comment", Category::Comment),
+            ("\n  ", Category::Whitespace),
+            ("###\n  multi-line comment\n  ###", Category::Comment),
+            ("\n  ", Category::Whitespace),
+            ("$", Category::Keyword),
+            ("(", Category::Parenthesis),
+            ("'.class'", Category::String),
+            (")", Category::Parenthesis),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
         }
     }
 
@@ -368,16 +476,60 @@ mod tests {
     fn it_identifies_integers_and_operators() {
         let data = "123 + 456";
         let tokens = lex(data);
-        let expected_tokens = vec![
-            Token{ lexeme: "123".to_string(), category: Category::Integer },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "+".to_string(), category: Category::Operator },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "456".to_string(), category: Category::Integer },
+        let expected_tokens = [
+            ("123", Category::Integer),
+            (" ", Category::Whitespace),
+            ("+", Category::Operator),
+            (" ", Category::Whitespace),
+            ("456", Category::Integer),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
+        }
+    }
+
+    #[test]
+    fn it_identifies_interpolation_and_punctuation() {
+        let tokens = lex("greet \"Hi #{name}!\", [1]\n");
+        let expected_tokens = [
+            ("greet", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("\"Hi ", Category::String),
+            ("#{name}", Category::Interpolation),
+            ("!\"", Category::String),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("[", Category::Bracket),
+            ("1", Category::Integer),
+            ("]", Category::Bracket),
+            ("\n", Category::Whitespace),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
         }
     }
+
+    #[test]
+    fn it_categorizes_tokens_ending_the_data() {
+        let tokens = lex("# c");
+        assert_eq!(tokens.last().unwrap().lexeme, "# c");
+        assert_eq!(tokens.last().unwrap().category, Category::Comment);
+    }
+
+    #[test]
+    fn it_reports_unexpected_characters() {
+        let (tokens, diagnostics) = lex_with_diagnostics("@a¿ = 1");
+        assert_eq!(tokens[1].lexeme, "¿");
+        assert_eq!(tokens[1].category, Category::Error);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, Kind::UnexpectedCharacter);
+        assert_eq!(diagnostics[0].span.range(), 2..4);
+    }
 }
@@ -2,8 +2,9 @@
 //! suitable as a fallback in situations where a format/language-specific
 //! equivalent is unavailable.
 
-use token::{Category, Token};
-use tokenizer::{Tokenizer, StateFunction};
+use lexers::Lexer;
+use token::{BorrowedToken, Category, Token};
+use tokenizer::{Tokenizer, StateFunction, Tokens};
 
 fn initial_state(lexer: &mut Tokenizer) -> Option<StateFunction> {
     match lexer.current_char() {
@@ -49,48 +50,70 @@ fn whitespace(lexer: &mut Tokenizer) -> Option<StateFunction> {
     }
 }
 
+/// Lexes the data lazily, producing tokens that borrow their lexemes
+/// from the data, rather than allocating a copy for each of them.
+pub fn lex_borrowed(data: &str) -> Tokens<'_> {
+    Tokenizer::new(data).run(StateFunction(initial_state))
+}
+
+/// Lexes the data lazily, running the lexer's
+/// state functions only as tokens are requested.
+pub fn lex_iter(data: &str) -> impl Iterator<Item=Token> + '_ {
+    lex_borrowed(data).map(Token::from)
+}
+
 /// Lexes any UTF-8 document.
 pub fn lex(data: &str) -> Vec<Token> {
-    let mut lexer = Tokenizer::new(data);
-    let mut state_function = StateFunction(initial_state);
-    loop {
-        let StateFunction(actual_function) = state_function;
-        match actual_function(&mut lexer) {
-            Some(f) => state_function = f,
-            None => return lexer.tokens(),
-        }
+    lex_iter(data).collect()
+}
+
+/// The plain text lexer, exposed through the common `Lexer` interface.
+pub struct DefaultLexer;
+
+impl Lexer for DefaultLexer {
+    fn name(&self) -> &str { "Text" }
+    fn aliases(&self) -> &[&str] { &["text", "plain", "default"] }
+    fn extensions(&self) -> &[&str] { &["txt"] }
+    fn mime_types(&self) -> &[&str] { &["text/plain"] }
+    fn lex(&self, data: &str) -> Vec<Token> { lex(data) }
+    fn lex_iter<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=Token> + 'a> { Box::new(lex_iter(data)) }
+    fn lex_borrowed<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=BorrowedToken<'a>> + 'a> {
+        Box::new(lex_borrowed(data))
     }
+    fn initial_state(&self) -> Option<StateFunction> { Some(StateFunction(initial_state)) }
 }
 
 #[cfg(test)]
 mod tests {
     use super::lex;
-    use token::Token;
     use token::Category;
 
     #[test]
     fn it_works() {
         let data = include_str!("../../test_data/data.txt");
         let tokens = lex(data);
-        let expected_tokens = vec![
-            Token{ lexeme: "This".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "is".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "a".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "test.".to_string(), category: Category::Text },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "Luthor".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "text".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "lexing.".to_string(), category: Category::Text },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
+        let expected_tokens = [
+            ("This", Category::Text),
+            (" ", Category::Whitespace),
+            ("is", Category::Text),
+            (" ", Category::Whitespace),
+            ("a", Category::Text),
+            (" ", Category::Whitespace),
+            ("test.", Category::Text),
+            ("\n  ", Category::Whitespace),
+            ("Luthor", Category::Text),
+            (" ", Category::Whitespace),
+            ("text", Category::Text),
+            (" ", Category::Whitespace),
+            ("lexing.", Category::Text),
+            ("\n", Category::Whitespace),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
         }
     }
 }
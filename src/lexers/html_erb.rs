@@ -1,119 +1,161 @@
-//! A simple lexer for HTML data with embedded Ruby. Breaks data into three
-//! segments: HTML, erb tags, and Ruby. Defers to other lexers for the HTML
-//! and Ruby segments.
-
-use lexers;
-use token::{Category, Token};
-use tokenizer::{Tokenizer, StateFunction};
-
-fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
-    // Check for (and tokenize) erb tags.
-    if tokenizer.has_prefix("<%=") {
-        tokenizer.tokenize_next(3, Category::Keyword);
-        return Some(StateFunction(ruby))
-    } else if tokenizer.has_prefix("<%") {
-        tokenizer.tokenize_next(2, Category::Keyword);
-        return Some(StateFunction(ruby))
-    }
+//! A lexer for HTML data with embedded Ruby, built using the `injection`
+//! module. Defers to other lexers for the HTML and the Ruby within erb tags,
+//! carrying each language's lexing state from one segment to the next, so
+//! that a Ruby block opened in one tag can be closed in another, and an erb
+//! tag can appear within an HTML attribute's value.
 
-    match tokenizer.current_char() {
-        Some(_) => {
-            tokenizer.advance();
-            Some(StateFunction(initial_state))
-        },
-        None => {
-            tokenizer.tokenize(Category::Text);
-            None
-        }
-    }
+use std::sync::OnceLock;
+use diagnostic::Diagnostic;
+use injection::{self, Delimited};
+use lexers::{self, Lexer};
+use token::{BorrowedToken, Token};
+
+// HTML, with Ruby in erb tags.
+fn host() -> &'static Delimited {
+    static HOST: OnceLock<Delimited> = OnceLock::new();
+
+    HOST.get_or_init(|| {
+        let mut host = Delimited::new("xml");
+        host.add(&["<%=", "<%"], "%>", "ruby");
+
+        host
+    })
 }
 
-fn ruby(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
-    // Check for (and tokenize) erb exit tag.
-    if tokenizer.has_prefix("%>") {
-        tokenizer.tokenize(Category::String);
-        tokenizer.tokenize_next(2, Category::Keyword);
-        return Some(StateFunction(initial_state))
-    }
+/// Lexes the data, deferring to the nested lexers for HTML and
+/// Ruby segments. Tokens borrow their lexemes from the data.
+pub fn lex_borrowed(data: &str) -> impl Iterator<Item=BorrowedToken<'_>> {
+    injection::lex_borrowed(lexers::shared_registry(), host(), data)
+}
 
-    match tokenizer.current_char() {
-        Some(_) => {
-            tokenizer.advance();
-            Some(StateFunction(ruby))
-        },
-        None => {
-            tokenizer.tokenize(Category::String);
-            None
-        }
-    }
+/// Lexes the data, deferring to the nested lexers for HTML and Ruby segments.
+pub fn lex_iter(data: &str) -> impl Iterator<Item=Token> + '_ {
+    lex_borrowed(data).map(Token::from)
 }
 
 pub fn lex(data: &str) -> Vec<Token> {
-    // Lex the data into three categories; one for html segments.
-    // another for erb tags, and yet another for Ruby segments.
-    let mut tokenizer = Tokenizer::new(data);
-    let mut state_function = StateFunction(initial_state);
-    loop {
-        let StateFunction(actual_function) = state_function;
-        match actual_function(&mut tokenizer) {
-            Some(f) => state_function = f,
-            None => break,
-        }
+    lex_iter(data).collect()
+}
+
+/// Lexes the data, reporting any malformed input in its HTML and Ruby segments.
+pub fn lex_with_diagnostics(data: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+    injection::lex_with_diagnostics(lexers::shared_registry(), host(), data)
+}
+
+/// The HTML/erb lexer, exposed through the common `Lexer` interface.
+pub struct HtmlErbLexer;
+
+impl Lexer for HtmlErbLexer {
+    fn name(&self) -> &str { "HTML+ERB" }
+    fn aliases(&self) -> &[&str] { &["erb", "eruby", "html+erb", "rhtml"] }
+    fn extensions(&self) -> &[&str] { &["html.erb", "erb", "rhtml"] }
+    fn mime_types(&self) -> &[&str] { &["text/html+ruby"] }
+    fn analyse(&self, data: &str) -> f32 {
+        if data.contains("<%") && data.contains("%>") { 0.5 } else { 0.0 }
     }
 
-    // Defer to other lexers for HTML and Ruby segments, and combine the sets.
-    tokenizer.tokens().iter().fold(
-        Vec::new(),
-        |mut tokens, token| {
-            match token.category {
-                Category::Keyword => tokens.push(token.clone()),
-                Category::String => tokens.extend(lexers::ruby::lex(&token.lexeme).into_iter()),
-                Category::Text => tokens.extend(lexers::xml::lex(&token.lexeme).into_iter()),
-                _ => (),
-            };
-
-            tokens
-        }
-    )
+    fn lex(&self, data: &str) -> Vec<Token> { lex(data) }
+    fn lex_with_diagnostics(&self, data: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+        lex_with_diagnostics(data)
+    }
+    fn lex_iter<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=Token> + 'a> { Box::new(lex_iter(data)) }
+    fn lex_borrowed<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=BorrowedToken<'a>> + 'a> {
+        Box::new(lex_borrowed(data))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use token::{Category, Token};
+    use token::Category;
 
     #[test]
     fn it_works() {
         let data = include_str!("../../test_data/html_erb.html.erb");
         let tokens = super::lex(data);
 
-        let expected_tokens = vec![
-            Token{ lexeme: "<".to_string(), category: Category::Text },
-            Token{ lexeme: "html".to_string(), category: Category::Identifier },
-            Token{ lexeme: ">".to_string(), category: Category::Text },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "<%".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "class".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "Ruby".to_string(), category: Category::Identifier },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "%>".to_string(), category: Category::Keyword },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "<%=".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "class".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "Ruby".to_string(), category: Category::Identifier },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "%>".to_string(), category: Category::Keyword },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "</".to_string(), category: Category::Text },
-            Token{ lexeme: "html".to_string(), category: Category::Identifier },
-            Token{ lexeme: ">".to_string(), category: Category::Text },
+        let expected_tokens = [
+            ("<", Category::Punctuation),
+            ("html", Category::Identifier),
+            (">", Category::Punctuation),
+            ("\n", Category::Whitespace),
+            ("<%", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("class", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("Ruby", Category::Type),
+            (" ", Category::Whitespace),
+            ("%>", Category::Keyword),
+            ("\n", Category::Whitespace),
+            ("<%=", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("class", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("Ruby", Category::Type),
+            (" ", Category::Whitespace),
+            ("%>", Category::Keyword),
+            ("\n", Category::Whitespace),
+            ("</", Category::Punctuation),
+            ("html", Category::Identifier),
+            (">", Category::Punctuation),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
+        }
+    }
+
+    #[test]
+    fn it_lexes_ruby_spanning_several_tags() {
+        let tokens = super::lex("<% if x %><p class=\"<%= y %>\"><% end %>");
+        let expected_tokens = [
+            ("<%", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("if", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("x", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("%>", Category::Keyword),
+            ("<", Category::Punctuation),
+            ("p", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("class", Category::Attribute),
+            ("=", Category::Punctuation),
+            ("\"", Category::String),
+            ("<%=", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("y", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("%>", Category::Keyword),
+            ("\"", Category::String),
+            (">", Category::Punctuation),
+            ("<%", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("end", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("%>", Category::Keyword),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+        }
+    }
+
+    #[test]
+    fn it_produces_document_relative_spans() {
+        let data = "<p>\n<%= foo %>";
+        let tokens = super::lex(data);
+
+        for token in tokens.iter() {
+            assert_eq!(&data[token.span.range()], token.lexeme);
         }
+
+        let foo = tokens.iter().find(|token| token.lexeme == "foo").unwrap();
+        assert_eq!(foo.span.start.line, 1);
+        assert_eq!(foo.span.start.column, 4);
     }
 }
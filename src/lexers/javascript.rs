@@ -1,37 +1,45 @@
 //! A lexer for the Ruby programming language.
 
-use token::{Category, Token};
-use tokenizer::{Tokenizer, StateFunction};
+use diagnostic::{Diagnostic, Kind};
+use lexers::{is_unexpected, Lexer};
+use token::{BorrowedToken, Category, Token};
+use tokenizer::{Tokenizer, StateFunction, Tokens};
 
 fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     if tokenizer.starts_with_lexeme("function") {
-        tokenizer.tokenize_next(8, Category::Keyword);
+        tokenizer.tokenize_next_with_scope(8, Category::Keyword, "storage.type.function.js");
         tokenizer.states.push(StateFunction(function));
         return Some(StateFunction(whitespace))
     } else if tokenizer.starts_with_lexeme("if") {
-        tokenizer.tokenize_next(2, Category::Keyword);
+        tokenizer.tokenize_next_with_scope(2, Category::Keyword, "keyword.control.conditional.js");
         return Some(StateFunction(whitespace))
     } else if tokenizer.starts_with_lexeme("else") {
-        tokenizer.tokenize_next(4, Category::Keyword);
+        tokenizer.tokenize_next_with_scope(4, Category::Keyword, "keyword.control.conditional.js");
         return Some(StateFunction(whitespace))
     } else if tokenizer.starts_with_lexeme("return") {
-        tokenizer.tokenize_next(6, Category::Keyword);
+        tokenizer.tokenize_next_with_scope(6, Category::Keyword, "keyword.control.flow.js");
         return Some(StateFunction(whitespace))
     } else if tokenizer.starts_with_lexeme("true") {
-        tokenizer.tokenize_next(4, Category::Boolean);
+        tokenizer.tokenize_next_with_scope(4, Category::Boolean, "constant.language.boolean.js");
         return Some(StateFunction(initial_state))
     } else if tokenizer.starts_with_lexeme("false") {
-        tokenizer.tokenize_next(5, Category::Boolean);
+        tokenizer.tokenize_next_with_scope(5, Category::Boolean, "constant.language.boolean.js");
         return Some(StateFunction(initial_state))
     } else if tokenizer.starts_with_lexeme("var") {
-        tokenizer.tokenize_next(3, Category::Keyword);
+        tokenizer.tokenize_next_with_scope(3, Category::Keyword, "storage.type.js");
         tokenizer.consume_whitespace();
         return Some(StateFunction(identifier))
     } else if tokenizer.has_prefix("//") {
-        tokenizer.tokenize(Category::Text);
+        tokenizer.tokenize(Category::Identifier);
         return Some(StateFunction(comment))
+    } else if tokenizer.has_prefix("/**") && !tokenizer.has_prefix("/**/") {
+        tokenizer.tokenize(Category::Identifier);
+        tokenizer.advance();
+        tokenizer.advance();
+        tokenizer.advance();
+        return Some(StateFunction(doc_comment))
     } else if tokenizer.has_prefix("/*") {
-        tokenizer.tokenize(Category::Text);
+        tokenizer.tokenize(Category::Identifier);
         tokenizer.advance();
         tokenizer.advance();
         return Some(StateFunction(multi_line_comment))
@@ -39,43 +47,78 @@ fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
 
     match tokenizer.current_char() {
         Some('"') => {
-            tokenizer.tokenize(Category::Text);
+            tokenizer.tokenize(Category::Identifier);
             tokenizer.advance();
             Some(StateFunction(inside_string))
         },
         Some('\'') => {
-            tokenizer.tokenize(Category::Text);
+            tokenizer.tokenize(Category::Identifier);
             tokenizer.advance();
             Some(StateFunction(inside_single_quote_string))
         },
-        Some('.') => {
-            tokenizer.tokenize_next(1, Category::Text);
+        Some('`') => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.advance();
+            Some(StateFunction(inside_template_string))
+        },
+        Some('.') | Some(';') | Some(',') => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Punctuation);
             Some(StateFunction(initial_state))
         },
         Some('+') => {
-            tokenizer.tokenize_next(1, Category::Operator);
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next_with_scope(1, Category::Operator, "keyword.operator.arithmetic.js");
             Some(StateFunction(initial_state))
         },
         Some(' ') | Some('\n') => {
-            match tokenizer.next_non_whitespace_char() {
-                Some('=') => {
-                    tokenizer.tokenize(Category::Identifier);
-                    tokenizer.consume_whitespace();
-                    tokenizer.tokenize_next(1, Category::Text);
-                },
-                _ => tokenizer.consume_whitespace(),
-            }
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.consume_whitespace();
             Some(StateFunction(initial_state))
         },
         Some('=') => {
             tokenizer.tokenize(Category::Identifier);
-            tokenizer.tokenize_next(1, Category::Text);
+            tokenizer.tokenize_next(1, Category::Operator);
+            tokenizer.consume_whitespace();
+
+            // A slash can only begin a regular expression where
+            // a value is expected, rather than a division.
+            if tokenizer.has_prefix("/") && !tokenizer.has_prefix("//") && !tokenizer.has_prefix("/*") {
+                tokenizer.advance();
+                return Some(StateFunction(regex))
+            }
+            Some(StateFunction(initial_state))
+        },
+        Some('(') => {
+            tokenizer.tokenize(Category::Call);
+            tokenizer.tokenize_next(1, Category::Parenthesis);
+            Some(StateFunction(argument))
+        },
+        Some(')') => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Parenthesis);
+            Some(StateFunction(initial_state))
+        },
+        Some('{') | Some('}') => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Brace);
+            Some(StateFunction(initial_state))
+        },
+        Some('[') | Some(']') => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Bracket);
+            Some(StateFunction(initial_state))
+        },
+        Some('-') | Some('*') | Some('/') | Some('%') | Some('&') | Some('|') |
+        Some('<') | Some('>') | Some('!') | Some('?') | Some('^') | Some('~') => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Operator);
             Some(StateFunction(initial_state))
         },
         Some(':') => {
             if tokenizer.starts_with_lexeme(":") {
                 tokenizer.tokenize(Category::Literal);
-                tokenizer.tokenize_next(1, Category::Text);
+                tokenizer.tokenize_next(1, Category::Punctuation);
                 tokenizer.consume_whitespace();
                 Some(StateFunction(initial_state))
             } else {
@@ -83,6 +126,13 @@ fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
                 Some(StateFunction(symbol))
             }
         },
+        Some(c) if is_unexpected(c) => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.advance();
+            tokenizer.report(Kind::UnexpectedCharacter, "unexpected character");
+            tokenizer.tokenize_with_scope(Category::Error, "invalid.illegal.character.js");
+            Some(StateFunction(initial_state))
+        },
         Some(c) => {
             tokenizer.advance();
 
@@ -94,7 +144,7 @@ fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         }
 
         None => {
-            tokenizer.tokenize(Category::Text);
+            tokenizer.tokenize(Category::Identifier);
             None
         }
     }
@@ -107,7 +157,7 @@ fn inside_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
             match c {
                 '"' => {
                     tokenizer.advance();
-                    tokenizer.tokenize(Category::String);
+                    tokenizer.tokenize_with_scope(Category::String, "string.quoted.double.js");
                     Some(StateFunction(initial_state))
                 },
                 '\\' => {
@@ -123,7 +173,8 @@ fn inside_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         }
 
         None => {
-            tokenizer.tokenize(Category::String);
+            tokenizer.report(Kind::UnterminatedString, "unterminated string");
+            tokenizer.tokenize_with_scope(Category::String, "string.quoted.double.js");
             None
         }
     }
@@ -135,7 +186,7 @@ fn inside_single_quote_string(tokenizer: &mut Tokenizer) -> Option<StateFunction
             match c {
                 '\'' => {
                     tokenizer.advance();
-                    tokenizer.tokenize(Category::String);
+                    tokenizer.tokenize_with_scope(Category::String, "string.quoted.single.js");
                     Some(StateFunction(initial_state))
                 },
                 '\\' => {
@@ -151,7 +202,62 @@ fn inside_single_quote_string(tokenizer: &mut Tokenizer) -> Option<StateFunction
         }
 
         None => {
-            tokenizer.tokenize(Category::String);
+            tokenizer.report(Kind::UnterminatedString, "unterminated string");
+            tokenizer.tokenize_with_scope(Category::String, "string.quoted.single.js");
+            None
+        }
+    }
+}
+
+fn inside_template_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    match tokenizer.current_char() {
+        Some('`') => {
+            tokenizer.advance();
+            tokenizer.tokenize_with_scope(Category::String, "string.template.js");
+            Some(StateFunction(initial_state))
+        },
+        Some('\\') => {
+            tokenizer.advance();
+            tokenizer.advance();
+            Some(StateFunction(inside_template_string))
+        },
+        Some('$') if tokenizer.has_prefix("${") => {
+            tokenizer.tokenize_with_scope(Category::String, "string.template.js");
+            Some(StateFunction(interpolation))
+        },
+        Some(_) => {
+            tokenizer.advance();
+            Some(StateFunction(inside_template_string))
+        },
+        None => {
+            tokenizer.report(Kind::UnterminatedString, "unterminated string");
+            tokenizer.tokenize_with_scope(Category::String, "string.template.js");
+            None
+        }
+    }
+}
+
+fn interpolation(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    match tokenizer.current_char() {
+        Some('}') => {
+            tokenizer.advance();
+
+            // Strings ending with an interpolation are reported with it, as nothing else remains.
+            if tokenizer.current_char().is_none() {
+                tokenizer.report(Kind::UnterminatedString, "unterminated string");
+                tokenizer.tokenize_with_scope(Category::Interpolation, "meta.template.expression.js");
+                return None
+            }
+            tokenizer.tokenize_with_scope(Category::Interpolation, "meta.template.expression.js");
+            Some(StateFunction(inside_template_string))
+        },
+        Some(_) => {
+            tokenizer.advance();
+            Some(StateFunction(interpolation))
+        },
+        None => {
+            tokenizer.report(Kind::UnterminatedString, "unterminated string");
+            tokenizer.tokenize_with_scope(Category::Interpolation, "meta.template.expression.js");
             None
         }
     }
@@ -184,10 +290,10 @@ fn whitespace(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
 
 fn argument(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     if tokenizer.starts_with_lexeme("true") {
-        tokenizer.tokenize_next(4, Category::Boolean);
+        tokenizer.tokenize_next_with_scope(4, Category::Boolean, "constant.language.boolean.js");
         return Some(StateFunction(argument))
     } else if tokenizer.starts_with_lexeme("false") {
-        tokenizer.tokenize_next(5, Category::Boolean);
+        tokenizer.tokenize_next_with_scope(5, Category::Boolean, "constant.language.boolean.js");
         return Some(StateFunction(argument))
     }
 
@@ -199,16 +305,29 @@ fn argument(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
                     tokenizer.states.push(StateFunction(argument));
                     Some(StateFunction(whitespace))
                 },
-                '|' | ')' => {
+                ')' => {
                     tokenizer.tokenize(Category::Identifier);
-                    tokenizer.tokenize_next(1, Category::Text);
+                    tokenizer.tokenize_next(1, Category::Parenthesis);
                     Some(StateFunction(initial_state))
                 },
-                '=' | ',' => {
+                '=' => {
                     tokenizer.tokenize(Category::Identifier);
-                    tokenizer.tokenize_next(1, Category::Text);
+                    tokenizer.tokenize_next(1, Category::Operator);
                     Some(StateFunction(argument))
                 },
+                ',' => {
+                    tokenizer.tokenize(Category::Identifier);
+                    tokenizer.tokenize_next(1, Category::Punctuation);
+                    Some(StateFunction(argument))
+                },
+                '/' if !tokenizer.has_pending_data() => {
+                    tokenizer.advance();
+                    Some(StateFunction(regex))
+                },
+                _ if is_unexpected(c) || !(tokenizer.has_pending_data() || c.is_alphabetic() || c == '_' || c == '$') => {
+                    // Literals, operators and unexpected characters are handled by the initial state.
+                    Some(StateFunction(initial_state))
+                },
                 _ => {
                     tokenizer.advance();
                     Some(StateFunction(argument))
@@ -231,9 +350,28 @@ fn identifier(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
                     tokenizer.tokenize(Category::Identifier);
                     Some(StateFunction(initial_state))
                 },
-                '|' | '(' | ')' | '-' | ';' | '{' | '}' => {
+                _ if is_unexpected(c) => {
+                    tokenizer.tokenize(Category::Identifier);
+                    Some(StateFunction(initial_state))
+                },
+                ';' => {
                     tokenizer.tokenize(Category::Identifier);
-                    tokenizer.tokenize_next(1, Category::Text);
+                    tokenizer.tokenize_next(1, Category::Punctuation);
+                    Some(StateFunction(initial_state))
+                },
+                '(' | ')' => {
+                    tokenizer.tokenize(Category::Identifier);
+                    tokenizer.tokenize_next(1, Category::Parenthesis);
+                    Some(StateFunction(initial_state))
+                },
+                '{' | '}' => {
+                    tokenizer.tokenize(Category::Identifier);
+                    tokenizer.tokenize_next(1, Category::Brace);
+                    Some(StateFunction(initial_state))
+                },
+                '|' | '-' => {
+                    tokenizer.tokenize(Category::Identifier);
+                    tokenizer.tokenize_next(1, Category::Operator);
                     Some(StateFunction(initial_state))
                 },
                 _ => {
@@ -255,12 +393,12 @@ fn function(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         Some(c) => {
             match c {
                 ' ' | '\n' => {
-                    tokenizer.tokenize(Category::Function);
+                    tokenizer.tokenize_with_scope(Category::Function, "entity.name.function.js");
                     Some(StateFunction(initial_state))
                 },
                 '(' => {
-                    tokenizer.tokenize(Category::Function);
-                    tokenizer.tokenize_next(1, Category::Text);
+                    tokenizer.tokenize_with_scope(Category::Function, "entity.name.function.js");
+                    tokenizer.tokenize_next(1, Category::Parenthesis);
                     Some(StateFunction(argument))
                 },
                 _ => {
@@ -271,7 +409,7 @@ fn function(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         }
 
         None => {
-            tokenizer.tokenize(Category::Whitespace);
+            tokenizer.tokenize_with_scope(Category::Function, "entity.name.function.js");
             None
         }
     }
@@ -282,7 +420,7 @@ fn comment(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         Some(c) => {
             match c {
                 '\n' => {
-                    tokenizer.tokenize(Category::Comment);
+                    tokenizer.tokenize_with_scope(Category::Comment, "comment.line.double-slash.js");
                     Some(StateFunction(initial_state))
                 },
                 _ => {
@@ -293,7 +431,7 @@ fn comment(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         }
 
         None => {
-            tokenizer.tokenize(Category::Whitespace);
+            tokenizer.tokenize_with_scope(Category::Comment, "comment.line.double-slash.js");
             None
         }
     }
@@ -303,7 +441,7 @@ fn multi_line_comment(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     if tokenizer.has_prefix("*/") {
         tokenizer.advance();
         tokenizer.advance();
-        tokenizer.tokenize(Category::Comment);
+        tokenizer.tokenize_with_scope(Category::Comment, "comment.block.js");
         return Some(StateFunction(initial_state))
     }
 
@@ -313,12 +451,70 @@ fn multi_line_comment(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
             Some(StateFunction(multi_line_comment))
         },
         None => {
-            tokenizer.tokenize(Category::Comment);
+            tokenizer.report(Kind::UnterminatedComment, "unterminated comment");
+            tokenizer.tokenize_with_scope(Category::Comment, "comment.block.js");
+            None
+        }
+    }
+}
+
+fn doc_comment(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    if tokenizer.has_prefix("*/") {
+        tokenizer.advance();
+        tokenizer.advance();
+        tokenizer.tokenize_with_scope(Category::DocComment, "comment.block.documentation.js");
+        return Some(StateFunction(initial_state))
+    }
+
+    match tokenizer.current_char() {
+        Some(_) => {
+            tokenizer.advance();
+            Some(StateFunction(doc_comment))
+        },
+        None => {
+            tokenizer.report(Kind::UnterminatedComment, "unterminated comment");
+            tokenizer.tokenize_with_scope(Category::DocComment, "comment.block.documentation.js");
             None
         }
     }
 }
 
+fn regex(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    match tokenizer.current_char() {
+        Some('/') => {
+            tokenizer.advance();
+            Some(StateFunction(regex_flags))
+        },
+        Some('\\') => {
+            tokenizer.advance();
+            tokenizer.advance();
+            Some(StateFunction(regex))
+        },
+        Some('\n') | None => {
+            tokenizer.report(Kind::UnterminatedString, "unterminated regular expression");
+            tokenizer.tokenize_with_scope(Category::Regex, "string.regexp.js");
+            Some(StateFunction(initial_state))
+        },
+        Some(_) => {
+            tokenizer.advance();
+            Some(StateFunction(regex))
+        }
+    }
+}
+
+fn regex_flags(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    match tokenizer.current_char() {
+        Some(c) if c.is_alphabetic() => {
+            tokenizer.advance();
+            Some(StateFunction(regex_flags))
+        },
+        _ => {
+            tokenizer.tokenize_with_scope(Category::Regex, "string.regexp.js");
+            Some(StateFunction(initial_state))
+        }
+    }
+}
+
 fn integer(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     match tokenizer.current_char() {
         Some(c) => {
@@ -326,13 +522,13 @@ fn integer(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
                 tokenizer.advance();
                 Some(StateFunction(integer))
             } else {
-                tokenizer.tokenize(Category::Integer);
+                tokenizer.tokenize_with_scope(Category::Integer, "constant.numeric.integer.js");
                 Some(StateFunction(initial_state))
             }
         }
 
         None => {
-            tokenizer.tokenize(Category::Integer);
+            tokenizer.tokenize_with_scope(Category::Integer, "constant.numeric.integer.js");
             None
         }
     }
@@ -357,88 +553,125 @@ fn symbol(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     }
 }
 
+/// Lexes the data lazily, producing tokens that borrow their lexemes
+/// from the data, rather than allocating a copy for each of them.
+pub fn lex_borrowed(data: &str) -> Tokens<'_> {
+    Tokenizer::new(data).run(StateFunction(initial_state))
+}
+
+/// Lexes the data lazily, running the lexer's
+/// state functions only as tokens are requested.
+pub fn lex_iter(data: &str) -> impl Iterator<Item=Token> + '_ {
+    lex_borrowed(data).map(Token::from)
+}
+
 /// Lexes a JavaScript document.
 pub fn lex(data: &str) -> Vec<Token> {
-    let mut tokenizer = Tokenizer::new(data);
-    let mut state_function = StateFunction(initial_state);
-    loop {
-        let StateFunction(actual_function) = state_function;
-        match actual_function(&mut tokenizer) {
-            Some(f) => state_function = f,
-            None => {
-                match tokenizer.states.pop() {
-                    Some(f) => state_function = f,
-                    None => return tokenizer.tokens(),
-                }
-            }
-        }
+    lex_iter(data).collect()
+}
+
+/// Lexes a JavaScript document, reporting any malformed input it contains.
+pub fn lex_with_diagnostics(data: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+    let mut tokens = lex_borrowed(data);
+    let lexed = tokens.by_ref().map(Token::from).collect();
+
+    (lexed, tokens.into_diagnostics())
+}
+
+/// The JavaScript lexer, exposed through the common `Lexer` interface.
+pub struct JavaScriptLexer;
+
+impl Lexer for JavaScriptLexer {
+    fn name(&self) -> &str { "JavaScript" }
+    fn aliases(&self) -> &[&str] { &["javascript", "js"] }
+    fn extensions(&self) -> &[&str] { &["js", "mjs", "cjs"] }
+    fn mime_types(&self) -> &[&str] { &["application/javascript", "text/javascript"] }
+    fn filenames(&self) -> &[&str] { &["Jakefile"] }
+    fn interpreters(&self) -> &[&str] { &["node", "nodejs", "deno", "bun"] }
+
+    fn analyse(&self, data: &str) -> f32 {
+        if data.contains("\"use strict\"") || data.contains("'use strict'") { 0.4 } else { 0.0 }
     }
+
+    fn lex(&self, data: &str) -> Vec<Token> { lex(data) }
+    fn lex_with_diagnostics(&self, data: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+        lex_with_diagnostics(data)
+    }
+    fn lex_iter<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=Token> + 'a> { Box::new(lex_iter(data)) }
+    fn lex_borrowed<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=BorrowedToken<'a>> + 'a> {
+        Box::new(lex_borrowed(data))
+    }
+    fn initial_state(&self) -> Option<StateFunction> { Some(StateFunction(initial_state)) }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::lex;
-    use token::Token;
+    use super::{lex, lex_with_diagnostics};
+    use diagnostic::Kind;
     use token::Category;
 
     #[test]
     fn it_works() {
         let data = include_str!("../../test_data/data.js");
         let tokens = lex(data);
-        let expected_tokens = vec![
-            Token{ lexeme: "var".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "data".to_string(), category: Category::Identifier },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "=".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "\"string\"".to_string(), category: Category::String },
-            Token{ lexeme: ";".to_string(), category: Category::Text },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "var".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "data_2".to_string(), category: Category::Identifier },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "=".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "'string'".to_string(), category: Category::String },
-            Token{ lexeme: ";".to_string(), category: Category::Text },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "// comment".to_string(), category: Category::Comment },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "/*\n multi-line comment\n*/".to_string(), category: Category::Comment },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "function".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "myFunction".to_string(), category: Category::Function },
-            Token{ lexeme: "(".to_string(), category: Category::Text },
-            Token{ lexeme: "arg".to_string(), category: Category::Identifier },
-            Token{ lexeme: ")".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "{".to_string(), category: Category::Text },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "if".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "true".to_string(), category: Category::Boolean },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "{}".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "else".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "{".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "return".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "false".to_string(), category: Category::Boolean },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "}".to_string(), category: Category::Text },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "}".to_string(), category: Category::Text },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
+        let expected_tokens = [
+            ("var", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("data", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("\"string\"", Category::String),
+            (";", Category::Punctuation),
+            ("\n", Category::Whitespace),
+            ("var", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("data_2", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("'string'", Category::String),
+            (";", Category::Punctuation),
+            ("\n", Category::Whitespace),
+            ("// comment", Category::Comment),
+            ("\n", Category::Whitespace),
+            ("/*\n multi-line comment\n*/", Category::Comment),
+            ("\n", Category::Whitespace),
+            ("function", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("myFunction", Category::Function),
+            ("(", Category::Parenthesis),
+            ("arg", Category::Identifier),
+            (")", Category::Parenthesis),
+            (" ", Category::Whitespace),
+            ("{", Category::Brace),
+            ("\n  ", Category::Whitespace),
+            ("if", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("true", Category::Boolean),
+            (" ", Category::Whitespace),
+            ("{", Category::Brace),
+            ("}", Category::Brace),
+            (" ", Category::Whitespace),
+            ("else", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("{", Category::Brace),
+            (" ", Category::Whitespace),
+            ("return", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("false", Category::Boolean),
+            (" ", Category::Whitespace),
+            ("}", Category::Brace),
+            ("\n", Category::Whitespace),
+            ("}", Category::Brace),
+            ("\n", Category::Whitespace),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
         }
     }
 
@@ -446,16 +679,109 @@ mod tests {
     fn it_identifies_integers_and_operators() {
         let data = "123 + 456";
         let tokens = lex(data);
-        let expected_tokens = vec![
-            Token{ lexeme: "123".to_string(), category: Category::Integer },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "+".to_string(), category: Category::Operator },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "456".to_string(), category: Category::Integer },
+        let expected_tokens = [
+            ("123", Category::Integer),
+            (" ", Category::Whitespace),
+            ("+", Category::Operator),
+            (" ", Category::Whitespace),
+            ("456", Category::Integer),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
         }
     }
+
+    #[test]
+    fn it_reports_unterminated_strings_and_comments() {
+        let (tokens, diagnostics) = lex_with_diagnostics("'a';\n/* b");
+
+        assert_eq!(tokens.last().unwrap().lexeme, "/* b");
+        assert_eq!(tokens.last().unwrap().category, Category::Comment);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, Kind::UnterminatedComment);
+        assert_eq!(diagnostics[0].span.range(), 5..9);
+
+        let (_, diagnostics) = lex_with_diagnostics("x = \"a");
+        assert_eq!(diagnostics[0].kind, Kind::UnterminatedString);
+        assert_eq!(diagnostics[0].span.range(), 4..6);
+    }
+
+    #[test]
+    fn it_reports_template_literals_regexes_and_unexpected_characters() {
+        let tokens = lex("`a ${b} c`");
+        let expected_tokens = [
+            ("`a ", Category::String),
+            ("${b}", Category::Interpolation),
+            (" c`", Category::String),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+        }
+
+        let (tokens, diagnostics) = lex_with_diagnostics("a¿;");
+        assert_eq!(tokens[1].lexeme, "¿");
+        assert_eq!(tokens[1].category, Category::Error);
+        assert_eq!(diagnostics[0].kind, Kind::UnexpectedCharacter);
+        assert_eq!(diagnostics[0].span.range(), 1..3);
+
+        for (data, range) in [("x = `a ${b}", 7..11), ("x = /a", 4..6)] {
+            let (_, diagnostics) = lex_with_diagnostics(data);
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0].kind, Kind::UnterminatedString);
+            assert_eq!(diagnostics[0].span.range(), range);
+        }
+    }
+
+    #[test]
+    fn it_identifies_punctuation_doc_comments_and_regexes() {
+        let tokens = lex("/** Docs. */\nvar re = /a\\/[b]/gi;\nre.test(x, /y/);\n");
+        let expected_tokens = [
+            ("/** Docs. */", Category::DocComment),
+            ("\n", Category::Whitespace),
+            ("var", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("re", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("/a\\/[b]/gi", Category::Regex),
+            (";", Category::Punctuation),
+            ("\n", Category::Whitespace),
+            ("re", Category::Identifier),
+            (".", Category::Punctuation),
+            ("test", Category::Call),
+            ("(", Category::Parenthesis),
+            ("x", Category::Identifier),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("/y/", Category::Regex),
+            (")", Category::Parenthesis),
+            (";", Category::Punctuation),
+            ("\n", Category::Whitespace),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+        }
+    }
+
+    #[test]
+    fn it_categorizes_tokens_ending_the_data() {
+        let tokens = lex("function f");
+        assert_eq!(tokens.last().unwrap().lexeme, "f");
+        assert_eq!(tokens.last().unwrap().category, Category::Function);
+
+        let tokens = lex("// c");
+        assert_eq!(tokens.last().unwrap().lexeme, "// c");
+        assert_eq!(tokens.last().unwrap().category, Category::Comment);
+    }
 }
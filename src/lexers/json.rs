@@ -1,207 +1,230 @@
 //! A lexer for the JSON data format.
 
-use token::{Category, Token};
-use tokenizer::{Tokenizer, StateFunction};
-
-fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
-    match tokenizer.current_char() {
-        Some(c) => {
-            match c {
-                '{' => {
-                    tokenizer.tokenize_next(1, Category::Brace);
-                },
-                '[' => {
-                    tokenizer.tokenize_next(1, Category::Bracket);
-                },
-                ' ' | '\n' => {
-                    tokenizer.tokenize(Category::Text);
-                    tokenizer.advance();
-                    return Some(StateFunction(whitespace));
-                },
-                '"' => {
-                    tokenizer.tokenize(Category::Text);
-                    tokenizer.advance();
-                    return Some(StateFunction(inside_string));
-                },
-                ':' => {
-                    tokenizer.tokenize_next(1, Category::Operator);
-                },
-                '}' => {
-                    tokenizer.tokenize_next(1, Category::Brace);
-                },
-                ']' => {
-                    tokenizer.tokenize_next(1, Category::Bracket);
-                },
-                _ => {
-                    if tokenizer.has_prefix("true") {
-                        tokenizer.tokenize_next(4, Category::Boolean);
-                    } else if tokenizer.has_prefix("false") {
-                        tokenizer.tokenize_next(5, Category::Boolean);
-                    } else if tokenizer.has_prefix("null") {
-                        tokenizer.tokenize_next(4, Category::Keyword);
-                    } else {
-                        tokenizer.advance();
-                    }
-                }
-            }
-
-            Some(StateFunction(initial_state))
-        }
-
-        None => {
-            tokenizer.tokenize(Category::Text);
-            None
-        }
+use diagnostic::Diagnostic;
+use lexers::Lexer;
+use token::{BorrowedToken, Token};
+use tokenizer::{Tokenizer, StateFunction, Tokens};
+
+lexer_states! {
+    initial_state {
+        chars(' ' | '\n' | '\t' | '\r') => Whitespace;
+        literal("{") => Brace as "punctuation.section.mapping.begin.json";
+        literal("}") => Brace as "punctuation.section.mapping.end.json";
+        literal("[") => Bracket as "punctuation.section.sequence.begin.json";
+        literal("]") => Bracket as "punctuation.section.sequence.end.json";
+        literal(":") => Punctuation as "punctuation.separator.key-value.json";
+        literal(",") => Punctuation as "punctuation.separator.json";
+        literal("true", "false") => Boolean as "constant.language.boolean.json";
+        literal("null") => Constant as "constant.language.null.json";
+        regex("-?(0|[1-9][0-9]*)(\\.[0-9]+)?[eE][+-]?[0-9]+|-?(0|[1-9][0-9]*)\\.[0-9]+") =>
+            Float as "constant.numeric.float.json";
+        regex("-?(0|[1-9][0-9]*)") => Integer as "constant.numeric.integer.json";
+        literal("\"") => start, goto(inside_string);
+        regex("[^ \\n\\t\\r{}\\[\\]:,\"]+") =>
+            error(UnexpectedCharacter, "unexpected characters") as "invalid.illegal.json";
     }
-}
 
-fn inside_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
-    match tokenizer.current_char() {
-        Some(c) => {
-            match c {
-                '"' => {
-                    tokenizer.advance();
-                    tokenizer.tokenize(Category::String);
-                    Some(StateFunction(initial_state))
-                },
-                '\\' => {
-                    tokenizer.advance();
-                    tokenizer.advance();
-                    Some(StateFunction(inside_string))
-                }
-                _ => {
-                    tokenizer.advance();
-                    Some(StateFunction(inside_string))
-                }
-            }
-        }
-
-        None => {
-            tokenizer.tokenize(Category::String);
-            None
-        }
+    inside_string {
+        literal("\\\\", "\\\"") => continue;
+        literal("\"") => finish String as "string.quoted.double.json", goto(initial_state);
+        end => finish String as "string.quoted.double.json", report(UnterminatedString, "unterminated string");
     }
 }
 
-fn whitespace(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
-    match tokenizer.current_char() {
-        Some(c) => {
-            match c {
-                ' ' | '\n' => {
-                    tokenizer.advance();
-                    Some(StateFunction(whitespace))
-                },
-                _ => {
-                    tokenizer.tokenize(Category::Whitespace);
-                    Some(StateFunction(initial_state))
-                }
-            }
-        }
+/// Lexes the data lazily, producing tokens that borrow their lexemes
+/// from the data, rather than allocating a copy for each of them.
+pub fn lex_borrowed(data: &str) -> Tokens<'_> {
+    Tokenizer::new(data).run(StateFunction(initial_state))
+}
 
-        None => {
-            tokenizer.tokenize(Category::Whitespace);
-            None
-        }
-    }
+/// Lexes the data lazily, running the lexer's
+/// state functions only as tokens are requested.
+pub fn lex_iter(data: &str) -> impl Iterator<Item=Token> + '_ {
+    lex_borrowed(data).map(Token::from)
 }
 
 /// Lexes a JSON document.
 pub fn lex(data: &str) -> Vec<Token> {
-    let mut tokenizer = Tokenizer::new(data);
-    let mut state_function = StateFunction(initial_state);
-    loop {
-        let StateFunction(actual_function) = state_function;
-        match actual_function(&mut tokenizer) {
-            Some(f) => state_function = f,
-            None => return tokenizer.tokens(),
+    lex_iter(data).collect()
+}
+
+/// Lexes a JSON document, reporting any malformed input it contains.
+pub fn lex_with_diagnostics(data: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+    let mut tokens = lex_borrowed(data);
+    let lexed = tokens.by_ref().map(Token::from).collect();
+
+    (lexed, tokens.into_diagnostics())
+}
+
+/// The JSON lexer, exposed through the common `Lexer` interface.
+pub struct JsonLexer;
+
+impl Lexer for JsonLexer {
+    fn name(&self) -> &str { "JSON" }
+    fn aliases(&self) -> &[&str] { &["json"] }
+    fn extensions(&self) -> &[&str] { &["json"] }
+    fn mime_types(&self) -> &[&str] { &["application/json"] }
+    fn filenames(&self) -> &[&str] { &[".babelrc", ".jshintrc", "composer.lock"] }
+
+    fn analyse(&self, data: &str) -> f32 {
+        let data = data.trim();
+
+        if (data.starts_with('{') && data.ends_with('}')) ||
+           (data.starts_with('[') && data.ends_with(']')) {
+            0.5
+        } else if data.starts_with('{') {
+            0.3
+        } else {
+            0.0
         }
     }
+
+    fn lex(&self, data: &str) -> Vec<Token> { lex(data) }
+    fn lex_with_diagnostics(&self, data: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+        lex_with_diagnostics(data)
+    }
+    fn lex_iter<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=Token> + 'a> { Box::new(lex_iter(data)) }
+    fn lex_borrowed<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=BorrowedToken<'a>> + 'a> {
+        Box::new(lex_borrowed(data))
+    }
+    fn initial_state(&self) -> Option<StateFunction> { Some(StateFunction(initial_state)) }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::lex;
-    use token::Token;
+    use super::{lex, lex_with_diagnostics};
+    use diagnostic::Kind;
     use token::Category;
 
     #[test]
     fn it_works() {
         let data = include_str!("../../test_data/data.json");
         let tokens = lex(data);
-        let expected_tokens = vec![
-            Token{ lexeme: "{".to_string(), category: Category::Brace },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "\"key\"".to_string(), category: Category::String },
-            Token{ lexeme: ":".to_string(), category: Category::Operator },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "\"4032\"".to_string(), category: Category::String },
-            Token{ lexeme: ",".to_string(), category: Category::Text },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "'single'".to_string(), category: Category::Text },
-            Token{ lexeme: ":".to_string(), category: Category::Operator },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "'quotes\\'',".to_string(), category: Category::Text },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "\"literals\"".to_string(), category: Category::String },
-            Token{ lexeme: ":".to_string(), category: Category::Operator },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "[".to_string(), category: Category::Bracket },
-            Token{ lexeme: "\n    ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "true".to_string(), category: Category::Boolean },
-            Token{ lexeme: ",".to_string(), category: Category::Text },
-            Token{ lexeme: "\n    ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "false".to_string(), category: Category::Boolean },
-            Token{ lexeme: ",".to_string(), category: Category::Text },
-            Token{ lexeme: "\n    ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "null".to_string(), category: Category::Keyword },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "]".to_string(), category: Category::Bracket },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "}".to_string(), category: Category::Brace },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
+        let expected_tokens = [
+            ("{", Category::Brace),
+            ("\n  ", Category::Whitespace),
+            ("\"key\"", Category::String),
+            (":", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("\"4032\"", Category::String),
+            (",", Category::Punctuation),
+            ("\n  ", Category::Whitespace),
+            ("'single'", Category::Error),
+            (":", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("'quotes\\''", Category::Error),
+            (",", Category::Punctuation),
+            ("\n  ", Category::Whitespace),
+            ("\"literals\"", Category::String),
+            (":", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("[", Category::Bracket),
+            ("\n    ", Category::Whitespace),
+            ("true", Category::Boolean),
+            (",", Category::Punctuation),
+            ("\n    ", Category::Whitespace),
+            ("false", Category::Boolean),
+            (",", Category::Punctuation),
+            ("\n    ", Category::Whitespace),
+            ("null", Category::Constant),
+            ("\n  ", Category::Whitespace),
+            ("]", Category::Bracket),
+            ("\n", Category::Whitespace),
+            ("}", Category::Brace),
+            ("\n", Category::Whitespace),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
         }
     }
 
     #[test]
     fn it_can_handle_garbage() {
         let tokens = lex("} adwyx123&*_ ");
-        let expected_tokens = vec![
-            Token{ lexeme: "}".to_string(), category: Category::Brace },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "adwyx123&*_".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
+        let expected_tokens = [
+            ("}", Category::Brace),
+            (" ", Category::Whitespace),
+            ("adwyx123&*_", Category::Error),
+            (" ", Category::Whitespace),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
         }
     }
 
     #[test]
     fn it_can_handle_open_strings() {
         let tokens = lex("\"open!");
-        let expected_tokens = vec![
-            Token{ lexeme: "\"open!".to_string(), category: Category::String },
+        let expected_tokens = [
+            ("\"open!", Category::String),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
         }
     }
 
     #[test]
     fn it_can_handle_utf8_data() {
         let tokens = lex("différent");
-        let expected_tokens = vec![
-            Token{ lexeme: "différent".to_string(), category: Category::Text },
+        let expected_tokens = [
+            ("différent", Category::Error),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+        }
+    }
+
+    #[test]
+    fn it_can_handle_numbers() {
+        let tokens = lex("[0, -12, 1.5, 2e-3]");
+        let expected_tokens = [
+            ("[", Category::Bracket),
+            ("0", Category::Integer),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("-12", Category::Integer),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("1.5", Category::Float),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("2e-3", Category::Float),
+            ("]", Category::Bracket),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
         }
     }
+
+    #[test]
+    fn it_reports_malformed_input() {
+        let (tokens, diagnostics) = lex_with_diagnostics("{\"a\": nil, \"b");
+
+        assert_eq!(tokens[4].lexeme, "nil");
+        assert_eq!(tokens[4].category, Category::Error);
+        assert_eq!(tokens[7].lexeme, "\"b");
+        assert_eq!(tokens[7].category, Category::String);
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].kind, Kind::UnexpectedCharacter);
+        assert_eq!(diagnostics[0].span.range(), 6..9);
+        assert_eq!(diagnostics[1].kind, Kind::UnterminatedString);
+        assert_eq!(diagnostics[1].span.range(), 11..13);
+    }
 }
@@ -1,4 +1,22 @@
 //! Pre-built lexers for various languages/formats.
+//!
+//! Each lexer module exposes `lex` and `lex_iter` functions (the latter
+//! producing tokens lazily), as well as `lex_borrowed`, which lazily produces
+//! tokens that borrow their lexemes from the data. Most also expose
+//! `lex_with_diagnostics`, which reports malformed input alongside the tokens.
+//! Alongside these is a type implementing the common `Lexer` trait, which
+//! makes it possible to work with lexers generically, and to look them up by
+//! name, file extension, or MIME type using a `Registry`.
+//!
+//! # Examples
+//!
+//! ```
+//! let registry = luthor::lexers::registry();
+//! let lexer = registry.find_by_extension("json").unwrap();
+//!
+//! assert_eq!(lexer.name(), "JSON");
+//! assert_eq!(lexer.lex("{}").len(), 2);
+//! ```
 pub mod coffeescript;
 pub mod javascript;
 pub mod json;
@@ -7,3 +25,345 @@ pub mod ruby;
 pub mod rust;
 pub mod html_erb;
 pub mod default;
+
+use std::path::Path;
+use std::sync::OnceLock;
+use detection::{Detection, Evidence};
+use diagnostic::Diagnostic;
+use token::{BorrowedToken, Token};
+use tokenizer::StateFunction;
+
+/// A common interface to lexers, describing the language/format they
+/// handle, as well as providing a means to lex data.
+pub trait Lexer {
+    /// A human-readable name for the language/format (e.g. "Ruby").
+    fn name(&self) -> &str;
+
+    /// Alternative, lowercase names used to refer to the language/format.
+    fn aliases(&self) -> &[&str] { &[] }
+
+    /// File extensions, excluding the leading period (e.g. "rb" or "html.erb").
+    fn extensions(&self) -> &[&str] { &[] }
+
+    /// MIME types associated with the language/format.
+    fn mime_types(&self) -> &[&str] { &[] }
+
+    /// Well-known file names used for the language/format (e.g. "Gemfile").
+    fn filenames(&self) -> &[&str] { &[] }
+
+    /// Interpreters named on `#!` lines of scripts in the language, without
+    /// their paths or version numbers (e.g. "ruby").
+    fn interpreters(&self) -> &[&str] { &[] }
+
+    /// Analyses the data, returning a confidence between 0 and 1 that it is
+    /// written in the language/format. Used to detect languages when other
+    /// evidence (file names, modelines, etc.) is unavailable.
+    fn analyse(&self, _data: &str) -> f32 { 0.0 }
+
+    /// Lexes the data, producing tokens whose lexemes join to form the data.
+    fn lex(&self, data: &str) -> Vec<Token>;
+
+    /// Lexes the data, additionally reporting any malformed input it
+    /// contains, such as unterminated strings. Defaults to reporting nothing.
+    fn lex_with_diagnostics(&self, data: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+        (self.lex(data), Vec::new())
+    }
+
+    /// Lexes the data lazily, producing tokens as they're requested.
+    /// Defaults to lexing all of the data up front.
+    fn lex_iter<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=Token> + 'a> {
+        Box::new(self.lex(data).into_iter())
+    }
+
+    /// Lexes the data lazily, producing tokens that borrow their lexemes from
+    /// the data. Defaults to lexing all of the data up front, borrowing each
+    /// lexeme using its token's span.
+    fn lex_borrowed<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=BorrowedToken<'a>> + 'a> {
+        Box::new(self.lex(data).into_iter().map(move |token| {
+            BorrowedToken{
+                lexeme: &data[token.span.range()],
+                category: token.category,
+                span: token.span,
+                scope: token.scope,
+            }
+        }))
+    }
+
+    /// The state function with which lexing begins, for lexers built using
+    /// `Tokenizer` state functions. Providing one allows lexing to resume part
+    /// way through the data (see the `incremental` module); lexers without
+    /// one are always run in full.
+    fn initial_state(&self) -> Option<StateFunction> { None }
+}
+
+/// A collection of lexers that can be looked up by name, file extension, or
+/// MIME type. Lexers registered later take precedence over earlier ones,
+/// which allows third-party lexers to replace those included with luthor.
+#[derive(Default)]
+pub struct Registry {
+    lexers: Vec<Box<dyn Lexer + Send + Sync>>,
+}
+
+impl Registry {
+    /// Creates an empty registry. See the `registry` function
+    /// for one pre-populated with luthor's lexers.
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    /// Adds a lexer to the registry. Lexers must be thread-safe,
+    /// so that registries can be shared (see `shared_registry`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use luthor::lexers::{self, Lexer};
+    /// use luthor::token::Token;
+    ///
+    /// struct Markdown;
+    ///
+    /// impl Lexer for Markdown {
+    ///     fn name(&self) -> &str { "Markdown" }
+    ///     fn extensions(&self) -> &[&str] { &["md"] }
+    ///     fn lex(&self, data: &str) -> Vec<Token> { lexers::default::lex(data) }
+    /// }
+    ///
+    /// let mut registry = lexers::registry();
+    /// registry.register(Markdown);
+    ///
+    /// assert_eq!(registry.find_by_extension("md").unwrap().name(), "Markdown");
+    /// ```
+    pub fn register<T: Lexer + Send + Sync + 'static>(&mut self, lexer: T) {
+        self.lexers.push(Box::new(lexer));
+    }
+
+    /// Iterates over the registered lexers, in the order they were registered.
+    pub fn lexers(&self) -> impl Iterator<Item = &dyn Lexer> {
+        self.lexers.iter().map(|lexer| lexer.as_ref() as &dyn Lexer)
+    }
+
+    /// Finds a lexer using its name or one of its aliases, ignoring case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let registry = luthor::lexers::registry();
+    ///
+    /// assert_eq!(registry.find_by_name("ruby").unwrap().name(), "Ruby");
+    /// assert_eq!(registry.find_by_name("rb").unwrap().name(), "Ruby");
+    /// ```
+    pub fn find_by_name(&self, name: &str) -> Option<&dyn Lexer> {
+        self.find(|lexer| {
+            lexer.name().eq_ignore_ascii_case(name) ||
+            lexer.aliases().iter().any(|alias| alias.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// Finds a lexer using a file extension, ignoring case
+    /// and with or without its leading period.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let registry = luthor::lexers::registry();
+    ///
+    /// assert_eq!(registry.find_by_extension(".rs").unwrap().name(), "Rust");
+    /// assert_eq!(registry.find_by_extension("html.erb").unwrap().name(), "HTML+ERB");
+    /// ```
+    pub fn find_by_extension(&self, extension: &str) -> Option<&dyn Lexer> {
+        let extension = extension.trim_start_matches('.');
+
+        self.find(|lexer| {
+            lexer.extensions().iter().any(|e| e.eq_ignore_ascii_case(extension))
+        })
+    }
+
+    /// Finds a lexer using a MIME type, ignoring case and any parameters.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let registry = luthor::lexers::registry();
+    /// let lexer = registry.find_by_mime_type("application/json; charset=utf-8");
+    ///
+    /// assert_eq!(lexer.unwrap().name(), "JSON");
+    /// ```
+    pub fn find_by_mime_type(&self, mime_type: &str) -> Option<&dyn Lexer> {
+        let mime_type = mime_type.split(';').next().unwrap_or("").trim();
+
+        self.find(|lexer| {
+            lexer.mime_types().iter().any(|m| m.eq_ignore_ascii_case(mime_type))
+        })
+    }
+
+    /// Chooses the lexer best suited to the data, using its path (if known)
+    /// and content. Returns nothing if there's no evidence for any lexer.
+    /// See the `detection` module for details on the evidence considered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::path::Path;
+    ///
+    /// let registry = luthor::lexers::registry();
+    /// let detection = registry.detect(Some(Path::new("Rakefile")), "task :default").unwrap();
+    ///
+    /// assert_eq!(detection.lexer.name(), "Ruby");
+    /// assert_eq!(detection.confidence, 1.0);
+    /// ```
+    pub fn detect(&self, path: Option<&Path>, content: &str) -> Option<Detection<'_>> {
+        let evidence = Evidence::gather(path, content);
+        let mut best: Option<Detection> = None;
+
+        // Ties go to the most recently registered lexer.
+        for lexer in self.lexers.iter().rev() {
+            let confidence = evidence.confidence(lexer.as_ref());
+            if confidence > best.as_ref().map_or(0.0, |detection| detection.confidence) {
+                best = Some(Detection{ lexer: lexer.as_ref(), confidence });
+            }
+        }
+
+        best
+    }
+
+    // Finds the most recently registered lexer satisfying the predicate.
+    fn find<P: Fn(&dyn Lexer) -> bool>(&self, predicate: P) -> Option<&dyn Lexer> {
+        self.lexers.iter().rev().map(|lexer| lexer.as_ref() as &dyn Lexer).find(|lexer| predicate(*lexer))
+    }
+}
+
+/// Creates a registry containing all of the lexers included with luthor.
+pub fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register(default::DefaultLexer);
+    registry.register(coffeescript::CoffeeScriptLexer);
+    registry.register(javascript::JavaScriptLexer);
+    registry.register(json::JsonLexer);
+    registry.register(xml::XmlLexer);
+    registry.register(ruby::RubyLexer);
+    registry.register(rust::RustLexer);
+    registry.register(html_erb::HtmlErbLexer);
+
+    registry
+}
+
+/// A registry containing luthor's lexers (see `registry`), which is
+/// built once and shared, rather than being built for each use.
+pub fn shared_registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+    REGISTRY.get_or_init(registry)
+}
+
+// Whether a character can't appear in code outside of strings and comments
+// (e.g. `¿`), in which case lexers report it and tokenize it as an error.
+pub(crate) fn is_unexpected(c: char) -> bool {
+    !(c.is_alphanumeric() || c.is_whitespace() || c.is_ascii_punctuation())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{registry, Lexer, Registry};
+    use token::Token;
+
+    struct Replacement;
+
+    impl Lexer for Replacement {
+        fn name(&self) -> &str { "Replacement" }
+        fn aliases(&self) -> &[&str] { &["json"] }
+        fn lex(&self, _: &str) -> Vec<Token> { vec![] }
+    }
+
+    #[test]
+    fn new_registries_are_empty() {
+        assert_eq!(Registry::new().lexers().count(), 0);
+    }
+
+    #[test]
+    fn registry_includes_every_lexer() {
+        let registry = registry();
+        let names: Vec<&str> = registry.lexers().map(|lexer| lexer.name()).collect();
+
+        assert_eq!(
+            names,
+            vec!["Text", "CoffeeScript", "JavaScript", "JSON", "XML", "Ruby", "Rust", "HTML+ERB"]
+        );
+    }
+
+    #[test]
+    fn lex_iter_produces_the_same_tokens_as_lex() {
+        let samples = [
+            ("Ruby", include_str!("../../test_data/ruby.rb")),
+            ("Rust", include_str!("../../test_data/rust.rs")),
+            ("JSON", include_str!("../../test_data/data.json")),
+            ("XML", include_str!("../../test_data/data.xml")),
+            ("JavaScript", include_str!("../../test_data/data.js")),
+            ("CoffeeScript", include_str!("../../test_data/data.coffee")),
+            ("Text", include_str!("../../test_data/data.txt")),
+            ("HTML+ERB", include_str!("../../test_data/html_erb.html.erb")),
+        ];
+        let registry = registry();
+
+        for &(name, data) in samples.iter() {
+            let lexer = registry.find_by_name(name).unwrap();
+            let tokens: Vec<Token> = lexer.lex_iter(data).collect();
+
+            assert_eq!(tokens, lexer.lex(data));
+
+            let tokens: Vec<Token> = lexer.lex_borrowed(data).map(Token::from).collect();
+            assert_eq!(tokens, lexer.lex(data));
+        }
+    }
+
+    #[test]
+    fn lex_with_diagnostics_defaults_to_reporting_nothing() {
+        let (tokens, diagnostics) = Replacement.lex_with_diagnostics("\"open");
+
+        assert_eq!(tokens, Replacement.lex("\"open"));
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn lex_iter_defaults_to_lex() {
+        assert_eq!(Replacement.lex_iter("data").count(), 0);
+    }
+
+    #[test]
+    fn lex_borrowed_defaults_to_borrowing_lexed_spans() {
+        struct Words;
+
+        impl Lexer for Words {
+            fn name(&self) -> &str { "Words" }
+            fn lex(&self, data: &str) -> Vec<Token> { super::default::lex(data) }
+        }
+
+        let lexemes: Vec<&str> = Words.lex_borrowed("lex luthor").map(|token| token.lexeme).collect();
+        assert_eq!(lexemes, ["lex", " ", "luthor"]);
+    }
+
+    #[test]
+    fn find_by_name_ignores_case() {
+        assert_eq!(registry().find_by_name("JavaScript").unwrap().name(), "JavaScript");
+        assert_eq!(registry().find_by_name("javascript").unwrap().name(), "JavaScript");
+        assert_eq!(registry().find_by_name("JS").unwrap().name(), "JavaScript");
+    }
+
+    #[test]
+    fn find_by_extension_handles_unknown_extensions() {
+        assert!(registry().find_by_extension("unknown").is_none());
+    }
+
+    #[test]
+    fn find_by_mime_type_uses_the_essence_of_the_type() {
+        assert_eq!(registry().find_by_mime_type("Text/X-Ruby ").unwrap().name(), "Ruby");
+    }
+
+    #[test]
+    fn registered_lexers_take_precedence() {
+        let mut registry = registry();
+        registry.register(Replacement);
+
+        assert_eq!(registry.find_by_name("json").unwrap().name(), "Replacement");
+        assert_eq!(registry.find_by_extension("json").unwrap().name(), "JSON");
+    }
+}
@@ -1,145 +1,205 @@
 //! A lexer for the Ruby programming language.
 
-use token::{Category, Token};
-use tokenizer::{Tokenizer, StateFunction};
+use diagnostic::{Diagnostic, Kind};
+use lexers::{is_unexpected, Lexer};
+use token::{BorrowedToken, Category, Token};
+use tokenizer::{Tokenizer, StateFunction, Tokens};
 
 fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
-    if tokenizer.starts_with_lexeme("class") {
-        tokenizer.tokenize_next(5, Category::Keyword);
-        tokenizer.states.push(StateFunction(identifier));
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme("module") {
-        tokenizer.tokenize_next(6, Category::Keyword);
-        tokenizer.states.push(StateFunction(identifier));
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme("include") {
-        tokenizer.tokenize_next(7, Category::Keyword);
-        tokenizer.states.push(StateFunction(identifier));
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme("extend") {
-        tokenizer.tokenize_next(6, Category::Keyword);
-        tokenizer.states.push(StateFunction(identifier));
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme("def") {
-        tokenizer.tokenize_next(3, Category::Keyword);
+    for (keyword, scope) in [
+        ("class", "keyword.control.class.ruby"),
+        ("module", "keyword.control.module.ruby"),
+        ("include", "keyword.other.special-method.ruby"),
+        ("extend", "keyword.other.special-method.ruby"),
+    ] {
+        if tokenizer.starts_with_lexeme(keyword) {
+            tokenizer.tokenize_next_with_scope(keyword.chars().count(), Category::Keyword, scope);
+            if keyword == "class" || keyword == "module" {
+                tokenizer.states.push(StateFunction(type_name));
+            } else {
+                tokenizer.states.push(StateFunction(constant));
+            }
+            return Some(StateFunction(whitespace))
+        }
+    }
+
+    if tokenizer.starts_with_lexeme("def") {
+        tokenizer.tokenize_next_with_scope(3, Category::Keyword, "keyword.control.def.ruby");
         tokenizer.states.push(StateFunction(method));
         return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme("do") {
-        tokenizer.tokenize_next(2, Category::Keyword);
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme("if") {
-        tokenizer.tokenize_next(2, Category::Keyword);
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme("unless") {
-        tokenizer.tokenize_next(6, Category::Keyword);
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme("elsif") {
-        tokenizer.tokenize_next(5, Category::Keyword);
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme("else") {
-        tokenizer.tokenize_next(4, Category::Keyword);
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme("end") {
-        tokenizer.tokenize_next(3, Category::Keyword);
-        return Some(StateFunction(initial_state))
-    } else if tokenizer.starts_with_lexeme("true") {
-        tokenizer.tokenize_next(4, Category::Boolean);
-        return Some(StateFunction(initial_state))
-    } else if tokenizer.starts_with_lexeme("false") {
-        tokenizer.tokenize_next(5, Category::Boolean);
-        return Some(StateFunction(initial_state))
-    } else if tokenizer.starts_with_lexeme("nil") {
-        tokenizer.tokenize_next(3, Category::Literal);
-        return Some(StateFunction(initial_state))
-    } else if tokenizer.starts_with_lexeme("private") {
-        tokenizer.tokenize_next(7, Category::Keyword);
-        return Some(StateFunction(initial_state))
-    } else if tokenizer.starts_with_lexeme("begin") {
-        tokenizer.tokenize_next(5, Category::Keyword);
-        return Some(StateFunction(initial_state))
-    } else if tokenizer.starts_with_lexeme("rescue") {
-        tokenizer.tokenize_next(6, Category::Keyword);
-        return Some(StateFunction(initial_state))
-    } else if tokenizer.starts_with_lexeme("raise") {
-        tokenizer.tokenize_next(5, Category::Keyword);
+    }
+
+    for keyword in ["do", "if", "unless", "elsif", "else"] {
+        if tokenizer.starts_with_lexeme(keyword) {
+            tokenizer.tokenize_next_with_scope(keyword.chars().count(), Category::Keyword, "keyword.control.ruby");
+            return Some(StateFunction(whitespace))
+        }
+    }
+
+    for (keyword, scope) in [
+        ("end", "keyword.control.ruby"),
+        ("private", "keyword.other.special-method.ruby"),
+        ("begin", "keyword.control.ruby"),
+        ("rescue", "keyword.control.ruby"),
+        ("raise", "keyword.other.special-method.ruby"),
+    ] {
+        if tokenizer.starts_with_lexeme(keyword) {
+            tokenizer.tokenize_next_with_scope(keyword.chars().count(), Category::Keyword, scope);
+            return Some(StateFunction(initial_state))
+        }
+    }
+
+    for boolean in ["true", "false"] {
+        if tokenizer.starts_with_lexeme(boolean) {
+            tokenizer.tokenize_next_with_scope(boolean.chars().count(), Category::Boolean, "constant.language.boolean.ruby");
+            return Some(StateFunction(initial_state))
+        }
+    }
+
+    if tokenizer.starts_with_lexeme("nil") {
+        tokenizer.tokenize_next_with_scope(3, Category::Constant, "constant.language.nil.ruby");
         return Some(StateFunction(initial_state))
     }
 
+    if tokenizer.position().column == 0 && tokenizer.has_prefix("=begin") {
+        tokenizer.advance_bytes(6);
+        return Some(StateFunction(embedded_document))
+    }
+
+    // Heredocs (e.g. `<<~SQL`) begin on the following line, so their
+    // terminator is kept in user state until the end of this one.
+    if !tokenizer.has_pending_data() {
+        if let Some(length) = tokenizer.match_regex(regex!(r#"<<[~-]?([A-Z_][A-Za-z0-9_]*|'[^'\n]+'|"[^"\n]+")"#)) {
+            let end = tokenizer.position().offset + length;
+            let mut opener = String::new();
+            while tokenizer.position().offset < end {
+                opener.extend(tokenizer.current_char());
+                tokenizer.advance();
+            }
+
+            let terminator = opener[2..].trim_start_matches(['~', '-']).trim_matches(['\'', '"']);
+            tokenizer.set_user_state(terminator.to_string());
+            tokenizer.tokenize_with_scope(Category::String, "string.unquoted.heredoc.ruby");
+            return Some(StateFunction(initial_state))
+        }
+    }
+
     match tokenizer.current_char() {
         Some('"') => {
-            tokenizer.tokenize(Category::Text);
+            tokenizer.tokenize(Category::Identifier);
             tokenizer.advance();
             Some(StateFunction(inside_string))
         },
         Some('\'') => {
-            tokenizer.tokenize(Category::Text);
+            tokenizer.tokenize(Category::Identifier);
             tokenizer.advance();
             Some(StateFunction(inside_single_quote_string))
         },
+        Some('`') => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.advance();
+            Some(StateFunction(inside_backtick_string))
+        },
         Some('#') => {
-            tokenizer.tokenize(Category::Text);
+            tokenizer.tokenize(Category::Identifier);
             tokenizer.advance();
             tokenizer.states.push(StateFunction(initial_state));
             Some(StateFunction(comment))
         },
         Some('|') => {
-            tokenizer.tokenize_next(1, Category::Text);
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Punctuation);
             tokenizer.states.push(StateFunction(argument));
             Some(StateFunction(whitespace))
         },
-        Some('.') => {
-            tokenizer.tokenize_next(1, Category::Text);
+        Some('.') | Some(';') | Some(',') => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Punctuation);
             Some(StateFunction(initial_state))
         },
         Some('(') => {
             tokenizer.tokenize(Category::Call);
-            tokenizer.tokenize_next(1, Category::Text);
+            tokenizer.tokenize_next(1, Category::Parenthesis);
             Some(StateFunction(argument))
         },
+        Some(')') => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Parenthesis);
+            Some(StateFunction(initial_state))
+        },
         Some('+') => {
-            tokenizer.tokenize_next(1, Category::Operator);
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next_with_scope(1, Category::Operator, "keyword.operator.arithmetic.ruby");
             Some(StateFunction(initial_state))
         },
+        Some('\n') if !tokenizer.user_state::<String>().is_empty() => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Whitespace);
+            Some(StateFunction(heredoc))
+        },
         Some(' ') | Some('\n') => {
-            match tokenizer.next_non_whitespace_char() {
-                Some('=') => {
-                    tokenizer.tokenize(Category::Identifier);
-                    tokenizer.consume_whitespace();
-                    tokenizer.tokenize_next(1, Category::Text);
-                },
-                _ => tokenizer.consume_whitespace(),
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.consume_whitespace();
+            Some(StateFunction(initial_state))
+        },
+        Some('=') | Some('~') => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Operator);
+            tokenizer.consume_whitespace();
+
+            // A slash can only begin a regular expression where
+            // a value is expected, rather than a division.
+            if tokenizer.has_prefix("/") {
+                tokenizer.advance();
+                return Some(StateFunction(regex))
             }
             Some(StateFunction(initial_state))
         },
-        Some('=') => {
+        Some('-') | Some('*') | Some('/') | Some('%') | Some('&') |
+        Some('<') | Some('>') | Some('!') | Some('^') => {
             tokenizer.tokenize(Category::Identifier);
-            tokenizer.tokenize_next(1, Category::Text);
+            tokenizer.tokenize_next(1, Category::Operator);
             Some(StateFunction(initial_state))
         },
         Some('@') => {
-            tokenizer.tokenize(Category::Text);
+            tokenizer.tokenize(Category::Identifier);
             Some(StateFunction(identifier))
         },
-        Some('[') => {
+        Some('[') | Some(']') => {
             tokenizer.tokenize(Category::Identifier);
-            tokenizer.tokenize_next(1, Category::Text);
+            tokenizer.tokenize_next(1, Category::Bracket);
             Some(StateFunction(initial_state))
         },
-        Some(']') => {
-            tokenizer.tokenize_next(1, Category::Text);
+        Some('{') | Some('}') => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Brace);
             Some(StateFunction(initial_state))
         },
         Some(':') => {
             if tokenizer.has_prefix(": ") {
-                tokenizer.tokenize(Category::Literal);
-                tokenizer.tokenize_next(1, Category::Text);
+                tokenizer.tokenize_with_scope(Category::Literal, "constant.other.symbol.hashkey.ruby");
+                tokenizer.tokenize_next(1, Category::Punctuation);
                 tokenizer.consume_whitespace();
                 Some(StateFunction(initial_state))
+            } else if tokenizer.has_prefix("::") {
+                tokenizer.tokenize(Category::Identifier);
+                tokenizer.tokenize_next(2, Category::Punctuation);
+                Some(StateFunction(initial_state))
             } else {
                 tokenizer.advance();
                 Some(StateFunction(symbol))
             }
         },
+        Some(c) if c.is_uppercase() && !tokenizer.has_pending_data() => {
+            Some(StateFunction(constant))
+        },
+        Some(c) if is_unexpected(c) => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.advance();
+            tokenizer.report(Kind::UnexpectedCharacter, "unexpected character");
+            tokenizer.tokenize_with_scope(Category::Error, "invalid.illegal.character.ruby");
+            Some(StateFunction(initial_state))
+        },
         Some(c) => {
             tokenizer.advance();
 
@@ -151,7 +211,7 @@ fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         }
 
         None => {
-            tokenizer.tokenize(Category::Text);
+            tokenizer.tokenize(Category::Identifier);
             None
         }
     }
@@ -164,7 +224,7 @@ fn inside_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
             match c {
                 '"' => {
                     tokenizer.advance();
-                    tokenizer.tokenize(Category::String);
+                    tokenizer.tokenize_with_scope(Category::String, "string.quoted.double.ruby");
                     Some(StateFunction(initial_state))
                 },
                 '\\' => {
@@ -172,6 +232,10 @@ fn inside_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
                     tokenizer.advance();
                     Some(StateFunction(inside_string))
                 }
+                '#' if tokenizer.has_prefix("#{") => {
+                    tokenizer.tokenize_with_scope(Category::String, "string.quoted.double.ruby");
+                    Some(StateFunction(interpolation))
+                }
                 _ => {
                     tokenizer.advance();
                     Some(StateFunction(inside_string))
@@ -180,7 +244,34 @@ fn inside_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         }
 
         None => {
-            tokenizer.tokenize(Category::String);
+            tokenizer.report(Kind::UnterminatedString, "unterminated string");
+            tokenizer.tokenize_with_scope(Category::String, "string.quoted.double.ruby");
+            None
+        }
+    }
+}
+
+fn interpolation(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    match tokenizer.current_char() {
+        Some('}') => {
+            tokenizer.advance();
+
+            // Strings ending with an interpolation are reported with it, as nothing else remains.
+            if tokenizer.current_char().is_none() {
+                tokenizer.report(Kind::UnterminatedString, "unterminated string");
+                tokenizer.tokenize_with_scope(Category::Interpolation, "meta.embedded.line.ruby");
+                return None
+            }
+            tokenizer.tokenize_with_scope(Category::Interpolation, "meta.embedded.line.ruby");
+            Some(StateFunction(inside_string))
+        },
+        Some(_) => {
+            tokenizer.advance();
+            Some(StateFunction(interpolation))
+        },
+        None => {
+            tokenizer.report(Kind::UnterminatedString, "unterminated string");
+            tokenizer.tokenize_with_scope(Category::Interpolation, "meta.embedded.line.ruby");
             None
         }
     }
@@ -192,7 +283,7 @@ fn inside_single_quote_string(tokenizer: &mut Tokenizer) -> Option<StateFunction
             match c {
                 '\'' => {
                     tokenizer.advance();
-                    tokenizer.tokenize(Category::String);
+                    tokenizer.tokenize_with_scope(Category::String, "string.quoted.single.ruby");
                     Some(StateFunction(initial_state))
                 },
                 '\\' => {
@@ -208,7 +299,95 @@ fn inside_single_quote_string(tokenizer: &mut Tokenizer) -> Option<StateFunction
         }
 
         None => {
-            tokenizer.tokenize(Category::String);
+            tokenizer.report(Kind::UnterminatedString, "unterminated string");
+            tokenizer.tokenize_with_scope(Category::String, "string.quoted.single.ruby");
+            None
+        }
+    }
+}
+
+fn inside_backtick_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    match tokenizer.current_char() {
+        Some('`') => {
+            tokenizer.advance();
+            tokenizer.tokenize_with_scope(Category::String, "string.interpolated.ruby");
+            Some(StateFunction(initial_state))
+        },
+        Some('\\') => {
+            tokenizer.advance();
+            tokenizer.advance();
+            Some(StateFunction(inside_backtick_string))
+        },
+        Some(_) => {
+            tokenizer.advance();
+            Some(StateFunction(inside_backtick_string))
+        },
+        None => {
+            tokenizer.report(Kind::UnterminatedString, "unterminated string");
+            tokenizer.tokenize_with_scope(Category::String, "string.interpolated.ruby");
+            None
+        }
+    }
+}
+
+// The start of one of a heredoc's lines, which ends
+// the heredoc if it contains only the terminator.
+fn heredoc(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    if let Some(indentation) = tokenizer.match_chars(|c| c == ' ' || c == '\t') {
+        tokenizer.advance_bytes(indentation);
+    }
+
+    let terminator = tokenizer.user_state::<String>().clone();
+    if tokenizer.has_prefix(&terminator) {
+        tokenizer.advance_bytes(terminator.len());
+
+        if matches!(tokenizer.current_char(), Some('\n') | None) {
+            tokenizer.tokenize_with_scope(Category::String, "string.unquoted.heredoc.ruby");
+            tokenizer.set_user_state(String::new());
+            return Some(StateFunction(initial_state))
+        }
+    }
+
+    Some(StateFunction(heredoc_line))
+}
+
+fn heredoc_line(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    match tokenizer.current_char() {
+        Some('\n') => {
+            tokenizer.advance();
+            Some(StateFunction(heredoc))
+        },
+        Some(_) => {
+            tokenizer.advance();
+            Some(StateFunction(heredoc_line))
+        },
+        None => {
+            tokenizer.report(Kind::UnterminatedString, "unterminated heredoc");
+            tokenizer.tokenize_with_scope(Category::String, "string.unquoted.heredoc.ruby");
+            None
+        }
+    }
+}
+
+// Embedded documentation, running from a line beginning with `=begin`
+// to the end of one beginning with `=end`.
+fn embedded_document(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    if tokenizer.position().column == 0 && tokenizer.has_prefix("=end") {
+        if let Some(length) = tokenizer.match_chars(|c| c != '\n') {
+            tokenizer.advance_bytes(length);
+        }
+        tokenizer.tokenize_with_scope(Category::Comment, "comment.block.documentation.ruby");
+        return Some(StateFunction(initial_state))
+    }
+
+    match tokenizer.current_char() {
+        Some(_) => {
+            tokenizer.advance();
+            Some(StateFunction(embedded_document))
+        },
+        None => {
+            tokenizer.report(Kind::UnterminatedComment, "unterminated comment");
+            tokenizer.tokenize_with_scope(Category::Comment, "comment.block.documentation.ruby");
             None
         }
     }
@@ -241,10 +420,10 @@ fn whitespace(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
 
 fn argument(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     if tokenizer.starts_with_lexeme("true") {
-        tokenizer.tokenize_next(4, Category::Boolean);
+        tokenizer.tokenize_next_with_scope(4, Category::Boolean, "constant.language.boolean.ruby");
         return Some(StateFunction(argument))
     } else if tokenizer.starts_with_lexeme("false") {
-        tokenizer.tokenize_next(5, Category::Boolean);
+        tokenizer.tokenize_next_with_scope(5, Category::Boolean, "constant.language.boolean.ruby");
         return Some(StateFunction(argument))
     }
 
@@ -252,12 +431,12 @@ fn argument(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         Some(c) => {
             match c {
                 '"' => {
-                    tokenizer.tokenize(Category::Text);
+                    tokenizer.tokenize(Category::Identifier);
                     tokenizer.advance();
                     Some(StateFunction(inside_string))
                 },
                 '\'' => {
-                    tokenizer.tokenize(Category::Text);
+                    tokenizer.tokenize(Category::Identifier);
                     tokenizer.advance();
                     Some(StateFunction(inside_single_quote_string))
                 },
@@ -266,16 +445,30 @@ fn argument(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
                     tokenizer.states.push(StateFunction(argument));
                     Some(StateFunction(whitespace))
                 },
-                '|' | ')' => {
+                '|' => {
                     tokenizer.tokenize(Category::Identifier);
-                    tokenizer.tokenize_next(1, Category::Text);
+                    tokenizer.tokenize_next(1, Category::Punctuation);
                     Some(StateFunction(initial_state))
                 },
-                '=' | ',' => {
+                ')' => {
+                    tokenizer.tokenize(Category::Identifier);
+                    tokenizer.tokenize_next(1, Category::Parenthesis);
+                    Some(StateFunction(initial_state))
+                },
+                '=' => {
+                    tokenizer.tokenize(Category::Identifier);
+                    tokenizer.tokenize_next(1, Category::Operator);
+                    Some(StateFunction(argument))
+                },
+                ',' => {
                     tokenizer.tokenize(Category::Identifier);
-                    tokenizer.tokenize_next(1, Category::Text);
+                    tokenizer.tokenize_next(1, Category::Punctuation);
                     Some(StateFunction(argument))
                 },
+                _ if is_unexpected(c) || !(tokenizer.has_pending_data() || c.is_alphabetic() || c == '_' || c == '@') => {
+                    // Literals, operators and unexpected characters are handled by the initial state.
+                    Some(StateFunction(initial_state))
+                },
                 _ => {
                     tokenizer.advance();
                     Some(StateFunction(argument))
@@ -298,9 +491,28 @@ fn identifier(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
                     tokenizer.tokenize(Category::Identifier);
                     Some(StateFunction(initial_state))
                 },
-                '|' | '(' | ')' | '-' | ';' | '{' | '}' => {
+                _ if is_unexpected(c) => {
+                    tokenizer.tokenize(Category::Identifier);
+                    Some(StateFunction(initial_state))
+                },
+                '|' | ';' => {
+                    tokenizer.tokenize(Category::Identifier);
+                    tokenizer.tokenize_next(1, Category::Punctuation);
+                    Some(StateFunction(initial_state))
+                },
+                '(' | ')' => {
+                    tokenizer.tokenize(Category::Identifier);
+                    tokenizer.tokenize_next(1, Category::Parenthesis);
+                    Some(StateFunction(initial_state))
+                },
+                '{' | '}' => {
+                    tokenizer.tokenize(Category::Identifier);
+                    tokenizer.tokenize_next(1, Category::Brace);
+                    Some(StateFunction(initial_state))
+                },
+                '-' => {
                     tokenizer.tokenize(Category::Identifier);
-                    tokenizer.tokenize_next(1, Category::Text);
+                    tokenizer.tokenize_next(1, Category::Operator);
                     Some(StateFunction(initial_state))
                 },
                 _ => {
@@ -317,17 +529,56 @@ fn identifier(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     }
 }
 
+fn constant(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    match tokenizer.current_char() {
+        Some(c) if c.is_alphanumeric() || c == '_' => {
+            tokenizer.advance();
+            Some(StateFunction(constant))
+        },
+        Some(_) => {
+            if tokenizer.has_prefix("::") {
+                tokenizer.tokenize(Category::Namespace);
+                tokenizer.tokenize_next(2, Category::Punctuation);
+            } else {
+                tokenizer.tokenize_with_scope(Category::Constant, "variable.other.constant.ruby");
+            }
+            Some(StateFunction(initial_state))
+        },
+        None => {
+            tokenizer.tokenize_with_scope(Category::Constant, "variable.other.constant.ruby");
+            None
+        }
+    }
+}
+
+fn type_name(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    match tokenizer.current_char() {
+        Some(c) if c.is_alphanumeric() || c == '_' || c == ':' => {
+            tokenizer.advance();
+            Some(StateFunction(type_name))
+        },
+        Some(_) => {
+            tokenizer.tokenize_with_scope(Category::Type, "entity.name.type.ruby");
+            Some(StateFunction(initial_state))
+        },
+        None => {
+            tokenizer.tokenize_with_scope(Category::Type, "entity.name.type.ruby");
+            None
+        }
+    }
+}
+
 fn method(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     match tokenizer.current_char() {
         Some(c) => {
             match c {
                 ' ' | '\n' => {
-                    tokenizer.tokenize(Category::Method);
+                    tokenizer.tokenize_with_scope(Category::Method, "entity.name.function.ruby");
                     Some(StateFunction(initial_state))
                 },
                 '(' => {
-                    tokenizer.tokenize(Category::Method);
-                    tokenizer.tokenize_next(1, Category::Text);
+                    tokenizer.tokenize_with_scope(Category::Method, "entity.name.function.ruby");
+                    tokenizer.tokenize_next(1, Category::Parenthesis);
                     Some(StateFunction(argument))
                 },
                 _ => {
@@ -338,7 +589,7 @@ fn method(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         }
 
         None => {
-            tokenizer.tokenize(Category::Whitespace);
+            tokenizer.tokenize_with_scope(Category::Method, "entity.name.function.ruby");
             None
         }
     }
@@ -349,7 +600,7 @@ fn comment(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         Some(c) => {
             match c {
                 '\n' => {
-                    tokenizer.tokenize(Category::Comment);
+                    tokenizer.tokenize_with_scope(Category::Comment, "comment.line.number-sign.ruby");
                     Some(StateFunction(initial_state))
                 },
                 _ => {
@@ -360,12 +611,48 @@ fn comment(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         }
 
         None => {
-            tokenizer.tokenize(Category::Whitespace);
+            tokenizer.tokenize_with_scope(Category::Comment, "comment.line.number-sign.ruby");
             None
         }
     }
 }
 
+fn regex(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    match tokenizer.current_char() {
+        Some('/') => {
+            tokenizer.advance();
+            Some(StateFunction(regex_options))
+        },
+        Some('\\') => {
+            tokenizer.advance();
+            tokenizer.advance();
+            Some(StateFunction(regex))
+        },
+        Some('\n') | None => {
+            tokenizer.report(Kind::UnterminatedString, "unterminated regular expression");
+            tokenizer.tokenize_with_scope(Category::Regex, "string.regexp.ruby");
+            Some(StateFunction(initial_state))
+        },
+        Some(_) => {
+            tokenizer.advance();
+            Some(StateFunction(regex))
+        }
+    }
+}
+
+fn regex_options(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    match tokenizer.current_char() {
+        Some(c) if c.is_alphabetic() => {
+            tokenizer.advance();
+            Some(StateFunction(regex_options))
+        },
+        _ => {
+            tokenizer.tokenize_with_scope(Category::Regex, "string.regexp.ruby");
+            Some(StateFunction(initial_state))
+        }
+    }
+}
+
 fn integer(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     match tokenizer.current_char() {
         Some(c) => {
@@ -373,13 +660,13 @@ fn integer(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
                 tokenizer.advance();
                 Some(StateFunction(integer))
             } else {
-                tokenizer.tokenize(Category::Integer);
+                tokenizer.tokenize_with_scope(Category::Integer, "constant.numeric.integer.ruby");
                 Some(StateFunction(initial_state))
             }
         }
 
         None => {
-            tokenizer.tokenize(Category::Integer);
+            tokenizer.tokenize_with_scope(Category::Integer, "constant.numeric.integer.ruby");
             None
         }
     }
@@ -392,171 +679,217 @@ fn symbol(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
                 tokenizer.advance();
                 Some(StateFunction(symbol))
             } else {
-                tokenizer.tokenize(Category::Literal);
+                tokenizer.tokenize_with_scope(Category::Literal, "constant.other.symbol.ruby");
                 Some(StateFunction(initial_state))
             }
         }
 
         None => {
-            tokenizer.tokenize(Category::Literal);
+            tokenizer.tokenize_with_scope(Category::Literal, "constant.other.symbol.ruby");
             None
         }
     }
 }
 
+/// Lexes the data lazily, producing tokens that borrow their lexemes
+/// from the data, rather than allocating a copy for each of them.
+pub fn lex_borrowed(data: &str) -> Tokens<'_> {
+    Tokenizer::new(data).run(StateFunction(initial_state))
+}
+
+/// Lexes the data lazily, running the lexer's
+/// state functions only as tokens are requested.
+pub fn lex_iter(data: &str) -> impl Iterator<Item=Token> + '_ {
+    lex_borrowed(data).map(Token::from)
+}
+
 /// Lexes a Ruby document.
 pub fn lex(data: &str) -> Vec<Token> {
-    let mut tokenizer = Tokenizer::new(data);
-    let mut state_function = StateFunction(initial_state);
-    loop {
-        let StateFunction(actual_function) = state_function;
-        match actual_function(&mut tokenizer) {
-            Some(f) => state_function = f,
-            None => {
-                match tokenizer.states.pop() {
-                    Some(f) => state_function = f,
-                    None => return tokenizer.tokens(),
-                }
-            }
+    lex_iter(data).collect()
+}
+
+/// Lexes a Ruby document, reporting any malformed input it contains.
+pub fn lex_with_diagnostics(data: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+    let mut tokens = lex_borrowed(data);
+    let lexed = tokens.by_ref().map(Token::from).collect();
+
+    (lexed, tokens.into_diagnostics())
+}
+
+/// The Ruby lexer, exposed through the common `Lexer` interface.
+pub struct RubyLexer;
+
+impl Lexer for RubyLexer {
+    fn name(&self) -> &str { "Ruby" }
+    fn aliases(&self) -> &[&str] { &["ruby", "rb"] }
+    fn extensions(&self) -> &[&str] { &["rb", "rake", "gemspec", "ru"] }
+    fn mime_types(&self) -> &[&str] { &["text/x-ruby", "application/x-ruby"] }
+    fn filenames(&self) -> &[&str] {
+        &["Gemfile", "Rakefile", "Guardfile", "Capfile", "Vagrantfile", "Podfile", "Berksfile"]
+    }
+    fn interpreters(&self) -> &[&str] { &["ruby", "jruby", "rbx"] }
+
+    fn analyse(&self, data: &str) -> f32 {
+        let lines = || data.lines().map(|line| line.trim());
+
+        if lines().any(|line| line.starts_with("def ")) && lines().any(|line| line == "end") {
+            0.4
+        } else if lines().any(|line| line.starts_with("require '") || line.starts_with("require \"")) {
+            0.3
+        } else {
+            0.0
         }
     }
+
+    fn lex(&self, data: &str) -> Vec<Token> { lex(data) }
+    fn lex_with_diagnostics(&self, data: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+        lex_with_diagnostics(data)
+    }
+    fn lex_iter<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=Token> + 'a> { Box::new(lex_iter(data)) }
+    fn lex_borrowed<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=BorrowedToken<'a>> + 'a> {
+        Box::new(lex_borrowed(data))
+    }
+    fn initial_state(&self) -> Option<StateFunction> { Some(StateFunction(initial_state)) }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::lex;
-    use token::Token;
+    use super::{lex, lex_with_diagnostics};
+    use diagnostic::Kind;
     use token::Category;
 
     #[test]
     fn it_works() {
         let data = include_str!("../../test_data/ruby.rb");
         let tokens = lex(data);
-        let expected_tokens = vec![
-            Token{ lexeme: "module".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "RubyModule".to_string(), category: Category::Identifier },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "end".to_string(), category: Category::Keyword },
-            Token{ lexeme: "\n\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "class".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "Ruby".to_string(), category: Category::Identifier },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "include".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "RubyModule".to_string(), category: Category::Identifier },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "extend".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "RubyModule".to_string(), category: Category::Identifier },
-            Token{ lexeme: "\n\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "private".to_string(), category: Category::Keyword },
-            Token{ lexeme: "\n\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "def".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "method".to_string(), category: Category::Method },
-            Token{ lexeme: "(".to_string(), category: Category::Text },
-            Token{ lexeme: "argument".to_string(), category: Category::Identifier },
-            Token{ lexeme: ",".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "\"string\"".to_string(), category: Category::String },
-            Token{ lexeme: ")".to_string(), category: Category::Text },
-            Token{ lexeme: "\n    ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "begin".to_string(), category: Category::Keyword },
-            Token{ lexeme: "\n      ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "unless".to_string(), category: Category::Keyword },
-            Token{ lexeme: "\n      ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "if".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "true".to_string(), category: Category::Boolean },
-            Token{ lexeme: "\n        ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "\"true\"".to_string(), category: Category::String },
-            Token{ lexeme: "\n      ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "elsif".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "false".to_string(), category: Category::Boolean },
-            Token{ lexeme: "\n        ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "\"false\"".to_string(), category: Category::String },
-            Token{ lexeme: "\n      ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "else".to_string(), category: Category::Keyword },
-            Token{ lexeme: "\n        ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "nil".to_string(), category: Category::Literal },
-            Token{ lexeme: "\n      ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "end".to_string(), category: Category::Keyword },
-            Token{ lexeme: "\n    ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "rescue".to_string(), category: Category::Keyword },
-            Token{ lexeme: "\n      ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "raise".to_string(), category: Category::Keyword },
-            Token{ lexeme: "\n    ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "end".to_string(), category: Category::Keyword },
-            Token{ lexeme: "\n\n    ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "# comment".to_string(), category: Category::Comment },
-            Token{ lexeme: "\n    ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "[".to_string(), category: Category::Text },
-            Token{ lexeme: "\"ruby\"".to_string(), category: Category::String },
-            Token{ lexeme: "]".to_string(), category: Category::Text },
-            Token{ lexeme: ".".to_string(), category: Category::Text },
-            Token{ lexeme: "each".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "do".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "|".to_string(), category: Category::Text },
-            Token{ lexeme: "string".to_string(), category: Category::Identifier },
-            Token{ lexeme: "|".to_string(), category: Category::Text },
-            Token{ lexeme: "\n      ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "variable".to_string(), category: Category::Identifier },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "=".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "'string'".to_string(), category: Category::String },
-            Token{ lexeme: "\n      ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "another_variable".to_string(), category: Category::Identifier },
-            Token{ lexeme: "=".to_string(), category: Category::Text },
-            Token{ lexeme: "1".to_string(), category: Category::Integer },
-            Token{ lexeme: "\n      ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "@instance_variable".to_string(), category: Category::Identifier },
-            Token{ lexeme: "\n      ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "method_call".to_string(), category: Category::Call },
-            Token{ lexeme: "(".to_string(), category: Category::Text },
-            Token{ lexeme: "argument".to_string(), category: Category::Identifier },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "=".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "false".to_string(), category: Category::Boolean },
-            Token{ lexeme: ",".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "another_argument".to_string(), category: Category::Identifier },
-            Token{ lexeme: ")".to_string(), category: Category::Text },
-            Token{ lexeme: "\n      ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "another_method_call".to_string(), category: Category::Text },
-            Token{ lexeme: "\n      ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "hash".to_string(), category: Category::Identifier },
-            Token{ lexeme: "[".to_string(), category: Category::Text },
-            Token{ lexeme: ":symbol_1234?".to_string(), category: Category::Literal },
-            Token{ lexeme: "]".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "=".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "{".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "key".to_string(), category: Category::Literal },
-            Token{ lexeme: ":".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "value".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "}".to_string(), category: Category::Text },
-            Token{ lexeme: "\n    ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "end".to_string(), category: Category::Keyword },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "end".to_string(), category: Category::Keyword },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "end".to_string(), category: Category::Keyword },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace }
+        let expected_tokens = [
+            ("module", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("RubyModule", Category::Type),
+            ("\n", Category::Whitespace),
+            ("end", Category::Keyword),
+            ("\n\n", Category::Whitespace),
+            ("class", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("Ruby", Category::Type),
+            ("\n  ", Category::Whitespace),
+            ("include", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("RubyModule", Category::Constant),
+            ("\n  ", Category::Whitespace),
+            ("extend", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("RubyModule", Category::Constant),
+            ("\n\n  ", Category::Whitespace),
+            ("private", Category::Keyword),
+            ("\n\n  ", Category::Whitespace),
+            ("def", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("method", Category::Method),
+            ("(", Category::Parenthesis),
+            ("argument", Category::Identifier),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("\"string\"", Category::String),
+            (")", Category::Parenthesis),
+            ("\n    ", Category::Whitespace),
+            ("begin", Category::Keyword),
+            ("\n      ", Category::Whitespace),
+            ("unless", Category::Keyword),
+            ("\n      ", Category::Whitespace),
+            ("if", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("true", Category::Boolean),
+            ("\n        ", Category::Whitespace),
+            ("\"true\"", Category::String),
+            ("\n      ", Category::Whitespace),
+            ("elsif", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("false", Category::Boolean),
+            ("\n        ", Category::Whitespace),
+            ("\"false\"", Category::String),
+            ("\n      ", Category::Whitespace),
+            ("else", Category::Keyword),
+            ("\n        ", Category::Whitespace),
+            ("nil", Category::Constant),
+            ("\n      ", Category::Whitespace),
+            ("end", Category::Keyword),
+            ("\n    ", Category::Whitespace),
+            ("rescue", Category::Keyword),
+            ("\n      ", Category::Whitespace),
+            ("raise", Category::Keyword),
+            ("\n    ", Category::Whitespace),
+            ("end", Category::Keyword),
+            ("\n\n    ", Category::Whitespace),
+            ("# comment", Category::Comment),
+            ("\n    ", Category::Whitespace),
+            ("[", Category::Bracket),
+            ("\"ruby\"", Category::String),
+            ("]", Category::Bracket),
+            (".", Category::Punctuation),
+            ("each", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("do", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("|", Category::Punctuation),
+            ("string", Category::Identifier),
+            ("|", Category::Punctuation),
+            ("\n      ", Category::Whitespace),
+            ("variable", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("'string'", Category::String),
+            ("\n      ", Category::Whitespace),
+            ("another_variable", Category::Identifier),
+            ("=", Category::Operator),
+            ("1", Category::Integer),
+            ("\n      ", Category::Whitespace),
+            ("@instance_variable", Category::Identifier),
+            ("\n      ", Category::Whitespace),
+            ("method_call", Category::Call),
+            ("(", Category::Parenthesis),
+            ("argument", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("false", Category::Boolean),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("another_argument", Category::Identifier),
+            (")", Category::Parenthesis),
+            ("\n      ", Category::Whitespace),
+            ("another_method_call", Category::Identifier),
+            ("\n      ", Category::Whitespace),
+            ("hash", Category::Identifier),
+            ("[", Category::Bracket),
+            (":symbol_1234?", Category::Literal),
+            ("]", Category::Bracket),
+            (" ", Category::Whitespace),
+            ("=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("{", Category::Brace),
+            (" ", Category::Whitespace),
+            ("key", Category::Literal),
+            (":", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("value", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("}", Category::Brace),
+            ("\n    ", Category::Whitespace),
+            ("end", Category::Keyword),
+            ("\n  ", Category::Whitespace),
+            ("end", Category::Keyword),
+            ("\n", Category::Whitespace),
+            ("end", Category::Keyword),
+            ("\n", Category::Whitespace),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
         }
     }
 
@@ -564,16 +897,130 @@ mod tests {
     fn it_identifies_integers_and_operators() {
         let data = "123 + 456";
         let tokens = lex(data);
-        let expected_tokens = vec![
-            Token{ lexeme: "123".to_string(), category: Category::Integer },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "+".to_string(), category: Category::Operator },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "456".to_string(), category: Category::Integer },
+        let expected_tokens = [
+            ("123", Category::Integer),
+            (" ", Category::Whitespace),
+            ("+", Category::Operator),
+            (" ", Category::Whitespace),
+            ("456", Category::Integer),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
+        }
+    }
+
+    #[test]
+    fn it_assigns_scopes() {
+        let tokens = lex("def run\n  nil # done\n");
+        let expected_tokens = [
+            ("def", Some("keyword.control.def.ruby")),
+            (" ", None),
+            ("run", Some("entity.name.function.ruby")),
+            ("\n  ", None),
+            ("nil", Some("constant.language.nil.ruby")),
+            (" ", None),
+            ("# done", Some("comment.line.number-sign.ruby")),
+            ("\n", None),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, (lexeme, scope)) in expected_tokens.iter().enumerate() {
+            assert_eq!(tokens[index].lexeme, *lexeme);
+            assert_eq!(tokens[index].scope.as_deref(), *scope);
+        }
+    }
+
+    #[test]
+    fn it_identifies_constants_interpolation_and_regexes() {
+        let tokens = lex("Foo::Bar.new(\"a #{b} c\") =~ /x\\/y/i\n");
+        let expected_tokens = [
+            ("Foo", Category::Namespace),
+            ("::", Category::Punctuation),
+            ("Bar", Category::Constant),
+            (".", Category::Punctuation),
+            ("new", Category::Call),
+            ("(", Category::Parenthesis),
+            ("\"a ", Category::String),
+            ("#{b}", Category::Interpolation),
+            (" c\"", Category::String),
+            (")", Category::Parenthesis),
+            (" ", Category::Whitespace),
+            ("=", Category::Operator),
+            ("~", Category::Operator),
+            (" ", Category::Whitespace),
+            ("/x\\/y/i", Category::Regex),
+            ("\n", Category::Whitespace),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+        }
+    }
+
+    #[test]
+    fn it_categorizes_tokens_ending_the_data() {
+        let tokens = lex("def m");
+        assert_eq!(tokens.last().unwrap().lexeme, "m");
+        assert_eq!(tokens.last().unwrap().category, Category::Method);
+
+        let tokens = lex("# c");
+        assert_eq!(tokens.last().unwrap().lexeme, "# c");
+        assert_eq!(tokens.last().unwrap().category, Category::Comment);
+    }
+
+    #[test]
+    fn it_identifies_heredocs_and_embedded_documents() {
+        let data = "x = <<~SQL.strip\n  select 1\n  SQL\n=begin\ndoc\n=end\n`ls`";
+        let tokens = lex(data);
+        let expected_tokens = [
+            ("x", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("<<~SQL", Category::String),
+            (".", Category::Punctuation),
+            ("strip", Category::Identifier),
+            ("\n", Category::Whitespace),
+            ("  select 1\n  SQL", Category::String),
+            ("\n", Category::Whitespace),
+            ("=begin\ndoc\n=end", Category::Comment),
+            ("\n", Category::Whitespace),
+            ("`ls`", Category::String),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
+        }
+    }
+
+    #[test]
+    fn it_reports_malformed_input() {
+        let (tokens, diagnostics) = lex_with_diagnostics("a¿ = 1");
+        assert_eq!(tokens[1].lexeme, "¿");
+        assert_eq!(tokens[1].category, Category::Error);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, Kind::UnexpectedCharacter);
+        assert_eq!(diagnostics[0].span.range(), 1..3);
+
+        for (data, kind, range) in [
+            ("x = /a", Kind::UnterminatedString, 4..6),
+            ("x = `a", Kind::UnterminatedString, 4..6),
+            ("x = <<~A\nb", Kind::UnterminatedString, 9..10),
+            ("=begin\nb", Kind::UnterminatedComment, 0..8),
+        ] {
+            let (_, diagnostics) = lex_with_diagnostics(data);
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0].kind, kind);
+            assert_eq!(diagnostics[0].span.range(), range);
         }
     }
 }
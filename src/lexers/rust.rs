@@ -1,205 +1,489 @@
-//! A lexer for the Ruby programming language.
+//! A lexer for the Rust programming language.
 
-use token::{Category, Token};
-use tokenizer::{Tokenizer, StateFunction};
+use diagnostic::{Diagnostic, Kind};
+use lexers::{is_unexpected, Lexer};
+use token::{BorrowedToken, Category, Token};
+use tokenizer::{Tokenizer, StateFunction, Tokens};
 
-fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
-    for keyword in vec!["pub", "let", "mut", "match", "loop"] {
-        if tokenizer.starts_with_lexeme(keyword) {
-            tokenizer.tokenize_next(keyword.chars().count(), Category::Keyword);
-
-            return Some(StateFunction(initial_state))
-        }
-    }
-
-    if tokenizer.starts_with_lexeme("use") {
-        tokenizer.tokenize_next(3, Category::Keyword);
-        tokenizer.states.push(StateFunction(identifier));
+// Keywords that are tokenized on their own, and their scopes. Those
+// introducing names (e.g. `fn` and `struct`) are handled separately.
+const KEYWORDS: &[(&str, &str)] = &[
+    // Strict keywords.
+    ("as", "keyword.operator.cast.rust"),
+    ("async", "storage.modifier.async.rust"),
+    ("await", "keyword.control.rust"),
+    ("const", "storage.modifier.const.rust"),
+    ("dyn", "storage.modifier.dyn.rust"),
+    ("else", "keyword.control.rust"),
+    ("if", "keyword.control.rust"),
+    ("impl", "keyword.other.impl.rust"),
+    ("let", "keyword.other.rust"),
+    ("loop", "keyword.control.rust"),
+    ("match", "keyword.control.rust"),
+    ("move", "storage.modifier.move.rust"),
+    ("mut", "storage.modifier.mut.rust"),
+    ("pub", "storage.modifier.visibility.rust"),
+    ("ref", "storage.modifier.ref.rust"),
+    ("return", "keyword.control.rust"),
+    ("self", "variable.language.self.rust"),
+    ("Self", "variable.language.self.rust"),
+    ("static", "storage.modifier.static.rust"),
+    ("super", "variable.language.super.rust"),
+    ("unsafe", "storage.modifier.unsafe.rust"),
+    ("where", "keyword.other.where.rust"),
+    ("while", "keyword.control.rust"),
 
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme("mod") {
-        tokenizer.tokenize_next(3, Category::Keyword);
-        tokenizer.states.push(StateFunction(identifier));
+    // Reserved keywords.
+    ("abstract", "keyword.other.reserved.rust"),
+    ("become", "keyword.other.reserved.rust"),
+    ("box", "keyword.other.reserved.rust"),
+    ("do", "keyword.other.reserved.rust"),
+    ("final", "keyword.other.reserved.rust"),
+    ("macro", "keyword.other.reserved.rust"),
+    ("override", "keyword.other.reserved.rust"),
+    ("priv", "keyword.other.reserved.rust"),
+    ("try", "keyword.other.reserved.rust"),
+    ("typeof", "keyword.other.reserved.rust"),
+    ("unsized", "keyword.other.reserved.rust"),
+    ("virtual", "keyword.other.reserved.rust"),
+    ("yield", "keyword.other.reserved.rust"),
+];
 
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme("extern") {
-        tokenizer.tokenize_next(6, Category::Keyword);
-        tokenizer.states.push(StateFunction(initial_state));
+// Keywords that introduce the name of a type.
+const TYPE_KEYWORDS: &[&str] = &["struct", "enum", "trait", "type", "union"];
 
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme("crate") {
-        tokenizer.tokenize_next(5, Category::Keyword);
-        tokenizer.states.push(StateFunction(identifier));
+const PRIMITIVE_TYPES: &[&str] = &[
+    "bool", "char", "str", "f32", "f64",
+    "i8", "i16", "i32", "i64", "i128", "isize",
+    "u8", "u16", "u32", "u64", "u128", "usize",
+];
 
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme("for") {
-        tokenizer.tokenize_next(3, Category::Keyword);
-        tokenizer.states.push(StateFunction(identifier));
+// Operators and punctuation (other than brackets and `::`, which is handled
+// separately), longest first so that the first with a matching prefix wins.
+const OPERATORS: &[(&str, Category, Option<&str>)] = &[
+    ("<<=", Category::Operator, Some("keyword.operator.assignment.rust")),
+    (">>=", Category::Operator, Some("keyword.operator.assignment.rust")),
+    ("...", Category::Operator, Some("keyword.operator.range.rust")),
+    ("..=", Category::Operator, Some("keyword.operator.range.rust")),
+    ("..", Category::Operator, Some("keyword.operator.range.rust")),
+    ("->", Category::Punctuation, None),
+    ("=>", Category::Punctuation, None),
+    ("==", Category::Operator, Some("keyword.operator.comparison.rust")),
+    ("!=", Category::Operator, Some("keyword.operator.comparison.rust")),
+    ("<=", Category::Operator, Some("keyword.operator.comparison.rust")),
+    (">=", Category::Operator, Some("keyword.operator.comparison.rust")),
+    ("&&", Category::Operator, Some("keyword.operator.logical.rust")),
+    ("||", Category::Operator, Some("keyword.operator.logical.rust")),
+    ("+=", Category::Operator, Some("keyword.operator.assignment.rust")),
+    ("-=", Category::Operator, Some("keyword.operator.assignment.rust")),
+    ("*=", Category::Operator, Some("keyword.operator.assignment.rust")),
+    ("/=", Category::Operator, Some("keyword.operator.assignment.rust")),
+    ("%=", Category::Operator, Some("keyword.operator.assignment.rust")),
+    ("^=", Category::Operator, Some("keyword.operator.assignment.rust")),
+    ("&=", Category::Operator, Some("keyword.operator.assignment.rust")),
+    ("|=", Category::Operator, Some("keyword.operator.assignment.rust")),
+    ("<<", Category::Operator, Some("keyword.operator.bitwise.rust")),
+    (">>", Category::Operator, Some("keyword.operator.bitwise.rust")),
+    ("+", Category::Operator, Some("keyword.operator.arithmetic.rust")),
+    ("-", Category::Operator, Some("keyword.operator.arithmetic.rust")),
+    ("*", Category::Operator, Some("keyword.operator.arithmetic.rust")),
+    ("/", Category::Operator, Some("keyword.operator.arithmetic.rust")),
+    ("%", Category::Operator, Some("keyword.operator.arithmetic.rust")),
+    ("^", Category::Operator, Some("keyword.operator.bitwise.rust")),
+    ("&", Category::Operator, Some("keyword.operator.bitwise.rust")),
+    ("|", Category::Operator, Some("keyword.operator.bitwise.rust")),
+    ("!", Category::Operator, Some("keyword.operator.logical.rust")),
+    ("=", Category::Operator, Some("keyword.operator.assignment.rust")),
+    ("<", Category::Operator, Some("keyword.operator.comparison.rust")),
+    (">", Category::Operator, Some("keyword.operator.comparison.rust")),
+    ("?", Category::Operator, Some("keyword.operator.question.rust")),
+    ("@", Category::Operator, Some("keyword.operator.at.rust")),
+    ("~", Category::Operator, None),
+    (".", Category::Punctuation, None),
+    (",", Category::Punctuation, None),
+    (";", Category::Punctuation, None),
+    (":", Category::Punctuation, None),
+    ("#", Category::Punctuation, None),
+    ("$", Category::Punctuation, None),
+];
 
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme("in") {
-        tokenizer.tokenize_next(2, Category::Keyword);
-        tokenizer.states.push(StateFunction(identifier));
-
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme("fn") {
-        tokenizer.tokenize_next(2, Category::Keyword);
-        tokenizer.states.push(StateFunction(function));
-
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.starts_with_lexeme(":") {
-        tokenizer.advance();
-        tokenizer.tokenize(Category::Key);
-        tokenizer.states.push(StateFunction(identifier));
+fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    if !tokenizer.has_pending_data() {
+        if let Some(state) = keyword(tokenizer).or_else(|| prefixed_literal(tokenizer)) {
+            return Some(state)
+        }
+    }
 
-        return Some(StateFunction(whitespace))
-    } else if tokenizer.has_prefix("::") {
-        tokenizer.tokenize(Category::Identifier);
-        tokenizer.tokenize_next(2, Category::Text);
+    if tokenizer.has_prefix("::") {
+        tokenizer.tokenize(Category::Namespace);
+        tokenizer.tokenize_next(2, Category::Punctuation);
 
         return Some(StateFunction(initial_state))
-    } else if tokenizer.has_prefix("#[") {
-        tokenizer.tokenize(Category::Text);
+    } else if tokenizer.has_prefix("#[") || tokenizer.has_prefix("#![") {
+        tokenizer.tokenize(Category::Identifier);
 
         return Some(StateFunction(attribute))
     } else if tokenizer.has_prefix("//") {
+        tokenizer.tokenize(Category::Identifier);
+
+        // Doc comments begin with `///` (but not `////`) or `//!`.
+        if tokenizer.match_regex(regex!(r"//(/[^/]|/$|!)")).is_some() {
+            return Some(StateFunction(doc_comment))
+        }
         return Some(StateFunction(comment))
+    } else if tokenizer.has_prefix("/*") {
+        tokenizer.tokenize(Category::Identifier);
+
+        // Block comments nest, so their depth is kept in user state. Doc
+        // comments begin with `/**` (but not `/**/` or `/***`) or `/*!`.
+        tokenizer.set_user_state(0usize);
+        if tokenizer.match_regex(regex!(r"/\*(\*[^*/]|\*$|!)")).is_some() {
+            return Some(StateFunction(doc_block_comment))
+        }
+        return Some(StateFunction(block_comment))
+    } else if !tokenizer.has_pending_data() && tokenizer.has_prefix("r#") {
+        // Raw identifiers (e.g. `r#type`) are never keywords.
+        tokenizer.advance_bytes(2);
+        return Some(StateFunction(identifier))
     }
 
     match tokenizer.current_char() {
         Some('"') => {
-            tokenizer.tokenize(Category::Text);
+            tokenizer.tokenize(Category::Identifier);
             tokenizer.advance();
             Some(StateFunction(inside_string))
         },
         Some('\'') => {
-            tokenizer.tokenize(Category::Text);
-            tokenizer.advance();
-            Some(StateFunction(inside_single_quote_string))
+            tokenizer.tokenize(Category::Identifier);
+            quote(tokenizer).or_else(|| {
+                tokenizer.advance();
+                tokenizer.report(Kind::UnexpectedCharacter, "unexpected quote");
+                tokenizer.tokenize_with_scope(Category::Error, "invalid.illegal.character.rust");
+                Some(StateFunction(initial_state))
+            })
         },
-        Some('|') => {
-            tokenizer.tokenize_next(1, Category::Text);
+        Some('|') if !tokenizer.has_pending_data() && !tokenizer.has_prefix("||") && !tokenizer.has_prefix("|=") => {
+            // Bars following operands are handled by the operand state, so
+            // this one opens a closure's arguments.
+            tokenizer.tokenize_next(1, Category::Punctuation);
             tokenizer.states.push(StateFunction(argument));
             Some(StateFunction(whitespace))
         },
-        Some('.') => {
-            tokenizer.tokenize(Category::Identifier);
-            tokenizer.tokenize_next(1, Category::Text);
-            Some(StateFunction(initial_state))
-        },
         Some('(') => {
-            tokenizer.tokenize(Category::Call);
-            tokenizer.tokenize_next(1, Category::Text);
+            tokenizer.tokenize_with_scope(Category::Call, "entity.name.function.call.rust");
+            tokenizer.tokenize_next(1, Category::Parenthesis);
             Some(StateFunction(argument))
         },
-        Some('+') => {
-            tokenizer.tokenize_next(1, Category::Operator);
-            Some(StateFunction(initial_state))
-        },
-        Some(' ') | Some('\n') => {
-            match tokenizer.next_non_whitespace_char() {
-                Some('=') => {
-                    tokenizer.tokenize(Category::Identifier);
-                    tokenizer.consume_whitespace();
-                    tokenizer.tokenize_next(1, Category::Text);
-                },
-                _ => tokenizer.consume_whitespace(),
-            }
-            Some(StateFunction(initial_state))
+        Some(')') => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Parenthesis);
+            Some(StateFunction(operand))
         },
-        Some('=') => {
+        Some('{') | Some('}') => {
             tokenizer.tokenize(Category::Identifier);
-            tokenizer.tokenize_next(1, Category::Text);
+            tokenizer.tokenize_next(1, Category::Brace);
             Some(StateFunction(initial_state))
         },
         Some('[') => {
             tokenizer.tokenize(Category::Identifier);
-            tokenizer.tokenize_next(1, Category::Text);
+            tokenizer.tokenize_next(1, Category::Bracket);
             Some(StateFunction(initial_state))
         },
         Some(']') => {
-            tokenizer.tokenize_next(1, Category::Text);
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Bracket);
+            Some(StateFunction(operand))
+        },
+        Some('.') if !tokenizer.has_prefix("..") => {
+            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_next(1, Category::Punctuation);
+            Some(StateFunction(field))
+        },
+        Some('!') if tokenizer.has_pending_data() && !tokenizer.has_prefix("!=") => {
+            // An identifier followed by a bang is a macro invocation.
+            tokenizer.advance();
+            tokenizer.tokenize_with_scope(Category::Macro, "support.macro.rust");
+            Some(StateFunction(initial_state))
+        },
+        Some(' ') | Some('\n') if tokenizer.has_pending_data() => {
+            tokenizer.tokenize(Category::Identifier);
+            Some(StateFunction(operand))
+        },
+        Some(' ') | Some('\n') => {
+            tokenizer.consume_whitespace();
             Some(StateFunction(initial_state))
         },
         Some(c) => {
-            tokenizer.advance();
+            if let Some(&(operator, ref category, scope)) = OPERATORS.iter().find(|operator| {
+                tokenizer.has_prefix(operator.0)
+            }) {
+                tokenizer.tokenize(Category::Identifier);
+                let length = operator.chars().count();
+                match scope {
+                    Some(scope) => tokenizer.tokenize_next_with_scope(length, category.clone(), scope),
+                    None => tokenizer.tokenize_next(length, category.clone()),
+                }
 
-            if c.is_numeric() {
-                Some(StateFunction(integer))
-            } else {
-                Some(StateFunction(initial_state))
+                return Some(StateFunction(initial_state))
+            }
+
+            if c.is_ascii_digit() && !tokenizer.has_pending_data() {
+                return number(tokenizer)
+            } else if is_unexpected(c) {
+                tokenizer.tokenize(Category::Identifier);
+                tokenizer.advance();
+                tokenizer.report(Kind::UnexpectedCharacter, "unexpected character");
+                tokenizer.tokenize_with_scope(Category::Error, "invalid.illegal.character.rust");
+
+                return Some(StateFunction(initial_state))
             }
+            tokenizer.advance();
+
+            Some(StateFunction(initial_state))
         }
 
         None => {
-            tokenizer.tokenize(Category::Text);
+            tokenizer.tokenize(Category::Identifier);
             None
         }
     }
 }
 
+// Tokenizes a keyword, boolean or primitive type at the current
+// position, returning the state that follows it, if there is one.
+fn keyword(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    if let Some(length) = tokenizer.match_keyword(&["true", "false"]) {
+        tokenizer.tokenize_next_with_scope(length, Category::Boolean, "constant.language.boolean.rust");
 
-fn inside_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+        return Some(StateFunction(operand))
+    } else if let Some(length) = tokenizer.match_keyword(PRIMITIVE_TYPES) {
+        tokenizer.tokenize_next_with_scope(length, Category::Type, "entity.name.type.primitive.rust");
+
+        return Some(StateFunction(operand))
+    }
+
+    for &(keyword, scope) in KEYWORDS {
+        if tokenizer.match_keyword(&[keyword]).is_some() {
+            tokenizer.tokenize_next_with_scope(keyword.len(), Category::Keyword, scope);
+
+            return Some(StateFunction(initial_state))
+        }
+    }
+
+    for (keyword, scope) in [
+        ("use", "keyword.other.rust"),
+        ("mod", "keyword.other.rust"),
+        ("crate", "keyword.other.rust"),
+        ("for", "keyword.control.rust"),
+        ("in", "keyword.control.rust"),
+    ] {
+        if tokenizer.match_keyword(&[keyword]).is_some() {
+            tokenizer.tokenize_next_with_scope(keyword.len(), Category::Keyword, scope);
+            tokenizer.states.push(StateFunction(identifier));
+
+            return Some(StateFunction(whitespace))
+        }
+    }
+
+    if let Some(length) = tokenizer.match_keyword(&["break", "continue"]) {
+        tokenizer.tokenize_next_with_scope(length, Category::Keyword, "keyword.control.rust");
+        tokenizer.states.push(StateFunction(label));
+
+        return Some(StateFunction(whitespace))
+    } else if let Some(length) = tokenizer.match_keyword(TYPE_KEYWORDS) {
+        // `union` is only a keyword when it declares a union.
+        if length != 5 || tokenizer.match_regex(regex!(r"union\s+[A-Za-z_]")).is_some() {
+            tokenizer.tokenize_next_with_scope(length, Category::Keyword, "storage.type.rust");
+            tokenizer.states.push(StateFunction(type_name));
+
+            return Some(StateFunction(whitespace))
+        }
+    } else if tokenizer.match_keyword(&["extern"]).is_some() {
+        tokenizer.tokenize_next_with_scope(6, Category::Keyword, "keyword.other.rust");
+        tokenizer.states.push(StateFunction(initial_state));
+
+        return Some(StateFunction(whitespace))
+    } else if tokenizer.match_keyword(&["fn"]).is_some() {
+        tokenizer.tokenize_next_with_scope(2, Category::Keyword, "keyword.other.fn.rust");
+        tokenizer.states.push(StateFunction(function));
+
+        return Some(StateFunction(whitespace))
+    }
+
+    None
+}
+
+// Begins a byte, C or raw string (e.g. `b"..."`, `c"..."` or `r#"..."#`),
+// or a byte character (e.g. `b'a'`), returning the state that lexes it.
+fn prefixed_literal(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    if let Some(length) = tokenizer.match_regex(regex!(r#"[bc]?r#*""#)) {
+        // Raw strings are terminated by a quote followed by as many hashes as
+        // precede their opening quote, which are counted and kept in user state.
+        let letters = if tokenizer.has_prefix("r") { 1 } else { 2 };
+        tokenizer.set_user_state(length - letters - 1);
+        tokenizer.advance_bytes(length);
+
+        Some(StateFunction(raw_string))
+    } else if tokenizer.has_prefix("b\"") {
+        tokenizer.advance_bytes(2);
+
+        Some(StateFunction(inside_byte_string))
+    } else if tokenizer.has_prefix("c\"") {
+        tokenizer.advance_bytes(2);
+
+        Some(StateFunction(inside_string))
+    } else if tokenizer.has_prefix("b'") {
+        quote(tokenizer)
+    } else {
+        None
+    }
+}
+
+fn raw_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     match tokenizer.current_char() {
-        Some(c) => {
-            match c {
-                '"' => {
-                    tokenizer.advance();
-                    tokenizer.tokenize(Category::String);
-                    Some(StateFunction(initial_state))
-                },
-                '\\' => {
-                    tokenizer.advance();
-                    tokenizer.advance();
-                    Some(StateFunction(inside_string))
-                }
-                _ => {
-                    tokenizer.advance();
-                    Some(StateFunction(inside_string))
-                }
+        Some('"') => {
+            tokenizer.advance();
+
+            let hashes = *tokenizer.user_state::<usize>();
+            if tokenizer.match_chars(|c| c == '#').unwrap_or(0) >= hashes {
+                tokenizer.advance_bytes(hashes);
+                tokenizer.tokenize_with_scope(Category::String, "string.quoted.double.raw.rust");
+
+                return Some(StateFunction(operand))
             }
-        }
 
+            Some(StateFunction(raw_string))
+        },
+        Some(_) => {
+            tokenizer.advance();
+            Some(StateFunction(raw_string))
+        },
         None => {
-            tokenizer.tokenize(Category::String);
+            tokenizer.report(Kind::UnterminatedString, "unterminated string");
+            tokenizer.tokenize_with_scope(Category::String, "string.quoted.double.raw.rust");
             None
         }
     }
 }
 
-fn inside_single_quote_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+// Tokenizes the escape sequence at the current position, which is
+// categorized as an error if it isn't a valid sequence. Byte literals
+// (e.g. `b"..."`) can't contain Unicode escapes.
+fn escape(tokenizer: &mut Tokenizer, byte: bool) {
+    let valid = if byte {
+        regex!(r#"\\(x[0-9A-Fa-f]{2}|[nrt0\\'"]|\n[ \t\n]*)"#)
+    } else {
+        regex!(r#"\\(u\{([0-9A-Fa-f]_*){1,6}\}|x[0-9A-Fa-f]{2}|[nrt0\\'"]|\n[ \t\n]*)"#)
+    };
+
+    match tokenizer.match_regex(valid) {
+        Some(length) => {
+            tokenizer.advance_bytes(length);
+            tokenizer.tokenize_with_scope(Category::Escape, "constant.character.escape.rust");
+        },
+        None => {
+            tokenizer.advance();
+            if tokenizer.current_char().is_some_and(|c| c != '"' && c != '\n') {
+                tokenizer.advance();
+            }
+            tokenizer.report(Kind::InvalidEscape, "invalid escape sequence");
+            tokenizer.tokenize_with_scope(Category::Error, "invalid.illegal.escape.rust");
+        }
+    }
+}
+
+fn inside_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    string(tokenizer, false, StateFunction(inside_string))
+}
+
+fn inside_byte_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    string(tokenizer, true, StateFunction(inside_byte_string))
+}
+
+// Consumes a string's content, tokenizing its escape sequences separately.
+fn string(tokenizer: &mut Tokenizer, byte: bool, state: StateFunction) -> Option<StateFunction> {
     match tokenizer.current_char() {
         Some(c) => {
             match c {
-                '\'' => {
+                '"' => {
                     tokenizer.advance();
-                    tokenizer.tokenize(Category::String);
-                    Some(StateFunction(initial_state))
+                    tokenizer.tokenize_with_scope(Category::String, "string.quoted.double.rust");
+                    Some(StateFunction(operand))
                 },
                 '\\' => {
-                    tokenizer.advance();
-                    tokenizer.advance();
-                    Some(StateFunction(inside_single_quote_string))
+                    tokenizer.tokenize_with_scope(Category::String, "string.quoted.double.rust");
+                    escape(tokenizer, byte);
+                    Some(state)
                 }
-                ':' => {
-                    tokenizer.advance();
-                    tokenizer.tokenize(Category::Identifier);
-                    Some(StateFunction(initial_state))
-                },
                 _ => {
                     tokenizer.advance();
-                    Some(StateFunction(inside_single_quote_string))
+                    Some(state)
                 }
             }
         }
 
         None => {
-            tokenizer.tokenize(Category::String);
+            tokenizer.report(Kind::UnterminatedString, "unterminated string");
+            tokenizer.tokenize_with_scope(Category::String, "string.quoted.double.rust");
             None
         }
     }
 }
 
+// Tokenizes the character, byte character (e.g. `b'a'`), lifetime or label
+// beginning at the current position, if there is one.
+fn quote(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    let character = regex!(r"b?'(\\'|\\[^'\n]+|[^'\\\n])'");
+    let lifetime = regex!(r"'(r#)?[\p{XID_Start}_]\p{XID_Continue}*");
+
+    if let Some(length) = tokenizer.match_regex(character) {
+        let end = tokenizer.position().offset + length;
+        let byte = tokenizer.has_prefix("b");
+        tokenizer.advance_bytes(if byte { 2 } else { 1 });
+
+        if tokenizer.current_char() == Some('\\') {
+            tokenizer.tokenize_with_scope(Category::String, "string.quoted.single.char.rust");
+            escape(tokenizer, byte);
+
+            // Whatever remains of a malformed escape is left in the literal.
+            let remaining = end.saturating_sub(tokenizer.position().offset);
+            tokenizer.advance_bytes(remaining);
+        } else {
+            tokenizer.advance_bytes(end - tokenizer.position().offset);
+        }
+        tokenizer.tokenize_with_scope(Category::String, "string.quoted.single.char.rust");
+
+        Some(StateFunction(operand))
+    } else if tokenizer.has_prefix("b") {
+        None
+    } else if let Some(length) = tokenizer.match_regex(lifetime) {
+        tokenizer.advance_bytes(length);
+
+        // Labels are declared before a loop or block, e.g. `'outer: loop {}`,
+        // which distinguishes them from bounds (e.g. `'a: 'b`).
+        if tokenizer.match_regex(regex!(r":\s*((loop|while|for)\b|\{)")).is_some() {
+            tokenizer.tokenize_with_scope(Category::Label, "entity.name.label.rust");
+        } else {
+            tokenizer.tokenize_with_scope(Category::Lifetime, "storage.modifier.lifetime.rust");
+        }
+
+        Some(StateFunction(initial_state))
+    } else {
+        None
+    }
+}
+
+// Follows `break` and `continue`, which may name the label of their loop.
+fn label(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    if let Some(length) = tokenizer.match_regex(regex!(r"'(r#)?[\p{XID_Start}_]\p{XID_Continue}*")) {
+        if tokenizer.match_regex(regex!(r"'.'")).is_none() {
+            tokenizer.tokenize_next_with_scope(length, Category::Label, "entity.name.label.rust");
+        }
+    }
+
+    Some(StateFunction(initial_state))
+}
+
 fn whitespace(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     match tokenizer.current_char() {
         Some(c) => {
@@ -225,37 +509,82 @@ fn whitespace(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     }
 }
 
+// Follows a dot, after which digits are a tuple index rather than a
+// float, e.g. `t.0.1` accesses the second field of the first.
+fn field(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    match tokenizer.match_chars(|c| c.is_ascii_digit()) {
+        Some(length) => {
+            tokenizer.tokenize_next_with_scope(length, Category::Integer, "constant.numeric.integer.rust");
+            Some(StateFunction(operand))
+        },
+        None => Some(StateFunction(initial_state)),
+    }
+}
+
+// Follows an operand (e.g. an identifier, literal or closing bracket),
+// after which a bar is a bitwise or, rather than the start of a closure.
+fn operand(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    match tokenizer.current_char() {
+        Some(' ') | Some('\n') => {
+            tokenizer.consume_whitespace();
+            Some(StateFunction(operand))
+        },
+        Some('|') if !tokenizer.has_prefix("||") && !tokenizer.has_prefix("|=") => {
+            tokenizer.tokenize_next_with_scope(1, Category::Operator, "keyword.operator.bitwise.rust");
+            Some(StateFunction(initial_state))
+        },
+        _ => Some(StateFunction(initial_state)),
+    }
+}
+
 fn argument(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
-    if tokenizer.starts_with_lexeme("true") {
-        tokenizer.tokenize_next(4, Category::Boolean);
-        return Some(StateFunction(argument))
-    } else if tokenizer.starts_with_lexeme("false") {
-        tokenizer.tokenize_next(5, Category::Boolean);
-        return Some(StateFunction(argument))
+    if !tokenizer.has_pending_data() {
+        if let Some(state) = keyword(tokenizer) {
+            // Plain keywords (e.g. `mut` and `self`) don't end the argument list.
+            return if state == StateFunction(initial_state) { Some(StateFunction(argument)) } else { Some(state) }
+        }
     }
 
     match tokenizer.current_char() {
         Some(c) => {
             match c {
-                ' ' | '\n' => {
+                ' ' | '\n' if tokenizer.has_pending_data() => {
                     tokenizer.tokenize(Category::Identifier);
+                    Some(StateFunction(operand))
+                },
+                ' ' | '\n' => {
                     tokenizer.states.push(StateFunction(argument));
                     Some(StateFunction(whitespace))
                 },
-                '|' | ')' => {
+                '|' if tokenizer.has_pending_data() => {
+                    // The bar closing a closure's arguments.
                     tokenizer.tokenize(Category::Identifier);
-                    tokenizer.tokenize_next(1, Category::Text);
+                    tokenizer.tokenize_next(1, Category::Punctuation);
                     Some(StateFunction(initial_state))
                 },
-                '=' | ',' => {
+                '|' if !tokenizer.has_prefix("||") && !tokenizer.has_prefix("|=") => {
+                    // The bar opening the arguments of a closure passed as an argument.
+                    tokenizer.tokenize_next(1, Category::Punctuation);
+                    Some(StateFunction(argument))
+                },
+                ')' => {
+                    tokenizer.tokenize(Category::Identifier);
+                    tokenizer.tokenize_next(1, Category::Parenthesis);
+                    Some(StateFunction(operand))
+                },
+                ',' => {
                     tokenizer.tokenize(Category::Identifier);
-                    tokenizer.tokenize_next(1, Category::Text);
+                    tokenizer.tokenize_next(1, Category::Punctuation);
                     Some(StateFunction(argument))
                 },
-                _ => {
+                _ if c.is_alphabetic() || c == '_' || (c.is_numeric() && tokenizer.has_pending_data()) => {
                     tokenizer.advance();
                     Some(StateFunction(argument))
-                }
+                },
+
+                // Anything else (e.g. a type annotation, operator or literal)
+                // ends the argument, which the initial state categorizes.
+                _ => Some(StateFunction(initial_state)),
             }
         }
 
@@ -268,23 +597,13 @@ fn argument(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
 
 fn identifier(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     match tokenizer.current_char() {
-        Some(c) => {
-            match c {
-                ' ' | '\n' => {
-                    tokenizer.tokenize(Category::Identifier);
-                    Some(StateFunction(initial_state))
-                },
-                '|' | '(' | ')' | '-' | ';' | '{' | '}' => {
-                    tokenizer.tokenize(Category::Identifier);
-                    tokenizer.tokenize_next(1, Category::Text);
-                    Some(StateFunction(initial_state))
-                },
-                _ => {
-                    tokenizer.advance();
-                    Some(StateFunction(identifier))
-                }
-            }
-        }
+        Some(c) if c.is_alphabetic() || c == '_' || (c.is_numeric() && tokenizer.has_pending_data()) => {
+            tokenizer.advance();
+            Some(StateFunction(identifier))
+        },
+
+        // The initial state categorizes the identifier, which may be a namespace.
+        Some(_) => Some(StateFunction(initial_state)),
 
         None => {
             tokenizer.tokenize(Category::Identifier);
@@ -293,28 +612,50 @@ fn identifier(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     }
 }
 
+fn type_name(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    match tokenizer.current_char() {
+        Some(c) if c.is_alphanumeric() || c == '_' => {
+            tokenizer.advance();
+            Some(StateFunction(type_name))
+        },
+        Some(_) => {
+            tokenizer.tokenize_with_scope(Category::Type, "entity.name.type.rust");
+            Some(StateFunction(initial_state))
+        },
+        None => {
+            tokenizer.tokenize_with_scope(Category::Type, "entity.name.type.rust");
+            None
+        }
+    }
+}
+
 fn function(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     match tokenizer.current_char() {
         Some(c) => {
             match c {
                 ' ' | '\n' => {
-                    tokenizer.tokenize(Category::Function);
+                    tokenizer.tokenize_with_scope(Category::Function, "entity.name.function.rust");
                     Some(StateFunction(initial_state))
                 },
                 '(' => {
-                    tokenizer.tokenize(Category::Function);
-                    tokenizer.tokenize_next(1, Category::Text);
+                    tokenizer.tokenize_with_scope(Category::Function, "entity.name.function.rust");
+                    tokenizer.tokenize_next(1, Category::Parenthesis);
                     Some(StateFunction(argument))
                 },
-                _ => {
+                _ if c.is_alphanumeric() || c == '_' => {
                     tokenizer.advance();
                     Some(StateFunction(function))
+                },
+                _ => {
+                    // Generic parameters, for instance.
+                    tokenizer.tokenize_with_scope(Category::Function, "entity.name.function.rust");
+                    Some(StateFunction(initial_state))
                 }
             }
         }
 
         None => {
-            tokenizer.tokenize(Category::Whitespace);
+            tokenizer.tokenize_with_scope(Category::Function, "entity.name.function.rust");
             None
         }
     }
@@ -322,43 +663,85 @@ fn function(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
 
 fn comment(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     match tokenizer.current_char() {
-        Some(c) => {
-            match c {
-                '\n' => {
-                    tokenizer.tokenize(Category::Comment);
-                    Some(StateFunction(initial_state))
-                },
-                _ => {
-                    tokenizer.advance();
-                    Some(StateFunction(comment))
-                }
-            }
-        }
-
-        None => {
-            tokenizer.tokenize(Category::Whitespace);
-            None
+        Some('\n') | None => {
+            tokenizer.tokenize_with_scope(Category::Comment, "comment.line.double-slash.rust");
+            tokenizer.current_char().map(|_| StateFunction(initial_state))
+        },
+        Some(_) => {
+            tokenizer.advance();
+            Some(StateFunction(comment))
         }
     }
 }
 
-fn integer(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+fn doc_comment(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     match tokenizer.current_char() {
-        Some(c) => {
-            if c.is_numeric() {
-                tokenizer.advance();
-                Some(StateFunction(integer))
-            } else {
-                tokenizer.tokenize(Category::Integer);
-                Some(StateFunction(initial_state))
-            }
+        Some('\n') | None => {
+            tokenizer.tokenize_with_scope(Category::DocComment, "comment.line.documentation.rust");
+            tokenizer.current_char().map(|_| StateFunction(initial_state))
+        },
+        Some(_) => {
+            tokenizer.advance();
+            Some(StateFunction(doc_comment))
         }
+    }
+}
 
-        None => {
-            tokenizer.tokenize(Category::Integer);
-            None
+fn block_comment(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    nested_comment(tokenizer, Category::Comment, "comment.block.rust", StateFunction(block_comment))
+}
+
+fn doc_block_comment(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    nested_comment(tokenizer, Category::DocComment, "comment.block.documentation.rust", StateFunction(doc_block_comment))
+}
+
+// Consumes a block comment's delimiters and content, tracking the depth of
+// nested comments, and tokenizes it once its outermost comment is closed.
+fn nested_comment(tokenizer: &mut Tokenizer, category: Category, scope: &'static str,
+                  state: StateFunction) -> Option<StateFunction> {
+    if tokenizer.has_prefix("/*") {
+        tokenizer.advance_bytes(2);
+        *tokenizer.user_state::<usize>() += 1;
+    } else if tokenizer.has_prefix("*/") {
+        tokenizer.advance_bytes(2);
+        *tokenizer.user_state::<usize>() -= 1;
+
+        if *tokenizer.user_state::<usize>() == 0 {
+            tokenizer.tokenize_with_scope(category, scope);
+            return Some(StateFunction(initial_state))
         }
+    } else if tokenizer.current_char().is_some() {
+        tokenizer.advance();
+    } else {
+        tokenizer.report(Kind::UnterminatedComment, "unterminated comment");
+        tokenizer.tokenize_with_scope(category, scope);
+        return None
     }
+
+    Some(state)
+}
+
+// Tokenizes the integer or floating-point literal at the current
+// position, along with its type suffix (e.g. `0xFF_u8` or `1.5e-3f32`).
+fn number(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    let float = regex!(
+        r"[0-9][0-9_]*(\.[0-9][0-9_]*([eE][+-]?_*[0-9][0-9_]*)?|[eE][+-]?_*[0-9][0-9_]*)(f32|f64)?|[0-9][0-9_]*(f32|f64)"
+    );
+    let integer = regex!(r"(0x[0-9A-Fa-f_]+|0o[0-7_]+|0b[01_]+|[0-9][0-9_]*)([iu](8|16|32|64|128|size))?");
+
+    if let Some(length) = tokenizer.match_regex(float) {
+        tokenizer.tokenize_next_with_scope(length, Category::Float, "constant.numeric.float.rust");
+    } else if let Some(length) = tokenizer.match_regex(regex!(r"[0-9][0-9_]*\.")).filter(|_| {
+        // A trailing dot belongs to the literal (e.g. `2.`), unless it begins
+        // a range, or a method call or field access (e.g. `2.pow(3)`).
+        tokenizer.match_regex(regex!(r"[0-9][0-9_]*\.[.\p{XID_Start}_]")).is_none()
+    }) {
+        tokenizer.tokenize_next_with_scope(length, Category::Float, "constant.numeric.float.rust");
+    } else if let Some(length) = tokenizer.match_regex(integer) {
+        tokenizer.tokenize_next_with_scope(length, Category::Integer, "constant.numeric.integer.rust");
+    }
+
+    Some(StateFunction(operand))
 }
 
 fn attribute(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
@@ -367,7 +750,7 @@ fn attribute(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
             match c {
                 ']' => {
                     tokenizer.advance();
-                    tokenizer.tokenize(Category::Identifier);
+                    tokenizer.tokenize_with_scope(Category::Attribute, "meta.attribute.rust");
                     Some(StateFunction(initial_state))
                 },
                 _ => {
@@ -378,93 +761,602 @@ fn attribute(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         }
 
         None => {
-            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_with_scope(Category::Attribute, "meta.attribute.rust");
             None
         }
     }
 }
 
+/// Lexes the data lazily, producing tokens that borrow their lexemes
+/// from the data, rather than allocating a copy for each of them.
+pub fn lex_borrowed(data: &str) -> Tokens<'_> {
+    Tokenizer::new(data).run(StateFunction(initial_state))
+}
+
+/// Lexes the data lazily, running the lexer's
+/// state functions only as tokens are requested.
+pub fn lex_iter(data: &str) -> impl Iterator<Item=Token> + '_ {
+    lex_borrowed(data).map(Token::from)
+}
+
 pub fn lex(data: &str) -> Vec<Token> {
-    let mut tokenizer = Tokenizer::new(data);
-    let mut state_function = StateFunction(initial_state);
-    loop {
-        let StateFunction(actual_function) = state_function;
-        match actual_function(&mut tokenizer) {
-            Some(f) => state_function = f,
-            None => {
-                match tokenizer.states.pop() {
-                    Some(f) => state_function = f,
-                    None => return tokenizer.tokens(),
-                }
-            }
+    lex_iter(data).collect()
+}
+
+/// Lexes a Rust document, reporting any malformed input it contains.
+pub fn lex_with_diagnostics(data: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+    let mut tokens = lex_borrowed(data);
+    let lexed = tokens.by_ref().map(Token::from).collect();
+
+    (lexed, tokens.into_diagnostics())
+}
+
+/// The Rust lexer, exposed through the common `Lexer` interface.
+pub struct RustLexer;
+
+impl Lexer for RustLexer {
+    fn name(&self) -> &str { "Rust" }
+    fn aliases(&self) -> &[&str] { &["rust", "rs"] }
+    fn extensions(&self) -> &[&str] { &["rs"] }
+    fn mime_types(&self) -> &[&str] { &["text/rust", "text/x-rust"] }
+    fn interpreters(&self) -> &[&str] { &["rust-script", "run-cargo-script"] }
+
+    fn analyse(&self, data: &str) -> f32 {
+        if data.contains("fn main(") {
+            0.5
+        } else if data.contains("use std::") || data.contains("pub fn ") {
+            0.3
+        } else {
+            0.0
         }
     }
+
+    fn lex(&self, data: &str) -> Vec<Token> { lex(data) }
+    fn lex_with_diagnostics(&self, data: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+        lex_with_diagnostics(data)
+    }
+    fn lex_iter<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=Token> + 'a> { Box::new(lex_iter(data)) }
+    fn lex_borrowed<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=BorrowedToken<'a>> + 'a> {
+        Box::new(lex_borrowed(data))
+    }
+    fn initial_state(&self) -> Option<StateFunction> { Some(StateFunction(initial_state)) }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::lex;
-    use token::Token;
+    use super::{lex, lex_with_diagnostics};
+    use diagnostic::Kind;
     use token::Category;
 
     #[test]
     fn it_works() {
         let data = include_str!("../../test_data/rust.rs");
         let tokens = lex(data);
-        let expected_tokens = vec![
-            Token{ lexeme: "extern".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "crate".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "luthor".to_string(), category: Category::Identifier },
-            Token{ lexeme: ";".to_string(), category: Category::Text },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "use".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "luthor".to_string(), category: Category::Identifier },
-            Token{ lexeme: ";".to_string(), category: Category::Text },
-            Token{ lexeme: "\n\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "#[attr]".to_string(), category: Category::Identifier },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "pub".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "fn".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "main".to_string(), category: Category::Function },
-            Token{ lexeme: "(".to_string(), category: Category::Text },
-            Token{ lexeme: ")".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "{".to_string(), category: Category::Text },
-            Token{ lexeme: "\n    ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "let".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "mut".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "variable".to_string(), category: Category::Identifier },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "=".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "\"string\"".to_string(), category: Category::String },
-            Token{ lexeme: ";".to_string(), category: Category::Text },
-            Token{ lexeme: "\n    ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "'loop_name:".to_string(), category: Category::Identifier },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "for".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "value".to_string(), category: Category::Identifier },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "in".to_string(), category: Category::Keyword },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "collection".to_string(), category: Category::Identifier },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "{}".to_string(), category: Category::Text },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "}".to_string(), category: Category::Text },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
+        let expected_tokens = [
+            ("extern", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("crate", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("luthor", Category::Identifier),
+            (";", Category::Punctuation),
+            ("\n", Category::Whitespace),
+            ("use", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("luthor", Category::Identifier),
+            (";", Category::Punctuation),
+            ("\n\n", Category::Whitespace),
+            ("#[attr]", Category::Attribute),
+            ("\n", Category::Whitespace),
+            ("pub", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("fn", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("main", Category::Function),
+            ("(", Category::Parenthesis),
+            (")", Category::Parenthesis),
+            (" ", Category::Whitespace),
+            ("{", Category::Brace),
+            ("\n    ", Category::Whitespace),
+            ("let", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("mut", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("variable", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("\"string\"", Category::String),
+            (";", Category::Punctuation),
+            ("\n    ", Category::Whitespace),
+            ("'loop_name", Category::Label),
+            (":", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("for", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("value", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("in", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("collection", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("{", Category::Brace),
+            ("}", Category::Brace),
+            ("\n", Category::Whitespace),
+            ("}", Category::Brace),
+            ("\n", Category::Whitespace),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
+        }
+    }
+
+    #[test]
+    fn it_assigns_scopes() {
+        let tokens = lex("pub fn run() {}");
+        let expected_tokens = [
+            ("pub", Some("storage.modifier.visibility.rust")),
+            (" ", None),
+            ("fn", Some("keyword.other.fn.rust")),
+            (" ", None),
+            ("run", Some("entity.name.function.rust")),
+            ("(", None),
+            (")", None),
+            (" ", None),
+            ("{", None),
+            ("}", None),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, (lexeme, scope)) in expected_tokens.iter().enumerate() {
+            assert_eq!(tokens[index].lexeme, *lexeme);
+            assert_eq!(tokens[index].scope.as_deref(), *scope);
+        }
+    }
+
+    #[test]
+    fn it_identifies_punctuation_macros_and_namespaces() {
+        let tokens = lex("std::mem::swap(a, b);\nprintln!(\"{}\", x - 1);\n");
+        let expected_tokens = [
+            ("std", Category::Namespace),
+            ("::", Category::Punctuation),
+            ("mem", Category::Namespace),
+            ("::", Category::Punctuation),
+            ("swap", Category::Call),
+            ("(", Category::Parenthesis),
+            ("a", Category::Identifier),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("b", Category::Identifier),
+            (")", Category::Parenthesis),
+            (";", Category::Punctuation),
+            ("\n", Category::Whitespace),
+            ("println!", Category::Macro),
+            ("(", Category::Parenthesis),
+            ("\"{}\"", Category::String),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("x", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("-", Category::Operator),
+            (" ", Category::Whitespace),
+            ("1", Category::Integer),
+            (")", Category::Parenthesis),
+            (";", Category::Punctuation),
+            ("\n", Category::Whitespace),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+        }
+    }
+
+    #[test]
+    fn it_identifies_keywords_types_and_operators() {
+        let tokens = lex("pub(crate) struct S<T> where T: Copy;\nif x >= 1 && !done { y <<= 2 } else { return r#type? }\n");
+        let expected_tokens = [
+            ("pub", Category::Keyword),
+            ("(", Category::Parenthesis),
+            ("crate", Category::Keyword),
+            (")", Category::Parenthesis),
+            (" ", Category::Whitespace),
+            ("struct", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("S", Category::Type),
+            ("<", Category::Operator),
+            ("T", Category::Identifier),
+            (">", Category::Operator),
+            (" ", Category::Whitespace),
+            ("where", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("T", Category::Identifier),
+            (":", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("Copy", Category::Identifier),
+            (";", Category::Punctuation),
+            ("\n", Category::Whitespace),
+            ("if", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("x", Category::Identifier),
+            (" ", Category::Whitespace),
+            (">=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("1", Category::Integer),
+            (" ", Category::Whitespace),
+            ("&&", Category::Operator),
+            (" ", Category::Whitespace),
+            ("!", Category::Operator),
+            ("done", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("{", Category::Brace),
+            (" ", Category::Whitespace),
+            ("y", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("<<=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("2", Category::Integer),
+            (" ", Category::Whitespace),
+            ("}", Category::Brace),
+            (" ", Category::Whitespace),
+            ("else", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("{", Category::Brace),
+            (" ", Category::Whitespace),
+            ("return", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("r#type", Category::Identifier),
+            ("?", Category::Operator),
+            (" ", Category::Whitespace),
+            ("}", Category::Brace),
+            ("\n", Category::Whitespace),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+        }
+    }
+
+    #[test]
+    fn it_lexes_every_string_literal_form() {
+        let tokens = lex(r##"r#"say "hi""#, br"\n", b"\x7f", c"\u{1F600}", b'x', "\t\"", "\q""##);
+        let expected_tokens = [
+            ("r#\"say \"hi\"\"#", Category::String),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("br\"\\n\"", Category::String),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("b\"", Category::String),
+            ("\\x7f", Category::Escape),
+            ("\"", Category::String),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("c\"", Category::String),
+            ("\\u{1F600}", Category::Escape),
+            ("\"", Category::String),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("b'x'", Category::String),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("\"", Category::String),
+            ("\\t", Category::Escape),
+            ("\\\"", Category::Escape),
+            ("\"", Category::String),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("\"", Category::String),
+            ("\\q", Category::Error),
+            ("\"", Category::String),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+        }
+    }
+
+    #[test]
+    fn it_reports_invalid_escapes_and_unterminated_raw_strings() {
+        let (tokens, diagnostics) = lex_with_diagnostics("\"\\q\" r#\"open\"");
+
+        assert_eq!(tokens.last().unwrap().lexeme, "r#\"open\"");
+        assert_eq!(diagnostics[0].kind, Kind::InvalidEscape);
+        assert_eq!(diagnostics[0].span.range(), 1..3);
+        assert_eq!(diagnostics[1].kind, Kind::UnterminatedString);
+    }
+
+    #[test]
+    fn it_limits_unicode_escapes_to_six_digits_outside_of_byte_literals() {
+        let data = "\"\\u{10FFFF}\" \"\\u{1234567}\" b\"\\u{41}\" b'\\u{41}' c\"\\u{41}\"";
+        let (tokens, diagnostics) = lex_with_diagnostics(data);
+        let escapes: Vec<_> = tokens.iter()
+            .filter(|token| token.category == Category::Escape)
+            .map(|token| token.lexeme.as_str())
+            .collect();
+
+        assert_eq!(escapes, ["\\u{10FFFF}", "\\u{41}"]);
+        assert_eq!(diagnostics.len(), 3);
+        for diagnostic in diagnostics.iter() {
+            assert_eq!(diagnostic.kind, Kind::InvalidEscape);
+            assert_eq!(&data[diagnostic.span.range()], "\\u");
+        }
+    }
+
+    #[test]
+    fn it_distinguishes_characters_lifetimes_and_labels() {
+        let tokens = lex("impl<'a, 'b: 'a> X<'a> for &'static str {}\n'outer: loop { break 'outer; }\n['x', '\\n', b'\\x7f', '\\u{1F600}']");
+        let expected_tokens = [
+            ("impl", Category::Keyword),
+            ("<", Category::Operator),
+            ("'a", Category::Lifetime),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("'b", Category::Lifetime),
+            (":", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("'a", Category::Lifetime),
+            (">", Category::Operator),
+            (" ", Category::Whitespace),
+            ("X", Category::Identifier),
+            ("<", Category::Operator),
+            ("'a", Category::Lifetime),
+            (">", Category::Operator),
+            (" ", Category::Whitespace),
+            ("for", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("&", Category::Operator),
+            ("'static", Category::Lifetime),
+            (" ", Category::Whitespace),
+            ("str", Category::Type),
+            (" ", Category::Whitespace),
+            ("{", Category::Brace),
+            ("}", Category::Brace),
+            ("\n", Category::Whitespace),
+            ("'outer", Category::Label),
+            (":", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("loop", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("{", Category::Brace),
+            (" ", Category::Whitespace),
+            ("break", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("'outer", Category::Label),
+            (";", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("}", Category::Brace),
+            ("\n", Category::Whitespace),
+            ("[", Category::Bracket),
+            ("'x'", Category::String),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("'", Category::String),
+            ("\\n", Category::Escape),
+            ("'", Category::String),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("b'", Category::String),
+            ("\\x7f", Category::Escape),
+            ("'", Category::String),
+            (",", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("'", Category::String),
+            ("\\u{1F600}", Category::Escape),
+            ("'", Category::String),
+            ("]", Category::Bracket),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+        }
+    }
+
+    #[test]
+    fn it_reports_unexpected_quotes() {
+        let (tokens, diagnostics) = lex_with_diagnostics("x = ' 1");
+
+        assert_eq!(tokens[4].lexeme, "'");
+        assert_eq!(tokens[4].category, Category::Error);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, Kind::UnexpectedCharacter);
+        assert_eq!(diagnostics[0].span.range(), 4..5);
+    }
+
+    #[test]
+    fn it_reports_unexpected_characters() {
+        let (tokens, diagnostics) = lex_with_diagnostics("let a¿ = 1;");
+        assert_eq!(tokens[3].lexeme, "¿");
+        assert_eq!(tokens[3].category, Category::Error);
+        assert_eq!(tokens[3].scope.as_deref(), Some("invalid.illegal.character.rust"));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, Kind::UnexpectedCharacter);
+        assert_eq!(diagnostics[0].span.range(), 5..7);
+    }
+
+    #[test]
+    fn it_lexes_numeric_literals() {
+        let tokens = lex("0xFF_u8 1_000_000 0b1010 0o777 1.5e-3f32 2. 1E+5_f64 7f32 3usize 1..2 2.pow()");
+        let expected_tokens = [
+            ("0xFF_u8", Category::Integer),
+            (" ", Category::Whitespace),
+            ("1_000_000", Category::Integer),
+            (" ", Category::Whitespace),
+            ("0b1010", Category::Integer),
+            (" ", Category::Whitespace),
+            ("0o777", Category::Integer),
+            (" ", Category::Whitespace),
+            ("1.5e-3f32", Category::Float),
+            (" ", Category::Whitespace),
+            ("2.", Category::Float),
+            (" ", Category::Whitespace),
+            ("1E+5_f64", Category::Float),
+            (" ", Category::Whitespace),
+            ("7f32", Category::Float),
+            (" ", Category::Whitespace),
+            ("3usize", Category::Integer),
+            (" ", Category::Whitespace),
+            ("1", Category::Integer),
+            ("..", Category::Operator),
+            ("2", Category::Integer),
+            (" ", Category::Whitespace),
+            ("2", Category::Integer),
+            (".", Category::Punctuation),
+            ("pow", Category::Call),
+            ("(", Category::Parenthesis),
+            (")", Category::Parenthesis),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+        }
+    }
+
+    #[test]
+    fn it_lexes_nested_block_comments_and_doc_comments() {
+        let tokens = lex("//! Crate.\n/// Item.\n//// Plain.\n/** Block. */ /*! Inner. */ /* a /* b */ c */ x/**/;");
+        let expected_tokens = [
+            ("//! Crate.", Category::DocComment),
+            ("\n", Category::Whitespace),
+            ("/// Item.", Category::DocComment),
+            ("\n", Category::Whitespace),
+            ("//// Plain.", Category::Comment),
+            ("\n", Category::Whitespace),
+            ("/** Block. */", Category::DocComment),
+            (" ", Category::Whitespace),
+            ("/*! Inner. */", Category::DocComment),
+            (" ", Category::Whitespace),
+            ("/* a /* b */ c */", Category::Comment),
+            (" ", Category::Whitespace),
+            ("x", Category::Identifier),
+            ("/**/", Category::Comment),
+            (";", Category::Punctuation),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+        }
+    }
+
+    #[test]
+    fn it_reports_unterminated_block_comments() {
+        let (tokens, diagnostics) = lex_with_diagnostics("/* a /* b */");
+
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].category, Category::Comment);
+        assert_eq!(diagnostics[0].kind, Kind::UnterminatedComment);
+    }
+
+    #[test]
+    fn it_separates_arguments_operators_and_closures() {
+        let data = "fn f(x: u32) {}\nf(a+b);\nlet z = a | b;\nlet g = |x| x;\n";
+        let tokens = lex(data);
+        let expected_tokens = [
+            ("fn", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("f", Category::Function),
+            ("(", Category::Parenthesis),
+            ("x", Category::Identifier),
+            (":", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("u32", Category::Type),
+            (")", Category::Parenthesis),
+            (" ", Category::Whitespace),
+            ("{", Category::Brace),
+            ("}", Category::Brace),
+            ("\n", Category::Whitespace),
+            ("f", Category::Call),
+            ("(", Category::Parenthesis),
+            ("a", Category::Identifier),
+            ("+", Category::Operator),
+            ("b", Category::Identifier),
+            (")", Category::Parenthesis),
+            (";", Category::Punctuation),
+            ("\n", Category::Whitespace),
+            ("let", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("z", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("a", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("|", Category::Operator),
+            (" ", Category::Whitespace),
+            ("b", Category::Identifier),
+            (";", Category::Punctuation),
+            ("\n", Category::Whitespace),
+            ("let", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("g", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("=", Category::Operator),
+            (" ", Category::Whitespace),
+            ("|", Category::Punctuation),
+            ("x", Category::Identifier),
+            ("|", Category::Punctuation),
+            (" ", Category::Whitespace),
+            ("x", Category::Identifier),
+            (";", Category::Punctuation),
+            ("\n", Category::Whitespace),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
         }
     }
+
+    #[test]
+    fn it_lexes_tuple_indices_as_integers() {
+        let data = "t.0.1 + x.1 * 1.5";
+        let tokens = lex(data);
+        let expected_tokens = [
+            ("t", Category::Identifier),
+            (".", Category::Punctuation),
+            ("0", Category::Integer),
+            (".", Category::Punctuation),
+            ("1", Category::Integer),
+            (" ", Category::Whitespace),
+            ("+", Category::Operator),
+            (" ", Category::Whitespace),
+            ("x", Category::Identifier),
+            (".", Category::Punctuation),
+            ("1", Category::Integer),
+            (" ", Category::Whitespace),
+            ("*", Category::Operator),
+            (" ", Category::Whitespace),
+            ("1.5", Category::Float),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
+        }
+    }
+
+    #[test]
+    fn it_categorizes_tokens_ending_the_data() {
+        let tokens = lex("fn main");
+        assert_eq!(tokens.last().unwrap().lexeme, "main");
+        assert_eq!(tokens.last().unwrap().category, Category::Function);
+    }
 }
@@ -1,18 +1,24 @@
 //! A simple lexer for XML documents.
 
-use token::{Category, Token};
-use tokenizer::{Tokenizer, StateFunction};
+use diagnostic::{Diagnostic, Kind};
+use lexers::{is_unexpected, Lexer};
+use token::{BorrowedToken, Category, Token};
+use tokenizer::{Tokenizer, StateFunction, Tokens};
 
 fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     match tokenizer.current_char() {
         Some(c) => {
-            if tokenizer.has_prefix("</") {
-                tokenizer.tokenize_next(2, Category::Text);
+            if tokenizer.has_prefix("<!--") {
+                tokenizer.tokenize(Category::Text);
+                tokenizer.advance_bytes(4);
+                return Some(StateFunction(comment))
+            } else if tokenizer.has_prefix("</") {
+                tokenizer.tokenize_next_with_scope(2, Category::Punctuation, "punctuation.definition.tag.begin.xml");
                 return Some(StateFunction(inside_tag))
             }
             match c {
                 '<' => {
-                    tokenizer.tokenize_next(1, Category::Text);
+                    tokenizer.tokenize_next_with_scope(1, Category::Punctuation, "punctuation.definition.tag.begin.xml");
                     return Some(StateFunction(start_of_tag));
                 },
                 ' ' | '\n' => {
@@ -41,15 +47,20 @@ fn start_of_tag(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         Some(c) => {
             match c {
                 ' ' | '\n' => {
-                    tokenizer.tokenize(Category::Identifier);
+                    tokenizer.tokenize_with_scope(Category::Identifier, "entity.name.tag.xml");
                     tokenizer.states.push(StateFunction(inside_tag));
-                    return Some(StateFunction(whitespace));
+                    Some(StateFunction(whitespace))
                 },
                 '>' => {
-                    tokenizer.tokenize(Category::Identifier);
-                    tokenizer.tokenize_next(1, Category::Text);
+                    tokenizer.tokenize_with_scope(Category::Identifier, "entity.name.tag.xml");
+                    tokenizer.tokenize_next_with_scope(1, Category::Punctuation, "punctuation.definition.tag.end.xml");
                     Some(StateFunction(initial_state))
                 }
+                _ if is_unexpected(c) => {
+                    tokenizer.tokenize_with_scope(Category::Identifier, "entity.name.tag.xml");
+                    unexpected_character(tokenizer);
+                    Some(StateFunction(start_of_tag))
+                }
                 _ => {
                     tokenizer.advance();
                     Some(StateFunction(start_of_tag))
@@ -58,7 +69,7 @@ fn start_of_tag(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         }
 
         None => {
-            tokenizer.tokenize(Category::Identifier);
+            tokenizer.tokenize_with_scope(Category::Identifier, "entity.name.tag.xml");
             None
         }
     }
@@ -77,22 +88,27 @@ fn inside_tag(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
                     tokenizer.tokenize(Category::Identifier);
                     tokenizer.advance();
                     tokenizer.states.push(StateFunction(inside_tag));
-                    return Some(StateFunction(whitespace));
+                    Some(StateFunction(whitespace))
                 },
                 '=' => {
-                    tokenizer.tokenize(Category::Identifier);
-                    tokenizer.tokenize_next(1, Category::Operator);
+                    tokenizer.tokenize_with_scope(Category::Attribute, "entity.other.attribute-name.xml");
+                    tokenizer.tokenize_next_with_scope(1, Category::Punctuation, "punctuation.separator.key-value.xml");
                     Some(StateFunction(inside_tag))
                 }
                 '>' => {
                     tokenizer.tokenize(Category::Identifier);
-                    tokenizer.tokenize_next(1, Category::Text);
+                    tokenizer.tokenize_next_with_scope(1, Category::Punctuation, "punctuation.definition.tag.end.xml");
                     Some(StateFunction(initial_state))
                 }
+                _ if is_unexpected(c) => {
+                    tokenizer.tokenize(Category::Identifier);
+                    unexpected_character(tokenizer);
+                    Some(StateFunction(inside_tag))
+                }
                 _ => {
                     if tokenizer.has_prefix("/>") {
                         tokenizer.tokenize(Category::Identifier);
-                        tokenizer.tokenize_next(2, Category::Text);
+                        tokenizer.tokenize_next_with_scope(2, Category::Punctuation, "punctuation.definition.tag.end.xml");
                         return Some(StateFunction(initial_state))
                     }
 
@@ -115,7 +131,7 @@ fn inside_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
             match c {
                 '"' => {
                     tokenizer.advance();
-                    tokenizer.tokenize(Category::String);
+                    tokenizer.tokenize_with_scope(Category::String, "string.quoted.double.xml");
                     Some(StateFunction(inside_tag))
                 },
                 '\\' => {
@@ -131,12 +147,41 @@ fn inside_string(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
         }
 
         None => {
-            tokenizer.tokenize(Category::String);
+            tokenizer.report(Kind::UnterminatedString, "unterminated string");
+            tokenizer.tokenize_with_scope(Category::String, "string.quoted.double.xml");
             None
         }
     }
 }
 
+fn comment(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    if tokenizer.has_prefix("-->") {
+        tokenizer.advance_bytes(3);
+        tokenizer.tokenize_with_scope(Category::Comment, "comment.block.xml");
+        return Some(StateFunction(initial_state))
+    }
+
+    match tokenizer.current_char() {
+        Some(_) => {
+            tokenizer.advance();
+            Some(StateFunction(comment))
+        },
+        None => {
+            tokenizer.report(Kind::UnterminatedComment, "unterminated comment");
+            tokenizer.tokenize_with_scope(Category::Comment, "comment.block.xml");
+            None
+        }
+    }
+}
+
+// Reports and tokenizes the character at the current position,
+// which can't appear in the names of tags and attributes.
+fn unexpected_character(tokenizer: &mut Tokenizer) {
+    tokenizer.advance();
+    tokenizer.report(Kind::UnexpectedCharacter, "unexpected character");
+    tokenizer.tokenize_with_scope(Category::Error, "invalid.illegal.character.xml");
+}
+
 fn whitespace(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     match tokenizer.current_char() {
         Some(c) => {
@@ -159,101 +204,188 @@ fn whitespace(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
     }
 }
 
+/// Lexes the data lazily, producing tokens that borrow their lexemes
+/// from the data, rather than allocating a copy for each of them.
+pub fn lex_borrowed(data: &str) -> Tokens<'_> {
+    Tokenizer::new(data).run(StateFunction(initial_state))
+}
+
+/// Lexes the data lazily, running the lexer's
+/// state functions only as tokens are requested.
+pub fn lex_iter(data: &str) -> impl Iterator<Item=Token> + '_ {
+    lex_borrowed(data).map(Token::from)
+}
+
 /// Lexes an XML document.
 pub fn lex(data: &str) -> Vec<Token> {
-    let mut tokenizer = Tokenizer::new(data);
-    let mut state_function = StateFunction(initial_state);
-    loop {
-        let StateFunction(actual_function) = state_function;
-        match actual_function(&mut tokenizer) {
-            Some(f) => state_function = f,
-            None => return tokenizer.tokens(),
+    lex_iter(data).collect()
+}
+
+/// Lexes an XML document, reporting any malformed input it contains.
+pub fn lex_with_diagnostics(data: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+    let mut tokens = lex_borrowed(data);
+    let lexed = tokens.by_ref().map(Token::from).collect();
+
+    (lexed, tokens.into_diagnostics())
+}
+
+/// The XML lexer, exposed through the common `Lexer` interface.
+pub struct XmlLexer;
+
+impl Lexer for XmlLexer {
+    fn name(&self) -> &str { "XML" }
+    fn aliases(&self) -> &[&str] { &["xml", "html"] }
+    fn extensions(&self) -> &[&str] { &["xml", "xsd", "xsl", "svg", "html", "htm"] }
+    fn mime_types(&self) -> &[&str] { &["application/xml", "text/xml", "text/html"] }
+    fn analyse(&self, data: &str) -> f32 {
+        let data = data.trim_start();
+        let head: String = data.chars().take(15).collect::<String>().to_lowercase();
+
+        if data.starts_with("<?xml") {
+            0.9
+        } else if head.starts_with("<!doctype html") || head.starts_with("<html") {
+            0.5
+        } else if data.starts_with('<') {
+            0.2
+        } else {
+            0.0
         }
     }
+
+    fn lex(&self, data: &str) -> Vec<Token> { lex(data) }
+    fn lex_with_diagnostics(&self, data: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+        lex_with_diagnostics(data)
+    }
+    fn lex_iter<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=Token> + 'a> { Box::new(lex_iter(data)) }
+    fn lex_borrowed<'a>(&self, data: &'a str) -> Box<dyn Iterator<Item=BorrowedToken<'a>> + 'a> {
+        Box::new(lex_borrowed(data))
+    }
+    fn initial_state(&self) -> Option<StateFunction> { Some(StateFunction(initial_state)) }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::lex;
-    use token::Token;
+    use super::{lex, lex_with_diagnostics};
+    use diagnostic::Kind;
     use token::Category;
 
     #[test]
     fn it_works() {
         let data = include_str!("../../test_data/data.xml");
         let tokens = lex(data);
-        let expected_tokens = vec![
-            Token{ lexeme: "<".to_string(), category: Category::Text },
-            Token{ lexeme: "tag".to_string(), category: Category::Identifier },
-            Token{ lexeme: ">".to_string(), category: Category::Text },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "<".to_string(), category: Category::Text },
-            Token{ lexeme: "tag_with_attribute".to_string(), category: Category::Identifier },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "attribute".to_string(), category: Category::Identifier },
-            Token{ lexeme: "=".to_string(), category: Category::Operator },
-            Token{ lexeme: "\"value\"".to_string(), category: Category::String },
-            Token{ lexeme: ">".to_string(), category: Category::Text },
-            Token{ lexeme: "text".to_string(), category: Category::Text },
-            Token{ lexeme: "</".to_string(), category: Category::Text },
-            Token{ lexeme: "tag_with_attribute".to_string(), category: Category::Identifier },
-            Token{ lexeme: ">".to_string(), category: Category::Text },
-            Token{ lexeme: "\n  ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "<".to_string(), category: Category::Text },
-            Token{ lexeme: "self_closing_tag".to_string(), category: Category::Identifier },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "/>".to_string(), category: Category::Text },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "</".to_string(), category: Category::Text },
-            Token{ lexeme: "tag".to_string(), category: Category::Identifier },
-            Token{ lexeme: ">".to_string(), category: Category::Text },
-            Token{ lexeme: "\n".to_string(), category: Category::Whitespace },
+        let expected_tokens = [
+            ("<", Category::Punctuation),
+            ("tag", Category::Identifier),
+            (">", Category::Punctuation),
+            ("\n  ", Category::Whitespace),
+            ("<", Category::Punctuation),
+            ("tag_with_attribute", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("attribute", Category::Attribute),
+            ("=", Category::Punctuation),
+            ("\"value\"", Category::String),
+            (">", Category::Punctuation),
+            ("text", Category::Text),
+            ("</", Category::Punctuation),
+            ("tag_with_attribute", Category::Identifier),
+            (">", Category::Punctuation),
+            ("\n  ", Category::Whitespace),
+            ("<", Category::Punctuation),
+            ("self_closing_tag", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("/>", Category::Punctuation),
+            ("\n", Category::Whitespace),
+            ("</", Category::Punctuation),
+            ("tag", Category::Identifier),
+            (">", Category::Punctuation),
+            ("\n", Category::Whitespace),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
         }
     }
 
     #[test]
     fn it_can_handle_garbage() {
         let tokens = lex("} adwyx123&*_ ");
-        let expected_tokens = vec![
-            Token{ lexeme: "}".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "adwyx123&*_".to_string(), category: Category::Text },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
+        let expected_tokens = [
+            ("}", Category::Text),
+            (" ", Category::Whitespace),
+            ("adwyx123&*_", Category::Text),
+            (" ", Category::Whitespace),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
         }
     }
 
     #[test]
     fn it_can_handle_open_strings() {
         let tokens = lex("<tag \"open!>");
-        let expected_tokens = vec![
-            Token{ lexeme: "<".to_string(), category: Category::Text },
-            Token{ lexeme: "tag".to_string(), category: Category::Identifier },
-            Token{ lexeme: " ".to_string(), category: Category::Whitespace },
-            Token{ lexeme: "\"open!>".to_string(), category: Category::String },
+        let expected_tokens = [
+            ("<", Category::Punctuation),
+            ("tag", Category::Identifier),
+            (" ", Category::Whitespace),
+            ("\"open!>", Category::String),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
         }
     }
 
     #[test]
     fn it_can_handle_utf8_data() {
         let tokens = lex("différent");
-        let expected_tokens = vec![
-            Token{ lexeme: "différent".to_string(), category: Category::Text },
+        let expected_tokens = [
+            ("différent", Category::Text),
         ];
 
+        assert_eq!(tokens.len(), expected_tokens.len());
         for (index, token) in tokens.iter().enumerate() {
-            assert_eq!(*token, expected_tokens[index]);
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
         }
     }
+
+    #[test]
+    fn it_reports_comments_and_unexpected_characters() {
+        let data = "<!-- a -->\n<a¿ b>";
+        let (tokens, diagnostics) = lex_with_diagnostics(data);
+        let expected_tokens = [
+            ("<!-- a -->", Category::Comment),
+            ("\n", Category::Whitespace),
+            ("<", Category::Punctuation),
+            ("a", Category::Identifier),
+            ("¿", Category::Error),
+            (" ", Category::Whitespace),
+            ("b", Category::Identifier),
+            (">", Category::Punctuation),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.lexeme, expected_tokens[index].0);
+            assert_eq!(token.category, expected_tokens[index].1);
+            assert_eq!(&data[token.span.range()], token.lexeme);
+        }
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, Kind::UnexpectedCharacter);
+        assert_eq!(diagnostics[0].span.range(), 13..15);
+
+        let (tokens, diagnostics) = lex_with_diagnostics("<a/><!-- b");
+        assert_eq!(tokens.last().unwrap().lexeme, "<!-- b");
+        assert_eq!(tokens.last().unwrap().category, Category::Comment);
+        assert_eq!(diagnostics[0].kind, Kind::UnterminatedComment);
+        assert_eq!(diagnostics[0].span.range(), 4..10);
+    }
 }
@@ -1,7 +1,26 @@
 //! Luthor provides a collection of lexers for various formats and languages.
-//! It also exposes types that aid in building lexers of your own.
+//! It also exposes types that aid in building lexers of your own, as well as
+//! the `lexer_states!` macro, which describes lexers declaratively.
+extern crate regex_automata;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
+#[macro_use]
+pub mod regex;
+#[macro_use]
+mod rules;
+
+pub mod detection;
+pub mod diagnostic;
+pub mod incremental;
+pub mod injection;
 pub mod lexers;
+pub mod lines;
+pub mod render;
+pub mod theme;
 pub mod token;
 mod tokenizer;
 
-pub use tokenizer::{Tokenizer, StateFunction};
+pub use tokenizer::{Tokenizer, StateFunction, Tokens, UserState};
@@ -0,0 +1,252 @@
+//! Line-oriented lexing, for editors that highlight documents a line at a
+//! time, caching the lexer's state at the start of each line.
+//!
+//! Each line is lexed on its own, starting from the state in which the
+//! previous line ended (see `LineState`). Line states are comparable, so
+//! when a line is edited, it can be lexed again from its cached start state,
+//! followed by the lines after it, until a line ends in the same state as it
+//! did before the edit; the lines beyond that point are unaffected.
+//!
+//! Tokens never span lines when lexed this way; those that would (e.g.
+//! multi-line strings) are split at the end of each line. As lexers can't see
+//! beyond the end of the line being lexed, the categories assigned to tokens
+//! may occasionally differ from those produced by lexing the whole document.
+//!
+//! Only lexers that provide an initial state function (see `Lexer`) can be
+//! lexed line by line.
+//!
+//! # Examples
+//!
+//! ```
+//! use luthor::lexers::rust::RustLexer;
+//! use luthor::lines::{lex_line, LineState};
+//! use luthor::token::Category;
+//!
+//! let lines = ["let s = \"multi\n", "line\";\n", "let x = 1;\n"];
+//!
+//! // Lex each line, caching the state in which it begins.
+//! let mut states = vec![LineState::initial(&RustLexer).unwrap()];
+//! for line in &lines {
+//!     let state = lex_line(line, states.last().unwrap()).state;
+//!     states.push(state);
+//! }
+//!
+//! // The second line begins inside of a string.
+//! let line = lex_line(lines[1], &states[1]);
+//! assert_eq!(line.tokens[0].lexeme, "line\"");
+//! assert_eq!(line.tokens[0].category, Category::String);
+//!
+//! // Editing the first line such that it no longer opens
+//! // a string changes the state in which the second begins.
+//! let state = lex_line("let s = 1;\n", &states[0]).state;
+//! assert!(state != states[1]);
+//!
+//! // Edits that don't, on the other hand, don't affect the following lines.
+//! let state = lex_line("let t = \"edited\n", &states[0]).state;
+//! assert_eq!(state, states[1]);
+//! ```
+
+use lexers::Lexer;
+use token::{Position, Token};
+use tokenizer::{StateFunction, Tokenizer, UserState};
+
+/// The state of a lexer between two lines, from which the latter can be
+/// lexed. Opaque, but comparable: lines lexed from equal states produce
+/// the same tokens.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineState {
+    state: StateFunction,
+    states: Vec<StateFunction>,
+    user_state: Option<Box<dyn UserState>>,
+}
+
+impl LineState {
+    /// The state in which the lexer begins the first line, if
+    /// the lexer provides an initial state function.
+    pub fn initial(lexer: &dyn Lexer) -> Option<LineState> {
+        lexer.initial_state().map(|state| {
+            LineState{ state, states: vec![], user_state: None }
+        })
+    }
+}
+
+/// A lexed line, along with the state in which the next line begins.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    pub tokens: Vec<Token>,
+    pub state: LineState,
+}
+
+/// Lexes a line, which should include its terminating newline (if it has
+/// one), starting from the specified state. Token spans are relative
+/// to the start of the line.
+///
+/// # Examples
+///
+/// ```
+/// use luthor::lexers::json::JsonLexer;
+/// use luthor::lines::{lex_line, LineState};
+///
+/// let state = LineState::initial(&JsonLexer).unwrap();
+/// let line = lex_line("[true,\n", &state);
+///
+/// assert_eq!(line.tokens.len(), 4);
+/// assert_eq!(line.tokens[3].lexeme, "\n");
+/// ```
+pub fn lex_line(line: &str, start: &LineState) -> Line {
+    let mut tokenizer = Tokenizer::resume(
+        line,
+        Position::default(),
+        start.states.clone(),
+        start.user_state.clone(),
+    );
+    let mut state = start.state;
+
+    loop {
+        // Stop once the line has been consumed. If it was consumed by the
+        // state function just invoked, the next line continues in that state,
+        // since it gave up only because it ran out of data (e.g. part way
+        // through a multi-line string).
+        if tokenizer.current_char().is_none() && !tokenizer.has_pending_data() {
+            break
+        }
+
+        let StateFunction(actual_function) = state;
+        match actual_function(&mut tokenizer) {
+            Some(f) => state = f,
+            None if tokenizer.current_char().is_none() => break,
+            None => match tokenizer.states.pop() {
+                Some(f) => state = f,
+                None => break,
+            }
+        }
+    }
+
+    Line{
+        tokens: tokenizer.tokens(),
+        state: LineState{
+            state,
+            states: tokenizer.states.clone(),
+            user_state: tokenizer.saved_user_state(),
+        },
+    }
+}
+
+/// Lexes the data a line at a time, starting from the specified state.
+/// Token spans are relative to the start of the data.
+///
+/// # Examples
+///
+/// ```
+/// use luthor::lexers::ruby::RubyLexer;
+/// use luthor::lines::{lex_lines, LineState};
+///
+/// let state = LineState::initial(&RubyLexer).unwrap();
+/// let lines: Vec<_> = lex_lines("x = 1\ny = 2\n", state).collect();
+///
+/// assert_eq!(lines.len(), 2);
+/// assert_eq!(lines[1].tokens[0].lexeme, "y");
+/// assert_eq!(lines[1].tokens[0].span.start.line, 1);
+/// ```
+pub fn lex_lines(data: &str, state: LineState) -> Lines<'_> {
+    Lines{ data, position: Position::default(), state }
+}
+
+/// An iterator over lexed lines, produced by `lex_lines`.
+pub struct Lines<'a> {
+    data: &'a str,
+    position: Position,
+    state: LineState,
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = Line;
+
+    fn next(&mut self) -> Option<Line> {
+        let remaining = &self.data[self.position.offset..];
+        let length = remaining.find('\n').map_or(remaining.len(), |index| index + 1);
+        if length == 0 {
+            return None
+        }
+
+        let mut line = lex_line(&remaining[..length], &self.state);
+        for token in line.tokens.iter_mut() {
+            token.span = token.span.translate(self.position);
+        }
+        self.position = line.tokens.last().map_or(self.position, |token| token.span.end);
+        self.state = line.state.clone();
+
+        Some(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lexers::{self, Lexer};
+    use token::Category;
+    use super::{lex_line, lex_lines, LineState};
+
+    // The category of each byte of the data.
+    fn categories<'a, I: Iterator<Item=&'a ::token::Token>>(tokens: I) -> Vec<Category> {
+        tokens.flat_map(|token| vec![token.category.clone(); token.lexeme.len()]).collect()
+    }
+
+    #[test]
+    fn lines_match_lexing_the_whole_document() {
+        let samples = [
+            ("Ruby", include_str!("../test_data/ruby.rb")),
+            ("Rust", include_str!("../test_data/rust.rs")),
+            ("JSON", include_str!("../test_data/data.json")),
+            ("XML", include_str!("../test_data/data.xml")),
+            ("JavaScript", include_str!("../test_data/data.js")),
+            ("CoffeeScript", include_str!("../test_data/data.coffee")),
+        ];
+        let registry = lexers::registry();
+
+        for &(name, data) in samples.iter() {
+            let lexer = registry.find_by_name(name).unwrap();
+            let lines: Vec<_> = lex_lines(data, LineState::initial(lexer).unwrap()).collect();
+            let tokens: Vec<_> = lines.iter().flat_map(|line| line.tokens.iter()).collect();
+
+            assert_eq!(lines.len(), data.lines().count());
+            assert_eq!(tokens.iter().map(|token| token.lexeme.as_str()).collect::<String>(), data);
+            assert_eq!(tokens.last().unwrap().span.end.offset, data.len());
+            assert_eq!(categories(tokens.into_iter()), categories(lexer.lex(data).iter()), "{}", name);
+        }
+    }
+
+    #[test]
+    fn multi_line_tokens_are_split_between_lines() {
+        let state = LineState::initial(&lexers::javascript::JavaScriptLexer).unwrap();
+        let lines: Vec<_> = lex_lines("a = \"one\\\ntwo\";\n", state).collect();
+
+        assert_eq!(lines[0].tokens.last().unwrap().lexeme, "\"one\\\n");
+        assert_eq!(lines[0].tokens.last().unwrap().category, Category::String);
+        assert_eq!(lines[1].tokens[0].lexeme, "two\"");
+        assert_eq!(lines[1].tokens[0].category, Category::String);
+        assert_eq!(lines[1].tokens[0].span.start.line, 1);
+    }
+
+    #[test]
+    fn equal_states_produce_equal_lines() {
+        let initial = LineState::initial(&lexers::rust::RustLexer).unwrap();
+        let first = lex_line("let x = 1;\n", &initial);
+        let second = lex_line("let y = 2;\n", &initial);
+
+        assert_eq!(first.state, second.state);
+        assert_eq!(lex_line("x\n", &first.state), lex_line("x\n", &second.state));
+        assert!(lex_line("let s = \"\n", &initial).state != first.state);
+    }
+
+    #[test]
+    fn lexers_without_initial_states_cannot_be_lexed_by_line() {
+        struct Words;
+
+        impl Lexer for Words {
+            fn name(&self) -> &str { "Words" }
+            fn lex(&self, data: &str) -> Vec<::token::Token> { lexers::default::lex(data) }
+        }
+
+        assert!(LineState::initial(&Words).is_none());
+    }
+}
@@ -0,0 +1,341 @@
+//! The `luthor` command, which lexes files (or standard input) and prints
+//! their tokens in a variety of formats. Run `luthor --help` for usage.
+extern crate luthor;
+
+use std::env;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process;
+use luthor::lexers::{self, Lexer, Registry};
+use luthor::render::{ansi, html};
+use luthor::theme::Theme;
+use luthor::token::{Position, Token};
+
+const USAGE: &str = "\
+Usage: luthor [OPTIONS] [FILE]...
+
+Lexes each FILE (or standard input, when no files are given or FILE is -)
+and prints its tokens. The language is detected from each file's name and
+content, unless one is given using --lang.
+
+Options:
+  -l, --lang NAME        lex using the named language (see --list-languages)
+  -f, --format FORMAT    print tokens as a table (the default), json, jsonl,
+                         ansi (highlighted for terminals), or html
+  -t, --theme FILE       style ansi and html output using a .tmTheme
+                         or base16 theme file
+      --list-languages   list the available languages, and exit
+  -h, --help             print this message, and exit
+  -V, --version          print the version, and exit
+";
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Format {
+    Table,
+    Json,
+    JsonLines,
+    Ansi,
+    Html,
+}
+
+#[derive(PartialEq, Debug)]
+enum Command {
+    Lex(Options),
+    ListLanguages,
+    Help,
+    Version,
+}
+
+#[derive(PartialEq, Debug)]
+struct Options {
+    lang: Option<String>,
+    format: Format,
+    theme: Option<String>,
+    files: Vec<String>,
+}
+
+fn main() {
+    let command = match parse_args(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("luthor: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let result = match command {
+        Command::Lex(options) => lex(&options),
+        Command::ListLanguages => print(&list_languages(&lexers::registry())),
+        Command::Help => print(USAGE),
+        Command::Version => print(&format!("luthor {}\n", env!("CARGO_PKG_VERSION"))),
+    };
+
+    if let Err(message) = result {
+        eprintln!("luthor: {}", message);
+        process::exit(1);
+    }
+}
+
+fn parse_args<I: Iterator<Item=String>>(mut args: I) -> Result<Command, String> {
+    let mut options = Options{ lang: None, format: Format::Table, theme: None, files: Vec::new() };
+
+    while let Some(arg) = args.next() {
+        // Support both `--option value` and `--option=value`.
+        let (name, inline_value) = match arg.find('=') {
+            Some(index) if arg.starts_with("--") => (arg[..index].to_string(), Some(arg[index + 1..].to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline_value.clone().or_else(|| args.next()).ok_or_else(|| format!("{} requires a value", name))
+        };
+
+        match name.as_str() {
+            "-l" | "--lang" => options.lang = Some(value()?),
+            "-f" | "--format" => options.format = parse_format(&value()?)?,
+            "-t" | "--theme" => options.theme = Some(value()?),
+            "--list-languages" => return Ok(Command::ListLanguages),
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--" => {
+                options.files.extend(args);
+                break
+            },
+            _ if name.starts_with('-') && name != "-" => return Err(format!("unknown option {}", name)),
+            _ => options.files.push(arg),
+        }
+    }
+
+    Ok(Command::Lex(options))
+}
+
+fn parse_format(format: &str) -> Result<Format, String> {
+    match format {
+        "table" => Ok(Format::Table),
+        "json" => Ok(Format::Json),
+        "jsonl" | "json-lines" => Ok(Format::JsonLines),
+        "ansi" => Ok(Format::Ansi),
+        "html" => Ok(Format::Html),
+        _ => Err(format!("unknown format {}", format)),
+    }
+}
+
+fn lex(options: &Options) -> Result<(), String> {
+    let registry = lexers::registry();
+    let lexer = match options.lang {
+        Some(ref name) => Some(
+            registry.find_by_name(name).ok_or_else(|| format!("unknown language {} (see --list-languages)", name))?
+        ),
+        None => None,
+    };
+    let theme = match options.theme {
+        Some(ref path) => Theme::open(path).map_err(|error| format!("{}: {}", path, error))?,
+        None => Theme::default(),
+    };
+
+    let stdin = vec!["-".to_string()];
+    let files = if options.files.is_empty() { &stdin } else { &options.files };
+
+    for file in files {
+        let data = read(file).map_err(|error| format!("{}: {}", file, error))?;
+        let lexer = lexer.unwrap_or_else(|| detect(&registry, file, &data));
+        let tokens = lexer.lex(&data);
+
+        let output = match options.format {
+            Format::Table => table(&tokens),
+            Format::Json => format!("[{}]\n", tokens.iter().map(json).collect::<Vec<String>>().join(",")),
+            Format::JsonLines => tokens.iter().map(|token| json(token) + "\n").collect(),
+            Format::Ansi => ansi::render(&tokens, &theme, ansi::ColorMode::from_env()),
+            Format::Html => format!(
+                "<style>\n{}</style>\n{}\n",
+                html::stylesheet(&theme),
+                html::render(&tokens, &html::Options::default())
+            ),
+        };
+        print(&output)?;
+    }
+
+    Ok(())
+}
+
+fn read(file: &str) -> io::Result<String> {
+    if file == "-" {
+        let mut data = String::new();
+        io::stdin().read_to_string(&mut data)?;
+
+        Ok(data)
+    } else {
+        fs::read_to_string(file)
+    }
+}
+
+// Chooses a lexer for the file, falling back to plain text.
+fn detect<'a>(registry: &'a Registry, file: &str, data: &str) -> &'a dyn Lexer {
+    let path = if file == "-" { None } else { Some(Path::new(file)) };
+
+    registry
+        .detect(path, data)
+        .map(|detection| detection.lexer)
+        .or_else(|| registry.find_by_name("text"))
+        .expect("the registry includes the default lexer")
+}
+
+fn print(output: &str) -> Result<(), String> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    match stdout.write_all(output.as_bytes()).and_then(|_| stdout.flush()) {
+        // Stop quietly when piped into a command that exits early (e.g. `head`).
+        Err(ref error) if error.kind() == io::ErrorKind::BrokenPipe => process::exit(0),
+        result => result.map_err(|error| error.to_string()),
+    }
+}
+
+fn list_languages(registry: &Registry) -> String {
+    let rows: Vec<(&str, String, String)> = registry
+        .lexers()
+        .map(|lexer| {
+            let mut names = vec![lexer.name().to_lowercase()];
+            for alias in lexer.aliases() {
+                if !names.iter().any(|name| name == alias) {
+                    names.push(alias.to_string());
+                }
+            }
+            let extensions: Vec<String> = lexer.extensions().iter().map(|extension| format!("*.{}", extension)).collect();
+
+            (lexer.name(), names.join(", "), extensions.join(", "))
+        })
+        .collect();
+    let name_width = rows.iter().map(|row| row.0.len()).max().unwrap_or(0);
+    let names_width = rows.iter().map(|row| row.1.len()).max().unwrap_or(0);
+
+    let mut output = String::new();
+    for (name, names, extensions) in rows {
+        let _ = writeln!(output, "{:<3$}  {:<4$}  {}", name, names, extensions, name_width, names_width);
+    }
+
+    output
+}
+
+// Prints a token per line, with one-based line and column numbers.
+fn table(tokens: &[Token]) -> String {
+    let mut output = String::new();
+
+    for token in tokens {
+        let position = format!("{}:{}", token.span.start.line + 1, token.span.start.column + 1);
+        let row = format!(
+            "{:<9} {:<14} {:<32} {}",
+            position,
+            token.category.name(),
+            format!("{:?}", token.lexeme),
+            token.scope.as_deref().unwrap_or("")
+        );
+        output.push_str(row.trim_end());
+        output.push('\n');
+    }
+
+    output
+}
+
+// Encodes the token using the JSON representation
+// described in luthor's `token` module.
+fn json(token: &Token) -> String {
+    let position = |position: &Position| {
+        format!(
+            "{{\"offset\":{},\"line\":{},\"column\":{},\"utf16_column\":{}}}",
+            position.offset, position.line, position.column, position.utf16_column
+        )
+    };
+
+    format!(
+        "{{\"lexeme\":{},\"category\":{},\"span\":{{\"start\":{},\"end\":{}}},\"scope\":{}}}",
+        json_string(&token.lexeme),
+        json_string(token.category.name()),
+        position(&token.span.start),
+        position(&token.span.end),
+        token.scope.as_deref().map(json_string).unwrap_or_else(|| "null".to_string())
+    )
+}
+
+fn json_string(value: &str) -> String {
+    let mut output = String::with_capacity(value.len() + 2);
+    output.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(output, "\\u{:04x}", c as u32); },
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{json, list_languages, parse_args, table, Command, Format, Options};
+    use luthor::lexers::{self, json as json_lexer};
+
+    fn args(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn it_parses_options_and_files() {
+        assert_eq!(
+            args(&["-l", "ruby", "--format=jsonl", "--theme", "theme.tmTheme", "a.rb", "-", "--", "-b.rb"]),
+            Ok(Command::Lex(Options{
+                lang: Some("ruby".to_string()),
+                format: Format::JsonLines,
+                theme: Some("theme.tmTheme".to_string()),
+                files: vec!["a.rb".to_string(), "-".to_string(), "-b.rb".to_string()],
+            }))
+        );
+        assert_eq!(args(&["a.rb", "--list-languages"]), Ok(Command::ListLanguages));
+    }
+
+    #[test]
+    fn it_rejects_invalid_arguments() {
+        assert_eq!(args(&["--lang"]), Err("--lang requires a value".to_string()));
+        assert_eq!(args(&["--format", "xml"]), Err("unknown format xml".to_string()));
+        assert_eq!(args(&["--colour"]), Err("unknown option --colour".to_string()));
+    }
+
+    #[test]
+    fn it_encodes_tokens_as_json() {
+        let tokens = json_lexer::lex("[\"a\\\"\t\"]");
+
+        assert_eq!(
+            json(&tokens[1]),
+            "{\"lexeme\":\"\\\"a\\\\\\\"\\t\\\"\",\"category\":\"string\",\
+             \"span\":{\"start\":{\"offset\":1,\"line\":0,\"column\":1,\"utf16_column\":1},\
+             \"end\":{\"offset\":7,\"line\":0,\"column\":7,\"utf16_column\":7}},\
+             \"scope\":\"string.quoted.double.json\"}"
+        );
+    }
+
+    #[test]
+    fn it_prints_tokens_as_a_table() {
+        let output = table(&json_lexer::lex("[\ntrue]"));
+        let rows: Vec<Vec<&str>> = output.lines().map(|line| line.split_whitespace().collect()).collect();
+
+        assert_eq!(rows[2], ["2:1", "boolean", "\"true\"", "constant.language.boolean.json"]);
+    }
+
+    #[test]
+    fn it_lists_languages() {
+        let output = list_languages(&lexers::registry());
+
+        assert_eq!(output.lines().count(), lexers::registry().lexers().count());
+        assert!(output.lines().any(|line| {
+            line.split_whitespace().collect::<Vec<&str>>() == ["Ruby", "ruby,", "rb", "*.rb,", "*.rake,", "*.gemspec,", "*.ru"]
+        }));
+    }
+}
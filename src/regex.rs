@@ -0,0 +1,210 @@
+//! Regular expressions, compiled for matching at a tokenizer's cursor.
+//!
+//! Patterns are matched using a lazily-built DFA, which is stepped through the
+//! data one byte at a time. Besides the match itself, this tells us exactly
+//! how much of the data had to be examined to produce it, which incremental
+//! lexing relies on to know which tokens are affected by an edit.
+
+use std::error;
+use std::fmt;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use regex_automata::{meta, Anchored, Input};
+use regex_automata::hybrid::dfa::{Cache, DFA};
+use regex_automata::util::pool::Pool;
+
+type CachePool = Pool<Cache, Box<dyn Fn() -> Cache + Send + Sync + RefUnwindSafe + UnwindSafe>>;
+
+/// A regular expression, compiled for anchored matching at a tokenizer's
+/// cursor (see `Tokenizer::match_regex`). Compiling a pattern is relatively
+/// expensive, so each should be compiled once and reused; the `regex!` macro
+/// does this for patterns used in lexers.
+///
+/// # Examples
+///
+/// ```
+/// use luthor::regex::Regex;
+///
+/// let regex = Regex::new(r"[0-9]+(\.[0-9]+)?").unwrap();
+/// let tokenizer = luthor::Tokenizer::new("3.14 * r");
+///
+/// assert_eq!(regex.as_str(), r"[0-9]+(\.[0-9]+)?");
+/// assert_eq!(tokenizer.match_regex(&regex), Some(4));
+/// ```
+pub struct Regex {
+    pattern: String,
+    regex: meta::Regex,
+
+    // Patterns that can't be matched using a lazy DFA fall back to the
+    // general-purpose regex, which doesn't report how much data it examined.
+    dfa: Option<(DFA, CachePool)>,
+}
+
+impl Regex {
+    /// Compiles the pattern, which uses the syntax of the `regex` crate.
+    pub fn new(pattern: &str) -> Result<Regex, Error> {
+        let regex = meta::Regex::new(pattern).map_err(|error| Error(Box::new(error)))?;
+        let dfa = DFA::builder()
+            .configure(DFA::config().unicode_word_boundary(true))
+            .build(pattern)
+            .ok()
+            .map(|dfa| {
+                let cache_dfa = dfa.clone();
+                let create: Box<dyn Fn() -> Cache + Send + Sync + RefUnwindSafe + UnwindSafe> =
+                    Box::new(move || cache_dfa.create_cache());
+
+                (dfa, Pool::new(create))
+            });
+
+        Ok(Regex{ pattern: pattern.to_string(), regex, dfa })
+    }
+
+    /// The pattern from which the regex was compiled.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Matches the pattern starting at the specified offset in the data,
+    /// returning the length of the match (if any), along with the length of
+    /// the data examined in doing so. The latter is `None` if the search
+    /// reached the end of the data.
+    pub(crate) fn match_at(&self, data: &str, offset: usize) -> (Option<usize>, Option<usize>) {
+        let input = Input::new(data).span(offset..data.len()).anchored(Anchored::Yes);
+
+        if let Some((ref dfa, ref pool)) = self.dfa {
+            if let Some(result) = walk(dfa, &mut pool.get(), &input) {
+                return result
+            }
+        }
+
+        let length = self.regex.search_half(&input).map(|half| half.offset() - offset);
+        (length, None)
+    }
+}
+
+impl fmt::Debug for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Regex").field(&self.pattern).finish()
+    }
+}
+
+/// The error produced when a pattern cannot be compiled.
+#[derive(Debug)]
+pub struct Error(Box<meta::BuildError>);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&*self.0)
+    }
+}
+
+// Steps the DFA through the input until it can no longer match, returning
+// the same values as `Regex::match_at`, or `None` if the DFA gives up.
+fn walk(dfa: &DFA, cache: &mut Cache, input: &Input) -> Option<(Option<usize>, Option<usize>)> {
+    let data = &input.haystack()[input.start()..];
+    let mut state = dfa.start_state_forward(cache, input).ok()?;
+    let mut length = None;
+
+    for (index, &byte) in data.iter().enumerate() {
+        state = dfa.next_state(cache, state, byte).ok()?;
+
+        if state.is_tagged() {
+            // Match states are delayed by a byte, so
+            // this marks a match that ends at the index.
+            if state.is_match() {
+                length = Some(index);
+            } else if state.is_dead() {
+                return Some((length, Some(index + 1)))
+            } else if state.is_quit() {
+                return None
+            }
+        }
+    }
+
+    state = dfa.next_eoi_state(cache, state).ok()?;
+    if state.is_match() {
+        length = Some(data.len());
+    }
+
+    Some((length, None))
+}
+
+/// Compiles a pattern into a `Regex` the first time it's evaluated, returning
+/// a reference to the same compiled regex on subsequent evaluations.
+///
+/// # Panics
+///
+/// Panics if the pattern is invalid.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate luthor;
+///
+/// use luthor::Tokenizer;
+/// use luthor::token::Category;
+///
+/// fn main() {
+///     let mut tokenizer = Tokenizer::new("0xFF");
+///     tokenizer.tokenize_regex(regex!("0x[0-9A-F]+"), Category::Integer);
+///
+///     assert_eq!(tokenizer.tokens()[0].lexeme, "0xFF");
+/// }
+/// ```
+#[macro_export]
+macro_rules! regex {
+    ($pattern:expr) => {{
+        static REGEX: ::std::sync::OnceLock<$crate::regex::Regex> = ::std::sync::OnceLock::new();
+        REGEX.get_or_init(|| $crate::regex::Regex::new($pattern).unwrap())
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Regex;
+
+    #[test]
+    fn new_rejects_invalid_patterns() {
+        assert!(Regex::new("(").is_err());
+    }
+
+    #[test]
+    fn match_at_is_anchored_at_the_offset() {
+        let regex = Regex::new("[a-z]+").unwrap();
+
+        assert_eq!(regex.match_at("12ab", 0).0, None);
+        assert_eq!(regex.match_at("12ab", 2).0, Some(2));
+    }
+
+    #[test]
+    fn match_at_reports_how_much_data_was_examined() {
+        let regex = Regex::new("abc|a").unwrap();
+
+        assert_eq!(regex.match_at("abd abc", 0), (Some(1), Some(3)));
+        assert_eq!(regex.match_at("abc", 0), (Some(3), None));
+    }
+
+    #[test]
+    fn match_at_considers_data_preceding_the_offset() {
+        let regex = Regex::new(r"^a|\bb").unwrap();
+
+        assert_eq!(regex.match_at("aa", 1).0, None);
+        assert_eq!(regex.match_at("ab", 1).0, None);
+        assert_eq!(regex.match_at("a b", 2).0, Some(1));
+    }
+
+    #[test]
+    fn match_at_falls_back_when_the_dfa_gives_up() {
+        // Unicode word boundaries can't be handled by
+        // the DFA once it encounters non-ASCII data.
+        let regex = Regex::new(r"\w+\b").unwrap();
+
+        assert_eq!(regex.match_at("héllo wörld", 0), (Some(6), None));
+    }
+}
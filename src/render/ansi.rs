@@ -0,0 +1,255 @@
+//! Renders tokens for display in terminals, using ANSI escape sequences.
+//!
+//! Each token's style is applied before it and reset after it, and
+//! tokens spanning several lines are reset before each newline and
+//! restyled after it, so that every line can be displayed on its own
+//! (e.g. when paging output using `less -R`). Tokens that the theme
+//! doesn't style are left in the terminal's default colours.
+//!
+//! # Examples
+//!
+//! ```
+//! use luthor::lexers::json;
+//! use luthor::render::ansi::{self, ColorMode};
+//! use luthor::theme::{Color, Style, Theme};
+//! use luthor::token::Category;
+//!
+//! let mut theme = Theme::new("example");
+//! theme.set_style(Category::Boolean, Style{
+//!     foreground: Color::from_hex("#ff8000"),
+//!     bold: true,
+//!     ..Style::default()
+//! });
+//!
+//! let tokens = json::lex("[true]");
+//! let output = ansi::render(&tokens, &theme, ColorMode::TrueColor);
+//!
+//! assert_eq!(output, "[\x1b[1;38;2;255;128;0mtrue\x1b[0m]");
+//! ```
+
+use std::env;
+use std::fmt::Write;
+use theme::{Color, Style, Theme};
+use token::Token;
+
+const RESET: &str = "\x1b[0m";
+
+// The colours commonly used by terminals (xterm's defaults) for
+// the 16 standard colours, used to approximate arbitrary colours.
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0),
+    (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+    (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0),
+    (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+
+// The component intensities used by the 6x6x6 colour cube of 256-colour terminals.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The range of colours supported by a terminal. Theme colours are
+/// approximated using the nearest colour available in the mode.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ColorMode {
+    /// The 8 standard colours and their bright variants.
+    Ansi16,
+
+    /// The 256-colour palette supported by most modern terminals.
+    Ansi256,
+
+    /// 24-bit colour.
+    TrueColor,
+}
+
+impl ColorMode {
+    /// Determines the mode supported by the current terminal, using
+    /// the `COLORTERM` and `TERM` environment variables.
+    pub fn from_env() -> ColorMode {
+        ColorMode::detect(
+            env::var("COLORTERM").ok().as_deref(),
+            env::var("TERM").ok().as_deref(),
+        )
+    }
+
+    fn detect(colorterm: Option<&str>, term: Option<&str>) -> ColorMode {
+        match (colorterm, term) {
+            (Some("truecolor"), _) | (Some("24bit"), _) => ColorMode::TrueColor,
+            (_, Some(term)) if term.contains("256color") => ColorMode::Ansi256,
+            _ => ColorMode::Ansi16,
+        }
+    }
+}
+
+/// Renders the tokens, styling them using the theme.
+pub fn render<'a, I>(tokens: I, theme: &Theme, mode: ColorMode) -> String where I: IntoIterator<Item=&'a Token> {
+    let mut output = String::new();
+
+    for token in tokens {
+        let sequence = escape_sequence(&theme.token_style(token), mode);
+        let mut segments = token.lexeme.split('\n').peekable();
+
+        while let Some(segment) = segments.next() {
+            match sequence {
+                Some(ref sequence) if !segment.is_empty() => {
+                    output.push_str(sequence);
+                    output.push_str(segment);
+                    output.push_str(RESET);
+                },
+                _ => output.push_str(segment),
+            }
+
+            if segments.peek().is_some() { output.push('\n'); }
+        }
+    }
+
+    output
+}
+
+// Builds the escape sequence that applies the style, if it has any effect.
+fn escape_sequence(style: &Style, mode: ColorMode) -> Option<String> {
+    let mut parameters = Vec::new();
+    if style.bold { parameters.push("1".to_string()); }
+    if style.italic { parameters.push("3".to_string()); }
+    if style.underline { parameters.push("4".to_string()); }
+    if let Some(color) = style.foreground {
+        parameters.push(color_parameter(color, mode, false));
+    }
+    if let Some(color) = style.background {
+        parameters.push(color_parameter(color, mode, true));
+    }
+
+    if parameters.is_empty() {
+        return None
+    }
+
+    let mut sequence = String::from("\x1b[");
+    let _ = write!(sequence, "{}m", parameters.join(";"));
+
+    Some(sequence)
+}
+
+fn color_parameter(color: Color, mode: ColorMode, background: bool) -> String {
+    match mode {
+        ColorMode::TrueColor => {
+            format!("{};2;{};{};{}", if background { 48 } else { 38 }, color.r, color.g, color.b)
+        },
+        ColorMode::Ansi256 => {
+            format!("{};5;{}", if background { 48 } else { 38 }, ansi256(color))
+        },
+        ColorMode::Ansi16 => {
+            let index = ansi16(color);
+            let base = match (index < 8, background) {
+                (true, false) => 30,
+                (false, false) => 90 - 8,
+                (true, true) => 40,
+                (false, true) => 100 - 8,
+            };
+
+            (base + index).to_string()
+        }
+    }
+}
+
+// The index of the nearest of the 16 standard colours.
+fn ansi16(color: Color) -> u8 {
+    (0..16u8)
+        .min_by_key(|&index| distance(color, PALETTE[index as usize]))
+        .unwrap_or(0)
+}
+
+// The index of the nearest colour in the 256-colour palette's colour
+// cube (16-231) or grayscale ramp (232-255). The first 16 colours are
+// skipped, since terminals commonly redefine them.
+fn ansi256(color: Color) -> u8 {
+    let level = |component: u8| {
+        (0..6).min_by_key(|&index| (CUBE_LEVELS[index] as i32 - component as i32).abs()).unwrap_or(0)
+    };
+    let (r, g, b) = (level(color.r), level(color.g), level(color.b));
+    let cube = (CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]);
+
+    let average = (color.r as u32 + color.g as u32 + color.b as u32) / 3;
+    let gray_index = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray_level = 8 + gray_index * 10;
+
+    if distance(color, (gray_level, gray_level, gray_level)) < distance(color, cube) {
+        232 + gray_index
+    } else {
+        16 + 36 * r as u8 + 6 * g as u8 + b as u8
+    }
+}
+
+fn distance(color: Color, other: (u8, u8, u8)) -> u32 {
+    let difference = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+
+    difference(color.r, other.0) + difference(color.g, other.1) + difference(color.b, other.2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ansi16, ansi256, render, ColorMode};
+    use lexers::javascript;
+    use theme::{Color, Style, Theme};
+    use token::Category;
+
+    fn theme() -> Theme {
+        let mut theme = Theme::new("test");
+        theme.set_style(Category::Comment, Style{
+            foreground: Some(Color::new(255, 0, 0)),
+            italic: true,
+            ..Style::default()
+        });
+        theme.set_style(Category::Identifier, Style{
+            background: Some(Color::new(0, 0, 0)),
+            ..Style::default()
+        });
+
+        theme
+    }
+
+    #[test]
+    fn styles_are_reapplied_after_each_newline() {
+        let tokens = javascript::lex("/* a\n\nb */ x");
+        let output = render(&tokens, &theme(), ColorMode::TrueColor);
+
+        assert_eq!(
+            output,
+            "\x1b[3;38;2;255;0;0m/* a\x1b[0m\n\
+             \n\
+             \x1b[3;38;2;255;0;0mb */\x1b[0m \
+             \x1b[48;2;0;0;0mx\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn colors_are_approximated_in_limited_modes() {
+        let tokens = javascript::lex("// a\nx");
+
+        assert_eq!(
+            render(&tokens, &theme(), ColorMode::Ansi256),
+            "\x1b[3;38;5;196m// a\x1b[0m\n\x1b[48;5;16mx\x1b[0m"
+        );
+        assert_eq!(
+            render(&tokens, &theme(), ColorMode::Ansi16),
+            "\x1b[3;91m// a\x1b[0m\n\x1b[40mx\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn ansi256_uses_the_grayscale_ramp_for_grays() {
+        assert_eq!(ansi256(Color::new(128, 128, 128)), 244);
+        assert_eq!(ansi256(Color::new(255, 255, 255)), 231);
+        assert_eq!(ansi256(Color::new(0, 95, 135)), 24);
+    }
+
+    #[test]
+    fn ansi16_chooses_the_nearest_standard_color() {
+        assert_eq!(ansi16(Color::new(10, 200, 10)), 2);
+        assert_eq!(ansi16(Color::new(250, 250, 250)), 15);
+    }
+
+    #[test]
+    fn detect_prefers_colorterm() {
+        assert_eq!(ColorMode::detect(Some("truecolor"), Some("xterm")), ColorMode::TrueColor);
+        assert_eq!(ColorMode::detect(None, Some("xterm-256color")), ColorMode::Ansi256);
+        assert_eq!(ColorMode::detect(None, None), ColorMode::Ansi16);
+    }
+}
@@ -0,0 +1,301 @@
+//! Renders tokens as HTML, for use in documentation and web pages.
+//!
+//! Tokens are wrapped in `span` elements with a CSS class named after their
+//! category (see `Category::name`), and each line is wrapped in a `span`
+//! with the `line` class. The output is placed in a `pre` element with the
+//! `luthor` class, to which the rules produced by `stylesheet` are scoped.
+//!
+//! # Examples
+//!
+//! ```
+//! use luthor::lexers::json;
+//! use luthor::render::html::{self, Options};
+//! use luthor::theme::Theme;
+//!
+//! let tokens = json::lex("[true]");
+//! let markup = html::render(&tokens, &Options::default());
+//!
+//! assert_eq!(
+//!     markup,
+//!     "<pre class=\"luthor\"><code><span class=\"line\">\
+//!      <span class=\"bracket\">[</span>\
+//!      <span class=\"boolean\">true</span>\
+//!      <span class=\"bracket\">]</span>\
+//!      </span></code></pre>"
+//! );
+//!
+//! let css = html::stylesheet(&Theme::default());
+//! assert!(css.contains(".luthor .boolean"));
+//! ```
+
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+use theme::{Style, Theme};
+use token::{Category, Token};
+
+/// Controls the markup produced by `render`. Line numbers count from
+/// `first_line`, which is useful when rendering part of a larger document.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Options {
+    /// Prefixes each line with its number, using the `line-number` class.
+    pub line_numbers: bool,
+
+    /// Gives each line an id made up of this prefix and its number (e.g.
+    /// `L12`), and links line numbers to them.
+    pub line_anchors: Option<String>,
+
+    /// Lines that are given the `highlighted` class.
+    pub highlighted_lines: Vec<RangeInclusive<usize>>,
+
+    /// The number of the first line.
+    pub first_line: usize,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options{
+            line_numbers: false,
+            line_anchors: None,
+            highlighted_lines: Vec::new(),
+            first_line: 1,
+        }
+    }
+}
+
+/// Renders the tokens as HTML. Tokens spanning several lines (e.g. block
+/// comments) are split, so that every line is wrapped in its own element.
+///
+/// # Examples
+///
+/// ```
+/// use luthor::lexers::json;
+/// use luthor::render::html::{self, Options};
+///
+/// let tokens = json::lex("[\n  1\n]");
+/// let options = Options{
+///     line_numbers: true,
+///     line_anchors: Some("L".to_string()),
+///     highlighted_lines: vec![2..=2],
+///     ..Options::default()
+/// };
+/// let markup = html::render(&tokens, &options);
+///
+/// assert!(markup.contains(
+///     "<span class=\"line highlighted\" id=\"L2\">\
+///      <a class=\"line-number\" href=\"#L2\">2</a>"
+/// ));
+/// ```
+pub fn render<'a, I>(tokens: I, options: &Options) -> String where I: IntoIterator<Item=&'a Token> {
+    let mut markup = String::from("<pre class=\"luthor\"><code>");
+    let mut line = options.first_line;
+    let mut line_open = false;
+
+    for token in tokens {
+        let mut segments = token.lexeme.split('\n').peekable();
+
+        while let Some(segment) = segments.next() {
+            if !segment.is_empty() {
+                if !line_open {
+                    open_line(&mut markup, line, options);
+                    line_open = true;
+                }
+                push_segment(&mut markup, segment, &token.category);
+            }
+
+            // Every segment but the last is followed by a newline.
+            if segments.peek().is_some() {
+                if !line_open { open_line(&mut markup, line, options); }
+                markup.push_str("</span>\n");
+                line_open = false;
+                line += 1;
+            }
+        }
+    }
+
+    if line_open { markup.push_str("</span>"); }
+    markup.push_str("</code></pre>");
+
+    markup
+}
+
+/// Produces CSS rules that style rendered tokens using the specified theme.
+pub fn stylesheet(theme: &Theme) -> String {
+    let mut css = String::new();
+
+    let base = Style{ foreground: theme.foreground, background: theme.background, ..Style::default() };
+    push_rule(&mut css, ".luthor", &base);
+
+    css.push_str(".luthor .line { display: inline-block; width: 100%; }\n");
+    let _ = writeln!(
+        css,
+        ".luthor .line-number {{ display: inline-block; min-width: 2em; padding-right: 1em; \
+         text-align: right; text-decoration: none; user-select: none;{} }}",
+        theme.gutter_foreground.map(|color| format!(" color: {};", color.to_hex())).unwrap_or_default()
+    );
+    if let Some(color) = theme.line_highlight {
+        let _ = writeln!(css, ".luthor .highlighted {{ background-color: {}; }}", color.to_hex());
+    }
+
+    for category in Category::ALL {
+        let style = theme.style(category);
+        if !style.is_plain() {
+            push_rule(&mut css, &format!(".luthor .{}", category.name()), &style);
+        }
+    }
+
+    css
+}
+
+fn open_line(markup: &mut String, line: usize, options: &Options) {
+    markup.push_str("<span class=\"line");
+    if options.highlighted_lines.iter().any(|range| range.contains(&line)) {
+        markup.push_str(" highlighted");
+    }
+    markup.push('"');
+
+    if let Some(ref prefix) = options.line_anchors {
+        let _ = write!(markup, " id=\"{}{}\">", escape(prefix), line);
+    } else {
+        markup.push('>');
+    }
+
+    if options.line_numbers {
+        match options.line_anchors {
+            Some(ref prefix) => {
+                let _ = write!(markup, "<a class=\"line-number\" href=\"#{}{}\">{}</a>", escape(prefix), line, line);
+            },
+            None => {
+                let _ = write!(markup, "<span class=\"line-number\">{}</span>", line);
+            }
+        }
+    }
+}
+
+fn push_segment(markup: &mut String, segment: &str, category: &Category) {
+    if *category == Category::Whitespace {
+        markup.push_str(&escape(segment));
+    } else {
+        let _ = write!(markup, "<span class=\"{}\">{}</span>", category.name(), escape(segment));
+    }
+}
+
+fn push_rule(css: &mut String, selector: &str, style: &Style) {
+    let mut declarations = String::new();
+    if let Some(color) = style.foreground {
+        let _ = write!(declarations, " color: {};", color.to_hex());
+    }
+    if let Some(color) = style.background {
+        let _ = write!(declarations, " background-color: {};", color.to_hex());
+    }
+    if style.bold { declarations.push_str(" font-weight: bold;"); }
+    if style.italic { declarations.push_str(" font-style: italic;"); }
+    if style.underline { declarations.push_str(" text-decoration: underline;"); }
+
+    if !declarations.is_empty() {
+        let _ = writeln!(css, "{} {{{} }}", selector, declarations);
+    }
+}
+
+// Escapes characters with special meaning in HTML text and attribute values.
+fn escape(data: &str) -> String {
+    let mut escaped = String::with_capacity(data.len());
+    for c in data.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, stylesheet, Options};
+    use lexers::{javascript, xml};
+    use theme::{Color, Style, Theme};
+    use token::Category;
+
+    #[test]
+    fn lexemes_are_escaped() {
+        let tokens = xml::lex("<a>&</a>");
+        let markup = render(&tokens, &Options::default());
+
+        assert_eq!(
+            markup,
+            "<pre class=\"luthor\"><code><span class=\"line\">\
+             <span class=\"punctuation\">&lt;</span>\
+             <span class=\"identifier\">a</span>\
+             <span class=\"punctuation\">&gt;</span>\
+             <span class=\"text\">&amp;</span>\
+             <span class=\"punctuation\">&lt;/</span>\
+             <span class=\"identifier\">a</span>\
+             <span class=\"punctuation\">&gt;</span>\
+             </span></code></pre>"
+        );
+    }
+
+    #[test]
+    fn multi_line_tokens_are_split_across_lines() {
+        let tokens = javascript::lex("/* a\nb */ x\n");
+        let options = Options{ line_numbers: true, first_line: 9, ..Options::default() };
+        let markup = render(&tokens, &options);
+
+        assert_eq!(
+            markup,
+            "<pre class=\"luthor\"><code>\
+             <span class=\"line\"><span class=\"line-number\">9</span><span class=\"comment\">/* a</span></span>\n\
+             <span class=\"line\"><span class=\"line-number\">10</span><span class=\"comment\">b */</span> \
+             <span class=\"identifier\">x</span></span>\n\
+             </code></pre>"
+        );
+    }
+
+    #[test]
+    fn empty_lines_are_numbered() {
+        let tokens = javascript::lex("x\n\ny");
+        let options = Options{ line_numbers: true, ..Options::default() };
+        let markup = render(&tokens, &options);
+
+        assert!(markup.contains("<span class=\"line\"><span class=\"line-number\">2</span></span>\n"));
+        assert!(markup.contains("<span class=\"line-number\">3</span><span class=\"identifier\">y</span>"));
+    }
+
+    #[test]
+    fn highlighted_ranges_are_inclusive() {
+        let tokens = javascript::lex("a\nb\nc\nd");
+        let options = Options{
+            line_anchors: Some("line-".to_string()),
+            highlighted_lines: vec![2..=3],
+            ..Options::default()
+        };
+        let markup = render(&tokens, &options);
+
+        assert!(markup.contains("<span class=\"line\" id=\"line-1\">"));
+        assert!(markup.contains("<span class=\"line highlighted\" id=\"line-2\">"));
+        assert!(markup.contains("<span class=\"line highlighted\" id=\"line-3\">"));
+        assert!(markup.contains("<span class=\"line\" id=\"line-4\">"));
+        assert!(!markup.contains("line-number"));
+    }
+
+    #[test]
+    fn stylesheet_describes_styled_categories() {
+        let mut theme = Theme::new("test");
+        theme.background = Color::from_hex("#000000");
+        theme.set_style(Category::DocComment, Style{
+            foreground: Color::from_hex("#808080"),
+            italic: true,
+            ..Style::default()
+        });
+        let css = stylesheet(&theme);
+
+        assert!(css.contains(".luthor { background-color: #000000; }\n"));
+        assert!(css.contains(".luthor .doc_comment { color: #808080; font-style: italic; }\n"));
+        assert!(!css.contains(".luthor .keyword"));
+        assert!(!css.contains(".highlighted"));
+    }
+}
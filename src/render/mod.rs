@@ -0,0 +1,5 @@
+//! Renderers, which present lexed tokens using the styles described by a
+//! theme (see the `theme` module).
+
+pub mod ansi;
+pub mod html;
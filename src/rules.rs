@@ -0,0 +1,436 @@
+//! A declarative means of describing lexers as a set of named states, each
+//! containing an ordered list of rules. See the `lexer_states!` macro.
+
+/// Generates state functions from a declarative description of a lexer.
+///
+/// Each state becomes a function usable with `StateFunction`, containing rules
+/// that are tried in order at the current position. A rule pairs a matcher with
+/// an effect on the tokenizer, optionally followed by a state transition:
+///
+/// ```text
+/// matcher => effect;
+/// matcher => effect, transition;
+/// ```
+///
+/// The following matchers are available:
+///
+/// * `literal("a", "b", ...)`: the longest of the specified literals.
+/// * `keywords("a", "b", ...)`: the longest of the specified keywords, which
+///   must not be followed by an alphanumeric character or underscore.
+/// * `chars(pattern)`: one or more characters matching a `char` pattern,
+///   e.g. `chars('0'..='9' | '_')`.
+/// * `char(pattern)`: a single character matching a `char` pattern.
+/// * `regex("pattern")`: a regular expression, compiled once, on first use
+///   (see `Tokenizer::match_regex`).
+/// * `any`: any single character.
+/// * `end`: the end of the data; only usable with `finish`, after which
+///   lexing completes. Malformed data left pending at the end (e.g. an
+///   unterminated string) can be reported as a diagnostic by following
+///   `finish` with `report(Kind, "message")` (see `Tokenizer::report`).
+///
+/// Matched data is handled using one of the following effects:
+///
+/// * `Category`: tokenizes any pending data as `Category::Text`,
+///   followed by the match as the specified category.
+/// * `start`: tokenizes any pending data as `Category::Text`, leaving the
+///   match pending; used to begin a token that spans several rules.
+/// * `continue`: leaves the match pending, adding it to the pending data.
+/// * `finish Category`: tokenizes the pending data and
+///   the match together as the specified category.
+/// * `error(Kind, "message")`: tokenizes any pending data as `Category::Text`,
+///   followed by the match as `Category::Error`, reporting it as a diagnostic
+///   of the specified kind.
+///
+/// Effects producing a token (including `finish` in `end` rules) can also
+/// assign it a TextMate-style scope, e.g. `Comment as "comment.line.json"`.
+///
+/// Unless a transition is specified, the lexer remains in the same state:
+///
+/// * `push(state)`: moves to the specified state, returning
+///   to the current one when it pops.
+/// * `goto(state)`: moves to the specified state.
+/// * `pop`: returns to the state that pushed the current one.
+///
+/// Characters not matched by any rule are left pending. At the end of the data,
+/// pending data is tokenized as `Category::Text`, unless an `end` rule applies.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate luthor;
+///
+/// use luthor::{StateFunction, Tokenizer};
+/// use luthor::token::Category;
+///
+/// lexer_states! {
+///     initial_state {
+///         chars(' ' | '\n') => Whitespace;
+///         keywords("let") => Keyword;
+///         regex("[0-9]+") => Integer;
+///         literal("=", ";") => Operator;
+///         literal("\"") => start, push(string);
+///     }
+///
+///     string {
+///         literal("\\\\", "\\\"") => continue;
+///         literal("\"") => finish String, pop;
+///         end => finish String, report(UnterminatedString, "unterminated string");
+///     }
+/// }
+///
+/// fn main() {
+///     let tokenizer = Tokenizer::new("let x = \"a \\\"b\\\"\";");
+///     let tokens: Vec<_> = tokenizer.run(StateFunction(initial_state)).collect();
+///
+///     assert_eq!(tokens[2].lexeme, "x");
+///     assert_eq!(tokens[2].category, Category::Text);
+///     assert_eq!(tokens[6].lexeme, "\"a \\\"b\\\"\"");
+///     assert_eq!(tokens[6].category, Category::String);
+///
+///     let mut tokens = Tokenizer::new("let y = \"").run(StateFunction(initial_state));
+///     assert_eq!(tokens.by_ref().count(), 7);
+///     assert_eq!(tokens.diagnostics()[0].span.range(), 8..9);
+/// }
+/// ```
+///
+/// The macro can also be imported by path, and renamed:
+///
+/// ```
+/// extern crate luthor;
+///
+/// use luthor::lexer_states as states;
+/// use luthor::{StateFunction, Tokenizer};
+/// use luthor::token::Category;
+///
+/// states! {
+///     initial_state {
+///         chars('0'..='9') => Integer;
+///         regex("[a-z]+") => Identifier;
+///         literal("+") => Operator;
+///     }
+/// }
+///
+/// fn main() {
+///     let tokens: Vec<_> = Tokenizer::new("1+x").run(StateFunction(initial_state)).collect();
+///
+///     assert_eq!(tokens[1].category, Category::Operator);
+///     assert_eq!(tokens[2].category, Category::Identifier);
+/// }
+/// ```
+#[macro_export]
+macro_rules! lexer_states {
+    // Each rule is captured by a single repetition, rather than by recursing
+    // once per rule, so that states can hold any number of rules without
+    // reaching the recursion limit. Effects are captured as a sequence of
+    // identifiers, any arguments and the words following them (e.g. `as`),
+    // and a scope, and are then interpreted by the arms below.
+    ($($(#[$attribute:meta])* $state:ident {
+        $(
+            $matcher:ident $(($($arguments:tt)*))? =>
+            $($effect:ident)+ $(($($effect_arguments:tt)*) $($modifier:ident)*)? $($scope:literal)?
+            $(, $transition:ident $(($($transition_arguments:tt)*))?)?;
+        )*
+    })*) => {
+        $(
+            $(#[$attribute])*
+            fn $state(tokenizer: &mut $crate::Tokenizer) -> Option<$crate::StateFunction> {
+                $(
+                    $crate::lexer_states!(@rule tokenizer, $state,
+                        [$matcher $(($($arguments)*))?],
+                        [$($effect)+ $(($($effect_arguments)*) $($modifier)*)? $($scope)?],
+                        [$($transition $(($($transition_arguments)*))?)?]);
+                )*
+
+                // Characters that don't match any of the
+                // rules are included in the next token.
+                if tokenizer.current_char().is_some() {
+                    tokenizer.advance();
+                    Some($crate::StateFunction($state))
+                } else {
+                    tokenizer.tokenize($crate::token::Category::Text);
+                    None
+                }
+            }
+        )*
+    };
+
+    // Rules matching the end of the data finish the pending token, optionally reporting it.
+    (@rule $tokenizer:ident, $state:ident, [end], [finish $category:ident $(as $scope:literal)?], []) => {
+        if $tokenizer.current_char().is_none() {
+            $crate::lexer_states!(@tokenize $tokenizer, $category, [$($scope)?]);
+            return None
+        }
+    };
+    (@rule $tokenizer:ident, $state:ident, [end], [finish $category:ident $(as $scope:literal)?],
+     [report($kind:ident, $message:expr)]) => {
+        if $tokenizer.current_char().is_none() {
+            $tokenizer.report($crate::diagnostic::Kind::$kind, $message);
+            $crate::lexer_states!(@tokenize $tokenizer, $category, [$($scope)?]);
+            return None
+        }
+    };
+
+    // Other rules are generated with their effect, followed by their transition.
+    (@rule $tokenizer:ident, $state:ident, $matcher:tt, $effect:tt, []) => {
+        $crate::lexer_states!(@effect $tokenizer, $matcher, $effect, Some($crate::StateFunction($state)));
+    };
+    (@rule $tokenizer:ident, $state:ident, $matcher:tt, $effect:tt, [push($target:ident)]) => {
+        $crate::lexer_states!(@effect $tokenizer, $matcher, $effect, {
+            $tokenizer.states.push($crate::StateFunction($state));
+            Some($crate::StateFunction($target))
+        });
+    };
+    (@rule $tokenizer:ident, $state:ident, $matcher:tt, $effect:tt, [goto($target:ident)]) => {
+        $crate::lexer_states!(@effect $tokenizer, $matcher, $effect, Some($crate::StateFunction($target)));
+    };
+    (@rule $tokenizer:ident, $state:ident, $matcher:tt, $effect:tt, [pop]) => {
+        $crate::lexer_states!(@effect $tokenizer, $matcher, $effect, None);
+    };
+
+    (@effect $tokenizer:ident, $matcher:tt, [continue], $transition:expr) => {
+        $crate::lexer_states!(@generate $tokenizer, $matcher, [continue], $transition);
+    };
+    (@effect $tokenizer:ident, $matcher:tt, [start], $transition:expr) => {
+        $crate::lexer_states!(@generate $tokenizer, $matcher, [start], $transition);
+    };
+    (@effect $tokenizer:ident, $matcher:tt, [finish $category:ident $(as $scope:literal)?], $transition:expr) => {
+        $crate::lexer_states!(@generate $tokenizer, $matcher, [finish $category [$($scope)?]], $transition);
+    };
+    (@effect $tokenizer:ident, $matcher:tt,
+     [error($kind:ident, $message:expr) $(as $scope:literal)?], $transition:expr) => {
+        $crate::lexer_states!(@generate $tokenizer, $matcher, [error($kind, $message) [$($scope)?]], $transition);
+    };
+    (@effect $tokenizer:ident, $matcher:tt, [$category:ident $(as $scope:literal)?], $transition:expr) => {
+        $crate::lexer_states!(@generate $tokenizer, $matcher, [$category [$($scope)?]], $transition);
+    };
+
+    // Generates a rule, applying its effect and transition if it matches.
+    (@generate $tokenizer:ident, [$($matcher:tt)*], [$($effect:tt)*], $transition:expr) => {
+        if let Some(length) = $crate::lexer_states!(@match $tokenizer, $($matcher)*) {
+            $crate::lexer_states!(@apply $tokenizer, length, $($effect)*);
+            return $transition
+        }
+    };
+
+    (@match $tokenizer:ident, literal($($literal:expr),+)) => {
+        $tokenizer.match_literal(&[$($literal),+])
+    };
+    (@match $tokenizer:ident, keywords($($keyword:expr),+)) => {
+        $tokenizer.match_keyword(&[$($keyword),+])
+    };
+    (@match $tokenizer:ident, chars($($pattern:pat)|+)) => {
+        $tokenizer.match_chars(|c| match c { $($pattern)|+ => true, _ => false })
+    };
+    (@match $tokenizer:ident, char($($pattern:pat)|+)) => {
+        $tokenizer.current_char().and_then(|c| match c {
+            $($pattern)|+ => Some(c.len_utf8()),
+            _ => None,
+        })
+    };
+    (@match $tokenizer:ident, regex($pattern:expr)) => {
+        $tokenizer.match_regex($crate::regex!($pattern))
+    };
+    (@match $tokenizer:ident, any) => {
+        $tokenizer.current_char().map(|c| c.len_utf8())
+    };
+
+    (@apply $tokenizer:ident, $length:ident, continue) => {
+        $tokenizer.advance_bytes($length);
+    };
+    (@apply $tokenizer:ident, $length:ident, start) => {
+        $tokenizer.tokenize($crate::token::Category::Text);
+        $tokenizer.advance_bytes($length);
+    };
+    (@apply $tokenizer:ident, $length:ident, finish $category:ident $scope:tt) => {
+        $tokenizer.advance_bytes($length);
+        $crate::lexer_states!(@tokenize $tokenizer, $category, $scope);
+    };
+    (@apply $tokenizer:ident, $length:ident, error($kind:ident, $message:expr) $scope:tt) => {
+        $tokenizer.tokenize($crate::token::Category::Text);
+        $tokenizer.advance_bytes($length);
+        $tokenizer.report($crate::diagnostic::Kind::$kind, $message);
+        $crate::lexer_states!(@tokenize $tokenizer, Error, $scope);
+    };
+    (@apply $tokenizer:ident, $length:ident, $category:ident $scope:tt) => {
+        $tokenizer.tokenize($crate::token::Category::Text);
+        $tokenizer.advance_bytes($length);
+        $crate::lexer_states!(@tokenize $tokenizer, $category, $scope);
+    };
+
+    (@tokenize $tokenizer:ident, $category:ident, []) => {
+        $tokenizer.tokenize($crate::token::Category::$category);
+    };
+    (@tokenize $tokenizer:ident, $category:ident, [$scope:expr]) => {
+        $tokenizer.tokenize_with_scope($crate::token::Category::$category, $scope);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use diagnostic::Kind;
+    use token::Category;
+    use tokenizer::{StateFunction, Tokenizer};
+
+    lexer_states! {
+        initial_state {
+            chars(' ') => Whitespace;
+            keywords("if", "else") => Keyword as "keyword.control.test";
+            char('(') => Parenthesis, push(parenthesized);
+            literal("#") => start, goto(comment);
+            regex("[0-9]+\\.[0-9]+") => Float;
+            char('a'..='z') => continue;
+        }
+
+        parenthesized {
+            char(')') => Parenthesis, pop;
+            literal("\n") => error(UnexpectedCharacter, "unclosed parenthesis"), pop;
+            any => Text;
+        }
+
+        comment {
+            literal("*/") => finish Comment as "comment.block.test", goto(initial_state);
+            end => finish Comment as "comment.block.test", report(UnterminatedComment, "unterminated comment");
+        }
+    }
+
+    fn lex(data: &str) -> Vec<(String, Category)> {
+        Tokenizer::new(data)
+            .run(StateFunction(initial_state))
+            .map(|token| (token.lexeme.to_string(), token.category))
+            .collect()
+    }
+
+    #[test]
+    fn rules_are_applied_in_order() {
+        let tokens = lex("if ifs else");
+        let expected_tokens = [
+            ("if", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("ifs", Category::Text),
+            (" ", Category::Whitespace),
+            ("else", Category::Keyword),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.0, expected_tokens[index].0);
+            assert_eq!(token.1, expected_tokens[index].1);
+        }
+    }
+
+    #[test]
+    fn push_and_pop_return_to_the_original_state() {
+        let tokens = lex("a(b)if");
+        let expected_tokens = [
+            ("a", Category::Text),
+            ("(", Category::Parenthesis),
+            ("b", Category::Text),
+            (")", Category::Parenthesis),
+            ("if", Category::Keyword),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.0, expected_tokens[index].0);
+            assert_eq!(token.1, expected_tokens[index].1);
+        }
+    }
+
+    #[test]
+    fn finish_tokenizes_pending_data_at_the_end() {
+        let tokens = lex("if # comment (");
+        let expected_tokens = [
+            ("if", Category::Keyword),
+            (" ", Category::Whitespace),
+            ("# comment (", Category::Comment),
+        ];
+
+        assert_eq!(tokens.len(), expected_tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            assert_eq!(token.0, expected_tokens[index].0);
+            assert_eq!(token.1, expected_tokens[index].1);
+        }
+    }
+
+    #[test]
+    fn unmatched_characters_join_the_next_token() {
+        let tokens = lex("1 2");
+
+        assert_eq!(tokens[0], ("1".to_string(), Category::Text));
+        assert_eq!(tokens[1], (" ".to_string(), Category::Whitespace));
+        assert_eq!(tokens[2], ("2".to_string(), Category::Text));
+    }
+
+    #[test]
+    fn scopes_are_assigned_to_tokens() {
+        let tokens: Vec<_> = Tokenizer::new("if a #b").run(StateFunction(initial_state)).collect();
+
+        assert_eq!(tokens[0].scope.as_deref(), Some("keyword.control.test"));
+        assert_eq!(tokens[2].scope, None);
+        assert_eq!(tokens[4].scope.as_deref(), Some("comment.block.test"));
+    }
+
+    #[test]
+    fn errors_are_tokenized_and_reported() {
+        let mut tokens = Tokenizer::new("(a\nb").run(StateFunction(initial_state));
+        let lexed: Vec<_> = tokens.by_ref().map(|token| (token.lexeme.to_string(), token.category)).collect();
+
+        assert_eq!(lexed[2], ("\n".to_string(), Category::Error));
+        assert_eq!(lexed[3], ("b".to_string(), Category::Text));
+
+        let diagnostics = tokens.into_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, Kind::UnexpectedCharacter);
+        assert_eq!(diagnostics[0].span.range(), 2..3);
+        assert_eq!(diagnostics[0].message, "unclosed parenthesis");
+    }
+
+    #[test]
+    fn end_rules_report_unterminated_data() {
+        let mut tokens = Tokenizer::new("#a*/ #b").run(StateFunction(initial_state));
+        assert_eq!(tokens.by_ref().count(), 3);
+
+        let diagnostics = tokens.into_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, Kind::UnterminatedComment);
+        assert_eq!(diagnostics[0].span.range(), 5..7);
+    }
+
+    #[test]
+    fn regex_rules_match_at_the_cursor() {
+        let tokens = lex("1.5 x2.0");
+
+        assert_eq!(tokens[0], ("1.5".to_string(), Category::Float));
+        assert_eq!(tokens[2], ("x".to_string(), Category::Text));
+        assert_eq!(tokens[3], ("2.0".to_string(), Category::Float));
+    }
+
+    #[test]
+    fn states_can_contain_many_rules() {
+        lexer_states! {
+            keywords {
+                literal("a0") => Keyword; literal("a1") => Keyword; literal("a2") => Keyword; literal("a3") => Keyword;
+                literal("a4") => Keyword; literal("a5") => Keyword; literal("a6") => Keyword; literal("a7") => Keyword;
+                literal("b0") => Keyword; literal("b1") => Keyword; literal("b2") => Keyword; literal("b3") => Keyword;
+                literal("b4") => Keyword; literal("b5") => Keyword; literal("b6") => Keyword; literal("b7") => Keyword;
+                literal("c0") => Keyword; literal("c1") => Keyword; literal("c2") => Keyword; literal("c3") => Keyword;
+                literal("c4") => Keyword; literal("c5") => Keyword; literal("c6") => Keyword; literal("c7") => Keyword;
+                literal("d0") => Keyword; literal("d1") => Keyword; literal("d2") => Keyword; literal("d3") => Keyword;
+                literal("d4") => Keyword; literal("d5") => Keyword; literal("d6") => Keyword; literal("d7") => Keyword;
+                literal("e0") => Keyword; literal("e1") => Keyword; literal("e2") => Keyword; literal("e3") => Keyword;
+                literal("e4") => Keyword; literal("e5") => Keyword; literal("e6") => Keyword; literal("e7") => Keyword;
+                literal("f0") => Keyword; literal("f1") => Keyword; literal("f2") => Keyword; literal("f3") => Keyword;
+                literal("f4") => Keyword; literal("f5") => Keyword; literal("f6") => Keyword; literal("f7") => Keyword;
+                literal("g0") => Keyword; literal("g1") => Keyword; literal("g2") => Keyword; literal("g3") => Keyword;
+                literal("g4") => Keyword; literal("g5") => Keyword; literal("g6") => Keyword; literal("g7") => Keyword;
+                literal("h0") => Keyword; literal("h1") => Keyword; literal("h2") => Keyword; literal("h3") => Keyword;
+                literal("h4") => Keyword; literal("h5") => Keyword; literal("h6") => Keyword; literal("h7") => Keyword;
+            }
+        }
+
+        let tokens: Vec<_> = Tokenizer::new("h7a0").run(StateFunction(keywords)).collect();
+        assert_eq!(tokens.len(), 2);
+        assert!(tokens.iter().all(|token| token.category == Category::Keyword));
+    }
+}
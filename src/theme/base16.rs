@@ -0,0 +1,150 @@
+//! Loads base16 colour schemes, written in YAML. Both the original format,
+//! in which the name is given by a `scheme` key, and the newer format, in
+//! which the colours are nested beneath a `palette` key, are supported.
+//!
+//! Styles are assigned to categories according to base16's styling
+//! guidelines (e.g. `base0E` for keywords, and `base0B` for strings).
+
+use std::collections::HashMap;
+use token::Category;
+use super::{Color, Error, Style, Theme};
+
+pub(crate) fn parse(data: &str) -> Result<Theme, Error> {
+    let mut values = HashMap::new();
+
+    for line in data.lines() {
+        let line = line.trim();
+        if line.starts_with('#') { continue }
+
+        if let Some(index) = line.find(':') {
+            let key = line[..index].trim();
+            values.insert(key.to_string(), scalar(&line[index + 1..]));
+        }
+    }
+
+    let base = |index: usize| {
+        let key = format!("base{:02X}", index);
+        values
+            .get(&key)
+            .or_else(|| values.get(&key.to_lowercase()))
+            .and_then(|value| Color::from_hex(value))
+            .ok_or_else(|| Error::Parse(format!("missing or invalid {} colour", key)))
+    };
+    let mut colors = Vec::with_capacity(16);
+    for index in 0..16 {
+        colors.push(base(index)?);
+    }
+
+    let name = values.get("scheme").or_else(|| values.get("name")).map(String::as_str).unwrap_or("");
+    let mut theme = Theme::new(name);
+    theme.background = Some(colors[0x00]);
+    theme.line_highlight = Some(colors[0x01]);
+    theme.gutter_foreground = Some(colors[0x03]);
+    theme.foreground = Some(colors[0x05]);
+
+    let foreground = |index: usize| Style{ foreground: Some(colors[index]), ..Style::default() };
+    let styles = [
+        (Category::Keyword, foreground(0x0E)),
+        (Category::Operator, foreground(0x05)),
+        (Category::Integer, foreground(0x09)),
+        (Category::Float, foreground(0x09)),
+        (Category::Boolean, foreground(0x09)),
+        (Category::Constant, foreground(0x09)),
+        (Category::Literal, foreground(0x09)),
+        (Category::String, foreground(0x0B)),
+        (Category::Escape, foreground(0x0C)),
+        (Category::Regex, foreground(0x0C)),
+        (Category::Interpolation, foreground(0x0F)),
+        (Category::Comment, foreground(0x03)),
+        (Category::DocComment, foreground(0x03)),
+        (Category::Function, foreground(0x0D)),
+        (Category::Method, foreground(0x0D)),
+        (Category::Call, foreground(0x0D)),
+        (Category::Macro, foreground(0x0C)),
+        (Category::Type, foreground(0x0A)),
+        (Category::Namespace, foreground(0x0A)),
+        (Category::Attribute, foreground(0x0A)),
+        (Category::Label, foreground(0x08)),
+        (Category::Lifetime, foreground(0x0E)),
+        (Category::Key, foreground(0x08)),
+        (Category::Error, Style{ underline: true, ..foreground(0x08) }),
+    ];
+    for (category, style) in styles {
+        theme.set_style(category, style);
+    }
+
+    Ok(theme)
+}
+
+// Reads a YAML scalar, removing quotes and trailing comments.
+fn scalar(value: &str) -> String {
+    let value = value.trim();
+
+    for quote in ['"', '\''] {
+        if let Some(quoted) = value.strip_prefix(quote) {
+            return quoted.split(quote).next().unwrap_or("").to_string()
+        }
+    }
+
+    value.split(" #").next().unwrap_or("").trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+    use theme::Color;
+    use token::Category;
+
+    const SCHEME: &str = "\
+scheme: \"Tomorrow Night\"
+author: \"Chris Kempson (http://chriskempson.com)\"
+base00: \"1d1f21\"
+base01: \"282a2e\"
+base02: \"373b41\"
+base03: \"969896\"
+base04: \"b4b7b4\"
+base05: \"c5c8c6\"
+base06: \"e0e0e0\"
+base07: \"ffffff\"
+base08: \"cc6666\"
+base09: \"de935f\"
+base0A: \"f0c674\"
+base0B: \"b5bd68\"
+base0C: \"8abeb7\"
+base0D: \"81a2be\"
+base0E: \"b294bb\"
+base0F: \"a3685a\" # brown
+";
+
+    #[test]
+    fn it_assigns_styles_using_the_styling_guidelines() {
+        let theme = parse(SCHEME).unwrap();
+
+        assert_eq!(theme.name, "Tomorrow Night");
+        assert_eq!(theme.background, Color::from_hex("1d1f21"));
+        assert_eq!(theme.foreground, Color::from_hex("c5c8c6"));
+        assert_eq!(theme.style(&Category::Keyword).foreground, Color::from_hex("b294bb"));
+        assert_eq!(theme.style(&Category::String).foreground, Color::from_hex("b5bd68"));
+        assert_eq!(theme.style(&Category::Interpolation).foreground, Color::from_hex("a3685a"));
+        assert!(theme.style(&Category::Identifier).is_plain());
+    }
+
+    #[test]
+    fn it_supports_the_palette_format() {
+        let scheme = SCHEME
+            .replace("scheme:", "system: \"base16\"\nname:")
+            .replace("base00:", "palette:\n  base00:")
+            .replace("\"1d1f21\"", "\"#1d1f21\"")
+            .replace("\nbase", "\n  base");
+        let theme = parse(&scheme).unwrap();
+
+        assert_eq!(theme.name, "Tomorrow Night");
+        assert_eq!(theme.background, Color::from_hex("1d1f21"));
+    }
+
+    #[test]
+    fn it_requires_every_base_colour() {
+        let error = parse(&SCHEME.replace("base0C", "base0c_")).unwrap_err();
+        assert_eq!(error.to_string(), "missing or invalid base0C colour");
+    }
+}
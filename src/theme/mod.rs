@@ -0,0 +1,403 @@
+//! Themes, which describe how tokens should be styled when rendered (see
+//! the `render` module).
+//!
+//! Themes can be built in code, or loaded from TextMate/Sublime Text
+//! `.tmTheme` files (see `Theme::from_tmtheme`) and base16 colour schemes
+//! (see `Theme::from_base16`). Styles are looked up using a token's scope
+//! and category (see `Theme::resolve`).
+//!
+//! # Examples
+//!
+//! ```
+//! use luthor::theme::{Color, Style, Theme};
+//! use luthor::token::Category;
+//!
+//! let mut theme = Theme::default();
+//! theme.set_style(Category::Keyword, Style{
+//!     foreground: Color::from_hex("#ff0000"),
+//!     bold: true,
+//!     ..Style::default()
+//! });
+//! theme.add_rule("keyword.control", Style{
+//!     foreground: Color::from_hex("#0000ff"),
+//!     ..Style::default()
+//! });
+//!
+//! assert!(theme.style(&Category::Keyword).bold);
+//! assert!(!theme.resolve(&Category::Keyword, Some("keyword.control.js")).bold);
+//! ```
+
+use std::collections::HashMap;
+use std::{error, fmt, fs, io};
+use std::path::Path;
+use token::{Category, Token};
+
+mod base16;
+mod plist;
+mod selector;
+mod textmate;
+
+pub use self::selector::{Score, Selector};
+
+/// The error produced when a theme cannot be loaded.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref error) => error.fmt(f),
+            Error::Parse(ref message) => f.write_str(message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref error) => Some(error),
+            Error::Parse(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+/// A 24-bit RGB colour.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub fn new(r: u8, g: u8, b: u8) -> Color {
+        Color{ r, g, b }
+    }
+
+    /// Parses a colour written in hex notation, with or without a leading
+    /// `#`, in either its long (`#rrggbb`) or short (`#rgb`) form. An alpha
+    /// component (`#rrggbbaa`), as found in TextMate themes, is ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use luthor::theme::Color;
+    ///
+    /// assert_eq!(Color::from_hex("#ff8000"), Some(Color::new(255, 128, 0)));
+    /// assert_eq!(Color::from_hex("f80"), Some(Color::new(255, 136, 0)));
+    /// assert_eq!(Color::from_hex("orange"), None);
+    /// ```
+    pub fn from_hex(hex: &str) -> Option<Color> {
+        let hex = hex.trim_start_matches('#');
+        if !hex.is_ascii() { return None }
+
+        let component = |index: usize, width: usize| {
+            let value = u8::from_str_radix(&hex[index * width..(index + 1) * width], 16).ok()?;
+            Some(if width == 1 { value * 17 } else { value })
+        };
+
+        match hex.len() {
+            3 => Some(Color::new(component(0, 1)?, component(1, 1)?, component(2, 1)?)),
+            6 | 8 => Some(Color::new(component(0, 2)?, component(1, 2)?, component(2, 2)?)),
+            _ => None,
+        }
+    }
+
+    /// The colour in `#rrggbb` notation.
+    pub fn to_hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+/// The presentation of a category of token. Colours that aren't
+/// set fall back to the theme's default foreground and background.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct Style {
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl Style {
+    /// Whether the style has no effect on the text it's applied to.
+    pub fn is_plain(&self) -> bool {
+        *self == Style::default()
+    }
+}
+
+/// A named set of styles, assigned to token categories,
+/// as well as to scopes using TextMate scope selectors.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Theme {
+    pub name: String,
+
+    /// The default text colour.
+    pub foreground: Option<Color>,
+
+    /// The default background colour.
+    pub background: Option<Color>,
+
+    /// The background colour of highlighted lines.
+    pub line_highlight: Option<Color>,
+
+    /// The colour of line numbers.
+    pub gutter_foreground: Option<Color>,
+
+    styles: HashMap<Category, Style>,
+    rules: Vec<(Selector, Style)>,
+}
+
+impl Theme {
+    /// Creates an empty theme, in which every category is unstyled.
+    pub fn new(name: &str) -> Theme {
+        Theme{
+            name: name.to_string(),
+            foreground: None,
+            background: None,
+            line_highlight: None,
+            gutter_foreground: None,
+            styles: HashMap::new(),
+            rules: Vec::new(),
+        }
+    }
+
+    /// Parses a TextMate/Sublime Text `.tmTheme` file (an XML property
+    /// list), adding a rule for each of its scoped settings.
+    pub fn from_tmtheme(data: &str) -> Result<Theme, Error> {
+        textmate::parse(data)
+    }
+
+    /// Parses a base16 colour scheme, written in YAML.
+    pub fn from_base16(data: &str) -> Result<Theme, Error> {
+        base16::parse(data)
+    }
+
+    /// Loads a theme from a file, which is parsed as a TextMate theme if it
+    /// has a `.tmTheme` extension or looks like XML, and as a base16 scheme
+    /// otherwise.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Theme, Error> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path)?;
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("");
+
+        if extension.eq_ignore_ascii_case("tmtheme") || data.trim_start().starts_with('<') {
+            Theme::from_tmtheme(&data)
+        } else {
+            Theme::from_base16(&data)
+        }
+    }
+
+    /// Sets the style used for the specified category.
+    pub fn set_style(&mut self, category: Category, style: Style) {
+        self.styles.insert(category, style);
+    }
+
+    /// Adds a rule styling the scopes matched by the selector (see
+    /// `Selector`). When several rules match a scope, the most specific one
+    /// is used, and later rules take precedence over equally specific ones.
+    pub fn add_rule(&mut self, selector: &str, style: Style) {
+        self.rules.push((Selector::parse(selector), style));
+    }
+
+    /// The style used for a token with the specified category and scope,
+    /// which is the first of the following that the theme describes:
+    ///
+    /// 1. the rule best matching the scope
+    /// 2. the category's style
+    /// 3. the rule best matching the category's conventional
+    ///    TextMate scope (see `default_scope`)
+    ///
+    /// If none of them are described, the style is plain.
+    pub fn resolve(&self, category: &Category, scope: Option<&str>) -> Style {
+        scope
+            .and_then(|scope| self.rule_style(scope))
+            .or_else(|| self.styles.get(category).cloned())
+            .or_else(|| default_scope(category).and_then(|scope| self.rule_style(scope)))
+            .unwrap_or_default()
+    }
+
+    /// The style used for the token (see `resolve`).
+    pub fn token_style(&self, token: &Token) -> Style {
+        self.resolve(&token.category, token.scope.as_deref())
+    }
+
+    /// The style used for tokens of the specified category
+    /// that don't have a scope (see `resolve`).
+    pub fn style(&self, category: &Category) -> Style {
+        self.resolve(category, None)
+    }
+
+    fn rule_style(&self, scope: &str) -> Option<Style> {
+        let mut best: Option<(Score, &Style)> = None;
+        for (selector, style) in &self.rules {
+            if let Some(score) = selector.score(&[scope]) {
+                if best.is_none_or(|(best_score, _)| score >= best_score) {
+                    best = Some((score, style));
+                }
+            }
+        }
+
+        best.map(|(_, style)| *style)
+    }
+}
+
+/// The TextMate scope conventionally used for a category, which is used to
+/// style tokens without scopes using themes that only describe scopes.
+pub fn default_scope(category: &Category) -> Option<&'static str> {
+    match *category {
+        Category::Whitespace | Category::Text => None,
+        Category::Identifier => Some("variable"),
+        Category::Keyword => Some("keyword"),
+        Category::Brace | Category::Bracket | Category::Parenthesis => Some("punctuation.section"),
+        Category::Punctuation => Some("punctuation"),
+        Category::Operator => Some("keyword.operator"),
+        Category::Integer | Category::Float => Some("constant.numeric"),
+        Category::String => Some("string"),
+        Category::Escape => Some("constant.character.escape"),
+        Category::Interpolation => Some("meta.embedded"),
+        Category::Regex => Some("string.regexp"),
+        Category::Boolean | Category::Constant => Some("constant.language"),
+        Category::Comment => Some("comment"),
+        Category::DocComment => Some("comment.block.documentation"),
+        Category::Function | Category::Method => Some("entity.name.function"),
+        Category::Call => Some("variable.function"),
+        Category::Macro => Some("entity.name.function.macro"),
+        Category::Type => Some("entity.name.type"),
+        Category::Namespace => Some("entity.name.namespace"),
+        Category::Attribute => Some("entity.other.attribute-name"),
+        Category::Label => Some("entity.name.label"),
+        Category::Lifetime => Some("storage.modifier.lifetime"),
+        Category::Literal => Some("constant.other"),
+        Category::Key => Some("support.type.property-name"),
+        Category::Error => Some("invalid.illegal"),
+    }
+}
+
+impl Default for Theme {
+    /// A dark theme, based on the Tomorrow Night palette.
+    fn default() -> Theme {
+        let color = |hex| Color::from_hex(hex);
+        let foreground = |hex| Style{ foreground: color(hex), ..Style::default() };
+
+        let mut theme = Theme::new("Tomorrow Night");
+        theme.foreground = color("#c5c8c6");
+        theme.background = color("#1d1f21");
+        theme.line_highlight = color("#282a2e");
+        theme.gutter_foreground = color("#4d5057");
+
+        let comment = Style{ italic: true, ..foreground("#969896") };
+        let styles = [
+            (Category::Keyword, foreground("#b294bb")),
+            (Category::Operator, foreground("#8abeb7")),
+            (Category::Integer, foreground("#de935f")),
+            (Category::Float, foreground("#de935f")),
+            (Category::Boolean, foreground("#de935f")),
+            (Category::Constant, foreground("#de935f")),
+            (Category::Literal, foreground("#de935f")),
+            (Category::String, foreground("#b5bd68")),
+            (Category::Escape, foreground("#8abeb7")),
+            (Category::Interpolation, foreground("#cc6666")),
+            (Category::Regex, foreground("#8abeb7")),
+            (Category::Comment, comment),
+            (Category::DocComment, comment),
+            (Category::Function, foreground("#81a2be")),
+            (Category::Method, foreground("#81a2be")),
+            (Category::Call, foreground("#81a2be")),
+            (Category::Macro, foreground("#8abeb7")),
+            (Category::Type, foreground("#f0c674")),
+            (Category::Namespace, foreground("#f0c674")),
+            (Category::Attribute, foreground("#f0c674")),
+            (Category::Label, foreground("#cc6666")),
+            (Category::Lifetime, foreground("#cc6666")),
+            (Category::Key, foreground("#cc6666")),
+            (Category::Error, Style{ underline: true, ..foreground("#cc6666") }),
+        ];
+
+        for (category, style) in styles {
+            theme.set_style(category, style);
+        }
+
+        theme
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Color, Style, Theme};
+    use token::Category;
+
+    fn style(hex: &str) -> Style {
+        Style{ foreground: Color::from_hex(hex), ..Style::default() }
+    }
+
+    #[test]
+    fn from_hex_rejects_malformed_colors() {
+        assert_eq!(Color::from_hex("#ff00"), None);
+        assert_eq!(Color::from_hex("#gg0000"), None);
+        assert_eq!(Color::from_hex("#éa000"), None);
+    }
+
+    #[test]
+    fn from_hex_ignores_alpha() {
+        assert_eq!(Color::from_hex("#10203040"), Some(Color::new(16, 32, 48)));
+    }
+
+    #[test]
+    fn to_hex_round_trips() {
+        let color = Color::new(1, 171, 255);
+        assert_eq!(color.to_hex(), "#01abff");
+        assert_eq!(Color::from_hex(&color.to_hex()), Some(color));
+    }
+
+    #[test]
+    fn style_is_plain_for_undescribed_categories() {
+        let theme = Theme::new("empty");
+        assert!(theme.style(&Category::Keyword).is_plain());
+        assert_eq!(theme.style(&Category::Keyword), Style::default());
+    }
+
+    #[test]
+    fn scopes_take_precedence_over_categories() {
+        let mut theme = Theme::new("test");
+        theme.set_style(Category::String, style("#000001"));
+        theme.add_rule("string.quoted", style("#000002"));
+
+        assert_eq!(theme.resolve(&Category::String, Some("string.quoted.double.js")), style("#000002"));
+        assert_eq!(theme.resolve(&Category::String, Some("string.unquoted.js")), style("#000001"));
+        assert_eq!(theme.resolve(&Category::String, None), style("#000001"));
+    }
+
+    #[test]
+    fn categories_fall_back_to_default_scopes() {
+        let mut theme = Theme::new("test");
+        theme.add_rule("comment", style("#000001"));
+        theme.add_rule("constant.numeric", style("#000002"));
+
+        assert_eq!(theme.style(&Category::DocComment), style("#000001"));
+        assert_eq!(theme.style(&Category::Float), style("#000002"));
+        assert_eq!(theme.resolve(&Category::Integer, Some("keyword.other")), style("#000002"));
+        assert!(theme.style(&Category::Text).is_plain());
+    }
+
+    #[test]
+    fn later_rules_win_ties() {
+        let mut theme = Theme::new("test");
+        theme.add_rule("string", style("#000001"));
+        theme.add_rule("string.quoted", style("#000002"));
+        theme.add_rule("comment, string", style("#000003"));
+
+        assert_eq!(theme.resolve(&Category::String, Some("string.quoted.single")), style("#000002"));
+        assert_eq!(theme.resolve(&Category::String, Some("string.unquoted")), style("#000003"));
+    }
+}
@@ -0,0 +1,266 @@
+//! A minimal reader for XML property lists, which is all that's needed to
+//! load TextMate themes. Values other than dictionaries, arrays and strings
+//! are recognized, but their contents are discarded.
+
+use super::Error;
+
+#[derive(PartialEq, Debug, Clone)]
+pub(crate) enum Value {
+    Dict(Vec<(String, Value)>),
+    Array(Vec<Value>),
+    String(String),
+    Other,
+}
+
+impl Value {
+    /// Looks up a key in a dictionary.
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::Dict(ref entries) => {
+                entries.iter().find(|(name, _)| name == key).map(|(_, value)| value)
+            },
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Value]> {
+        match *self {
+            Value::Array(ref values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a property list document, returning its root value.
+pub(crate) fn parse(data: &str) -> Result<Value, Error> {
+    let mut parser = Parser{ data, position: 0 };
+
+    parser.skip_prolog();
+    let (name, empty) = parser.open_tag()?;
+    let value = if name == "plist" && !empty {
+        parser.skip_prolog();
+        let (name, empty) = parser.open_tag()?;
+        let value = parser.value(&name, empty)?;
+        parser.skip_prolog();
+        parser.close_tag("plist")?;
+
+        value
+    } else {
+        parser.value(&name, empty)?
+    };
+
+    Ok(value)
+}
+
+struct Parser<'a> {
+    data: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn remaining(&self) -> &'a str {
+        &self.data[self.position..]
+    }
+
+    // Skips whitespace, along with XML declarations, doctypes, and comments.
+    fn skip_prolog(&mut self) {
+        loop {
+            let trimmed = self.remaining().trim_start();
+            self.position = self.data.len() - trimmed.len();
+
+            let terminator = if trimmed.starts_with("<?") {
+                "?>"
+            } else if trimmed.starts_with("<!--") {
+                "-->"
+            } else if trimmed.starts_with("<!") {
+                ">"
+            } else {
+                return
+            };
+
+            match trimmed.find(terminator) {
+                Some(index) => self.position += index + terminator.len(),
+                None => self.position = self.data.len(),
+            }
+        }
+    }
+
+    // Reads an opening tag, returning its name and whether it's empty (e.g. `<true/>`).
+    fn open_tag(&mut self) -> Result<(String, bool), Error> {
+        let remaining = self.remaining();
+        if !remaining.starts_with('<') || remaining.starts_with("</") {
+            return Err(self.error("expected an opening tag"))
+        }
+
+        let end = remaining.find('>').ok_or_else(|| self.error("unterminated tag"))?;
+        let tag = &remaining[1..end];
+        let empty = tag.ends_with('/');
+        let name = tag.trim_end_matches('/').split_whitespace().next().unwrap_or("").to_string();
+        self.position += end + 1;
+
+        Ok((name, empty))
+    }
+
+    fn close_tag(&mut self, name: &str) -> Result<(), Error> {
+        let tag = format!("</{}>", name);
+        if !self.remaining().starts_with(&tag) {
+            return Err(self.error(&format!("expected {}", tag)))
+        }
+        self.position += tag.len();
+
+        Ok(())
+    }
+
+    // Reads the text up to the element's closing tag, decoding entities.
+    fn text(&mut self, name: &str) -> Result<String, Error> {
+        let tag = format!("</{}>", name);
+        let end = self.remaining().find(&tag).ok_or_else(|| self.error(&format!("expected {}", tag)))?;
+        let text = decode(&self.remaining()[..end]);
+        self.position += end + tag.len();
+
+        Ok(text)
+    }
+
+    // Reads the value of an element whose opening tag has just been read.
+    fn value(&mut self, name: &str, empty: bool) -> Result<Value, Error> {
+        match name {
+            "dict" => {
+                let mut entries = Vec::new();
+                if empty { return Ok(Value::Dict(entries)) }
+
+                loop {
+                    self.skip_prolog();
+                    if self.remaining().starts_with("</") {
+                        self.close_tag("dict")?;
+                        return Ok(Value::Dict(entries))
+                    }
+
+                    let (tag, empty) = self.open_tag()?;
+                    if tag != "key" {
+                        return Err(self.error("expected a dictionary key"))
+                    }
+                    let key = if empty { String::new() } else { self.text("key")? };
+
+                    self.skip_prolog();
+                    let (tag, empty) = self.open_tag()?;
+                    entries.push((key, self.value(&tag, empty)?));
+                }
+            },
+            "array" => {
+                let mut values = Vec::new();
+                if empty { return Ok(Value::Array(values)) }
+
+                loop {
+                    self.skip_prolog();
+                    if self.remaining().starts_with("</") {
+                        self.close_tag("array")?;
+                        return Ok(Value::Array(values))
+                    }
+
+                    let (tag, empty) = self.open_tag()?;
+                    values.push(self.value(&tag, empty)?);
+                }
+            },
+            "string" => {
+                if empty { Ok(Value::String(String::new())) } else { Ok(Value::String(self.text(name)?)) }
+            },
+            "true" | "false" | "integer" | "real" | "date" | "data" => {
+                if !empty { self.text(name)?; }
+                Ok(Value::Other)
+            },
+            _ => Err(self.error(&format!("unexpected <{}> element", name))),
+        }
+    }
+
+    fn error(&self, message: &str) -> Error {
+        let line = self.data[..self.position].matches('\n').count() + 1;
+
+        Error::Parse(format!("{} on line {}", message, line))
+    }
+}
+
+// Decodes the predefined XML entities, as well as character references.
+fn decode(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut remaining = text;
+
+    while let Some(index) = remaining.find('&') {
+        decoded.push_str(&remaining[..index]);
+        remaining = &remaining[index..];
+
+        let entity = remaining.find(';').map(|end| (&remaining[1..end], end));
+        let character = entity.and_then(|(name, _)| match name {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if name.starts_with("#x") => u32::from_str_radix(&name[2..], 16).ok().and_then(char::from_u32),
+            _ if name.starts_with('#') => name[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        });
+
+        match (character, entity) {
+            (Some(character), Some((_, end))) => {
+                decoded.push(character);
+                remaining = &remaining[end + 1..];
+            },
+            _ => {
+                decoded.push('&');
+                remaining = &remaining[1..];
+            }
+        }
+    }
+    decoded.push_str(remaining);
+
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Value};
+
+    #[test]
+    fn it_parses_nested_values() {
+        let data = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+            <plist version=\"1.0\">\n\
+            <!-- a comment -->\n\
+            <dict>\n\
+              <key>name</key>\n\
+              <string>A &amp; B &#x263A;</string>\n\
+              <key>settings</key>\n\
+              <array>\n\
+                <dict><key>empty</key><string/><key>flag</key><true/></dict>\n\
+                <dict/>\n\
+              </array>\n\
+            </dict>\n\
+            </plist>";
+        let value = parse(data).unwrap();
+
+        assert_eq!(value.get("name").and_then(Value::as_str), Some("A & B \u{263A}"));
+
+        let settings = value.get("settings").and_then(Value::as_array).unwrap();
+        assert_eq!(settings[0], Value::Dict(vec![
+            ("empty".to_string(), Value::String(String::new())),
+            ("flag".to_string(), Value::Other),
+        ]));
+        assert_eq!(settings[1], Value::Dict(Vec::new()));
+    }
+
+    #[test]
+    fn it_reports_malformed_documents() {
+        let error = parse("<plist>\n<dict>\n<string>value</string>\n</dict>\n</plist>").unwrap_err();
+        assert_eq!(error.to_string(), "expected a dictionary key on line 3");
+
+        assert!(parse("<plist><dict><key>name</key><string>open").is_err());
+        assert!(parse("").is_err());
+    }
+}
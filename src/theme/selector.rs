@@ -0,0 +1,141 @@
+//! TextMate scope selectors, which choose the tokens that theme rules apply to.
+
+/// A parsed scope selector, such as `string, comment - comment.block`.
+///
+/// Selectors consist of comma-separated alternatives, each of which is a
+/// space-separated path of scope names, optionally followed by paths to
+/// exclude (each introduced by `-`). A scope name in a selector matches any
+/// scope that it's a dot-separated prefix of, so `string.quoted` matches
+/// `string.quoted.double.js`, but not `string.quotedx`.
+///
+/// # Examples
+///
+/// ```
+/// use luthor::theme::Selector;
+///
+/// let selector = Selector::parse("string, comment - comment.block");
+///
+/// assert!(selector.matches(&["string.quoted.double.js"]));
+/// assert!(selector.matches(&["comment.line.double-slash.js"]));
+/// assert!(!selector.matches(&["comment.block.js"]));
+/// assert!(!selector.matches(&["keyword.control.js"]));
+/// ```
+#[derive(PartialEq, Debug, Clone)]
+pub struct Selector {
+    alternatives: Vec<Alternative>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+struct Alternative {
+    path: Vec<String>,
+    exclusions: Vec<Vec<String>>,
+}
+
+/// The specificity of a selector's match against a scope stack, used to
+/// choose between several matching selectors; greater scores are more
+/// specific. Matches against deeper scopes rank highest, followed by
+/// matches with more scope name components, and then longer paths.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub struct Score {
+    depth: usize,
+    components: usize,
+    length: usize,
+}
+
+impl Selector {
+    pub fn parse(selector: &str) -> Selector {
+        let alternatives = selector
+            .split(',')
+            .filter_map(|alternative| {
+                let mut paths = alternative.split(" -").map(|path| {
+                    path.split_whitespace().map(str::to_string).collect::<Vec<String>>()
+                });
+                let path = paths.next().filter(|path| !path.is_empty())?;
+
+                Some(Alternative{ path, exclusions: paths.filter(|path| !path.is_empty()).collect() })
+            })
+            .collect();
+
+        Selector{ alternatives }
+    }
+
+    /// Whether the selector matches the scope stack, ordered
+    /// from the outermost scope to the innermost.
+    pub fn matches(&self, scopes: &[&str]) -> bool {
+        self.score(scopes).is_some()
+    }
+
+    /// The specificity of the selector's best matching
+    /// alternative, if any of them match the scope stack.
+    pub fn score(&self, scopes: &[&str]) -> Option<Score> {
+        self.alternatives
+            .iter()
+            .filter(|alternative| {
+                !alternative.exclusions.iter().any(|exclusion| match_path(exclusion, scopes).is_some())
+            })
+            .filter_map(|alternative| match_path(&alternative.path, scopes))
+            .max()
+    }
+}
+
+// Matches each of the path's scope names, in order, against the scope
+// stack, scoring the match by its innermost (i.e. last) scope name.
+fn match_path(path: &[String], scopes: &[&str]) -> Option<Score> {
+    let mut remaining = scopes.iter().enumerate();
+    let mut score = None;
+
+    for name in path {
+        let (depth, _) = remaining.find(|&(_, scope)| is_prefix(name, scope))?;
+        score = Some(Score{
+            depth: depth + 1,
+            components: name.split('.').count(),
+            length: path.len(),
+        });
+    }
+
+    score
+}
+
+fn is_prefix(name: &str, scope: &str) -> bool {
+    scope.starts_with(name) && matches!(scope.as_bytes().get(name.len()), None | Some(b'.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Selector;
+
+    #[test]
+    fn names_match_on_component_boundaries() {
+        let selector = Selector::parse("string.quoted");
+
+        assert!(selector.matches(&["string.quoted"]));
+        assert!(selector.matches(&["string.quoted.double"]));
+        assert!(!selector.matches(&["string.quotedx"]));
+        assert!(!selector.matches(&["string"]));
+    }
+
+    #[test]
+    fn paths_match_scopes_in_order() {
+        let selector = Selector::parse("source.js string");
+
+        assert!(selector.matches(&["source.js", "meta.block", "string.quoted"]));
+        assert!(!selector.matches(&["string.quoted", "source.js"]));
+        assert!(!selector.matches(&["string.quoted"]));
+    }
+
+    #[test]
+    fn more_specific_matches_score_higher() {
+        let scopes = ["source.js", "string.quoted.double"];
+        let score = |selector| Selector::parse(selector).score(&scopes).unwrap();
+
+        assert!(score("string.quoted") > score("string"));
+        assert!(score("string") > score("source.js"));
+        assert!(score("source string") > score("string"));
+    }
+
+    #[test]
+    fn empty_selectors_match_nothing() {
+        assert!(!Selector::parse("").matches(&["string"]));
+        assert!(!Selector::parse(" , ").matches(&["string"]));
+    }
+}
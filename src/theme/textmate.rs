@@ -0,0 +1,147 @@
+//! Loads TextMate (and Sublime Text) `.tmTheme` files.
+
+use super::{Color, Error, Style, Theme};
+use super::plist::{self, Value};
+
+pub(crate) fn parse(data: &str) -> Result<Theme, Error> {
+    let root = plist::parse(data)?;
+    let settings = root
+        .get("settings")
+        .and_then(Value::as_array)
+        .ok_or_else(|| Error::Parse("missing settings array".to_string()))?;
+
+    let mut theme = Theme::new(root.get("name").and_then(Value::as_str).unwrap_or(""));
+
+    for entry in settings {
+        let properties = match entry.get("settings") {
+            Some(properties) => properties,
+            None => continue,
+        };
+        let color = |key| properties.get(key).and_then(Value::as_str).and_then(Color::from_hex);
+
+        match entry.get("scope").and_then(Value::as_str) {
+            Some(scope) => {
+                let font_style = properties.get("fontStyle").and_then(Value::as_str).unwrap_or("");
+                let has_font_style = |name| font_style.split_whitespace().any(|style| style == name);
+
+                theme.add_rule(scope, Style{
+                    foreground: color("foreground"),
+                    background: color("background"),
+                    bold: has_font_style("bold"),
+                    italic: has_font_style("italic"),
+                    underline: has_font_style("underline"),
+                });
+            },
+            None => {
+                // Entries without a scope describe the editor itself.
+                theme.foreground = color("foreground").or(theme.foreground);
+                theme.background = color("background").or(theme.background);
+                theme.line_highlight = color("lineHighlight").or(theme.line_highlight);
+                theme.gutter_foreground = color("gutterForeground").or(theme.gutter_foreground);
+            }
+        }
+    }
+
+    Ok(theme)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+    use theme::{Color, Style};
+    use token::Category;
+
+    const THEME: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>name</key>
+    <string>Test</string>
+    <key>settings</key>
+    <array>
+        <dict>
+            <key>settings</key>
+            <dict>
+                <key>background</key>
+                <string>#272822</string>
+                <key>foreground</key>
+                <string>#F8F8F2</string>
+                <key>lineHighlight</key>
+                <string>#3E3D32</string>
+            </dict>
+        </dict>
+        <dict>
+            <key>name</key>
+            <string>Comment</string>
+            <key>scope</key>
+            <string>comment</string>
+            <key>settings</key>
+            <dict>
+                <key>foreground</key>
+                <string>#75715E</string>
+                <key>fontStyle</key>
+                <string>italic</string>
+            </dict>
+        </dict>
+        <dict>
+            <key>scope</key>
+            <string>string, constant.character.escape</string>
+            <key>settings</key>
+            <dict>
+                <key>foreground</key>
+                <string>#E6DB74</string>
+            </dict>
+        </dict>
+        <dict>
+            <key>scope</key>
+            <string>string.quoted.single</string>
+            <key>settings</key>
+            <dict>
+                <key>foreground</key>
+                <string>#FD971F</string>
+                <key>fontStyle</key>
+                <string>bold underline</string>
+            </dict>
+        </dict>
+    </array>
+</dict>
+</plist>"##;
+
+    #[test]
+    fn it_loads_global_settings() {
+        let theme = parse(THEME).unwrap();
+
+        assert_eq!(theme.name, "Test");
+        assert_eq!(theme.background, Some(Color::new(0x27, 0x28, 0x22)));
+        assert_eq!(theme.foreground, Some(Color::new(0xf8, 0xf8, 0xf2)));
+        assert_eq!(theme.line_highlight, Some(Color::new(0x3e, 0x3d, 0x32)));
+        assert_eq!(theme.gutter_foreground, None);
+    }
+
+    #[test]
+    fn it_resolves_the_most_specific_rule() {
+        let theme = parse(THEME).unwrap();
+        let yellow = Color::from_hex("#e6db74");
+        let orange = Color::from_hex("#fd971f");
+
+        assert_eq!(theme.resolve(&Category::String, Some("string.quoted.double.js")).foreground, yellow);
+        assert_eq!(
+            theme.resolve(&Category::String, Some("string.quoted.single.js")),
+            Style{ foreground: orange, bold: true, underline: true, ..Style::default() }
+        );
+    }
+
+    #[test]
+    fn it_resolves_categories_without_scopes() {
+        let theme = parse(THEME).unwrap();
+
+        assert!(theme.style(&Category::Comment).italic);
+        assert!(theme.style(&Category::DocComment).italic);
+        assert_eq!(theme.style(&Category::Escape).foreground, Color::from_hex("#e6db74"));
+        assert!(theme.style(&Category::Keyword).is_plain());
+    }
+
+    #[test]
+    fn it_requires_settings() {
+        assert!(parse("<plist><dict><key>name</key><string>Test</string></dict></plist>").is_err());
+    }
+}
@@ -1,7 +1,39 @@
 //! Token-related types.
+//!
+//! # Serialization
+//!
+//! With the `serde` feature enabled, tokens (along with their categories,
+//! positions and spans) implement `Serialize` and `Deserialize`. Categories
+//! are represented by their names (see `Category::name`), and the remaining
+//! types by their fields, so a token is represented in JSON as follows:
+//!
+//! ```json
+//! {
+//!   "lexeme": "fn",
+//!   "category": "keyword",
+//!   "span": {
+//!     "start": { "offset": 0, "line": 0, "column": 0, "utf16_column": 0 },
+//!     "end": { "offset": 2, "line": 0, "column": 2, "utf16_column": 2 }
+//!   },
+//!   "scope": "keyword.other.fn.rust"
+//! }
+//! ```
+//!
+//! Tokens without a scope have a `null` scope, which may also be omitted
+//! when deserializing. `BorrowedToken` has the same representation, but
+//! can only be serialized.
+
+use std::borrow::Cow;
+use std::ops::Range;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// The primary means of classifying a format or language's lexemes.
-#[derive(PartialEq, Debug, Clone)]
+/// Delimiters that don't come in pairs (e.g. `,`, `;` and `::`) are
+/// `Punctuation`, while `Text` is reserved for content that lexers
+/// don't classify at all, such as the text between XML tags.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "snake_case"))]
 pub enum Category {
     Whitespace,
     Identifier,
@@ -9,24 +41,346 @@ pub enum Category {
     Brace,
     Bracket,
     Parenthesis,
+    Punctuation,
     Operator,
     Integer,
     Float,
     String,
+    Escape,
+    Interpolation,
+    Regex,
     Boolean,
+    Constant,
     Text,
     Comment,
+    DocComment,
     Function,
     Method,
     Call,
+    Macro,
+    Type,
+    Namespace,
+    Attribute,
+    Label,
+    Lifetime,
     Literal,
     Key,
+    Error,
+}
+
+impl Category {
+    /// Every category, in declaration order.
+    pub const ALL: &'static [Category] = &[
+        Category::Whitespace,
+        Category::Identifier,
+        Category::Keyword,
+        Category::Brace,
+        Category::Bracket,
+        Category::Parenthesis,
+        Category::Punctuation,
+        Category::Operator,
+        Category::Integer,
+        Category::Float,
+        Category::String,
+        Category::Escape,
+        Category::Interpolation,
+        Category::Regex,
+        Category::Boolean,
+        Category::Constant,
+        Category::Text,
+        Category::Comment,
+        Category::DocComment,
+        Category::Function,
+        Category::Method,
+        Category::Call,
+        Category::Macro,
+        Category::Type,
+        Category::Namespace,
+        Category::Attribute,
+        Category::Label,
+        Category::Lifetime,
+        Category::Literal,
+        Category::Key,
+        Category::Error,
+    ];
+
+    /// The category's name in snake case (e.g. `doc_comment`),
+    /// suitable for use in CSS class names and serialized output.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use luthor::token::Category;
+    ///
+    /// assert_eq!(Category::DocComment.name(), "doc_comment");
+    /// ```
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Category::Whitespace => "whitespace",
+            Category::Identifier => "identifier",
+            Category::Keyword => "keyword",
+            Category::Brace => "brace",
+            Category::Bracket => "bracket",
+            Category::Parenthesis => "parenthesis",
+            Category::Punctuation => "punctuation",
+            Category::Operator => "operator",
+            Category::Integer => "integer",
+            Category::Float => "float",
+            Category::String => "string",
+            Category::Escape => "escape",
+            Category::Interpolation => "interpolation",
+            Category::Regex => "regex",
+            Category::Boolean => "boolean",
+            Category::Constant => "constant",
+            Category::Text => "text",
+            Category::Comment => "comment",
+            Category::DocComment => "doc_comment",
+            Category::Function => "function",
+            Category::Method => "method",
+            Category::Call => "call",
+            Category::Macro => "macro",
+            Category::Type => "type",
+            Category::Namespace => "namespace",
+            Category::Attribute => "attribute",
+            Category::Label => "label",
+            Category::Lifetime => "lifetime",
+            Category::Literal => "literal",
+            Category::Key => "key",
+            Category::Error => "error",
+        }
+    }
 }
 
-/// A lexeme and category pairing. Tokens are the final product of a lexer; 
+/// A location in the data passed to a lexer. Offsets are measured in bytes,
+/// while lines and columns are zero-based. Columns are tracked both in
+/// characters and in UTF-16 code units, the latter being what editors
+/// and language servers typically expect.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    pub utf16_column: usize,
+}
+
+impl Position {
+    /// Moves the position past the specified character.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use luthor::token::Position;
+    ///
+    /// let mut position = Position::default();
+    /// position.advance('é');
+    /// assert_eq!(position.offset, 2);
+    /// assert_eq!(position.column, 1);
+    ///
+    /// position.advance('\n');
+    /// assert_eq!(position.line, 1);
+    /// assert_eq!(position.column, 0);
+    /// ```
+    pub fn advance(&mut self, c: char) {
+        self.offset += c.len_utf8();
+
+        if c == '\n' {
+            self.line += 1;
+            self.column = 0;
+            self.utf16_column = 0;
+        } else {
+            self.column += 1;
+            self.utf16_column += c.len_utf16();
+        }
+    }
+
+    /// Converts a position relative to a fragment of data into one relative
+    /// to the data containing it, given the position at which the fragment
+    /// begins. Useful when combining tokens produced by a nested lexer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use luthor::token::Position;
+    ///
+    /// let origin = Position{ offset: 10, line: 2, column: 4, utf16_column: 4 };
+    /// let position = Position{ offset: 1, line: 0, column: 1, utf16_column: 1 };
+    ///
+    /// assert_eq!(
+    ///     position.translate(origin),
+    ///     Position{ offset: 11, line: 2, column: 5, utf16_column: 5 }
+    /// );
+    /// ```
+    pub fn translate(&self, origin: Position) -> Position {
+        self.rebase(Position::default(), origin)
+    }
+
+    /// Moves a position that follows `from` so that it instead follows `to`
+    /// by the same distance, e.g. to account for data inserted before it.
+    pub(crate) fn rebase(&self, from: Position, to: Position) -> Position {
+        let offset = self.offset - from.offset + to.offset;
+
+        if self.line == from.line {
+            Position{
+                offset,
+                line: to.line,
+                column: self.column - from.column + to.column,
+                utf16_column: self.utf16_column - from.utf16_column + to.utf16_column,
+            }
+        } else {
+            Position{
+                offset,
+                line: self.line - from.line + to.line,
+                column: self.column,
+                utf16_column: self.utf16_column,
+            }
+        }
+    }
+}
+
+/// The region of data covered by a token, from its
+/// start position up to (but not including) its end.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    /// The byte range covered by the span, suitable for slicing the lexed data.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let data = "lex luthor";
+    /// let tokens = luthor::lexers::default::lex(data);
+    ///
+    /// assert_eq!(&data[tokens[2].span.range()], "luthor");
+    /// ```
+    pub fn range(&self) -> Range<usize> {
+        self.start.offset..self.end.offset
+    }
+
+    /// Converts a span relative to a fragment of data into one relative to
+    /// the data containing it. See `Position::translate` for details.
+    pub fn translate(&self, origin: Position) -> Span {
+        Span{
+            start: self.start.translate(origin),
+            end: self.end.translate(origin),
+        }
+    }
+}
+
+/// A lexeme and category pairing. Tokens are the final product of a lexer;
 /// their lexemes should join to produce the original data passed to the lexer.
+/// The span records where the lexeme was found in that data.
+///
+/// Tokens can also carry a scope: a dotted, TextMate-style name refining the
+/// category, from the general to the specific (e.g. `comment.line.double-slash.rust`
+/// for a `Category::Comment` token). Each segment narrows the one before it,
+/// and the last usually names the language. Lexers' scopes are static
+/// strings, borrowed rather than allocated for each token.
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Token {
     pub lexeme: String,
     pub category: Category,
+    pub span: Span,
+    pub scope: Option<Cow<'static, str>>,
+}
+
+/// A token whose lexeme is borrowed from the lexed data, rather than copied
+/// into a `String`. Lexers produce these without allocating for each token;
+/// use `into_owned` (or `Token::from`) when the token must outlive the data.
+///
+/// # Examples
+///
+/// ```
+/// use luthor::token::Token;
+///
+/// let data = String::from("lex luthor");
+/// let token = luthor::lexers::default::lex_borrowed(&data).last().unwrap();
+/// assert_eq!(token.lexeme, "luthor");
+///
+/// let token: Token = token.into_owned();
+/// drop(data);
+/// assert_eq!(token.lexeme, "luthor");
+/// ```
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct BorrowedToken<'a> {
+    pub lexeme: &'a str,
+    pub category: Category,
+    pub span: Span,
+    pub scope: Option<Cow<'static, str>>,
+}
+
+impl<'a> BorrowedToken<'a> {
+    /// Converts the token into one that owns its lexeme.
+    pub fn into_owned(self) -> Token {
+        Token{
+            lexeme: self.lexeme.to_string(),
+            category: self.category,
+            span: self.span,
+            scope: self.scope,
+        }
+    }
+}
+
+impl<'a> From<BorrowedToken<'a>> for Token {
+    fn from(token: BorrowedToken<'a>) -> Token {
+        token.into_owned()
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::{Category, Token};
+    use serde_json;
+
+    #[test]
+    fn categories_are_serialized_by_name() {
+        for category in Category::ALL {
+            let json = serde_json::to_string(category).unwrap();
+            assert_eq!(json, format!("\"{}\"", category.name()));
+            assert_eq!(&serde_json::from_str::<Category>(&json).unwrap(), category);
+        }
+    }
+
+    #[test]
+    fn tokens_round_trip_through_json() {
+        let tokens = ::lexers::rust::lex("fn main() {}");
+        let json = serde_json::to_string(&tokens).unwrap();
+
+        assert_eq!(serde_json::from_str::<Vec<Token>>(&json).unwrap(), tokens);
+        assert_eq!(
+            serde_json::to_value(&tokens[0]).unwrap(),
+            serde_json::json!({
+                "lexeme": "fn",
+                "category": "keyword",
+                "span": {
+                    "start": { "offset": 0, "line": 0, "column": 0, "utf16_column": 0 },
+                    "end": { "offset": 2, "line": 0, "column": 2, "utf16_column": 2 }
+                },
+                "scope": tokens[0].scope
+            })
+        );
+    }
+
+    #[test]
+    fn scopes_are_optional() {
+        let json = r#"{
+            "lexeme": "luthor",
+            "category": "text",
+            "span": {
+                "start": { "offset": 0, "line": 0, "column": 0, "utf16_column": 0 },
+                "end": { "offset": 6, "line": 0, "column": 6, "utf16_column": 6 }
+            }
+        }"#;
+        let token: Token = serde_json::from_str(json).unwrap();
+
+        assert_eq!(token.category, Category::Text);
+        assert_eq!(token.scope, None);
+    }
 }
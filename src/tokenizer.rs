@@ -1,9 +1,17 @@
 //! Utility module for lexer implementations,
 //! providing types to help manage states and tokens.
 
-use std::str::Chars;
-use super::token::Token;
-use super::token::Category;
+use std::any::Any;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::collections::vec_deque::Drain;
+use std::fmt::Debug;
+use std::ptr;
+use std::vec;
+use super::diagnostic::{Diagnostic, Kind};
+use super::regex::Regex;
+use super::token::{BorrowedToken, Category, Position, Span, Token};
 
 /// A recursive function type used by lexers to manage their state.
 /// Based on Rob Pike's "Lexical Scanning in Go" talk, these functions are
@@ -11,13 +19,75 @@ use super::token::Category;
 /// the next) until a `None` value is returned, after which lexing is complete.
 ///
 /// See the `lexers` module for examples.
+#[derive(Clone, Copy, Debug)]
 pub struct StateFunction(pub fn(&mut Tokenizer) -> Option<StateFunction>);
 
+// State functions are equal when they wrap the same function.
+impl PartialEq for StateFunction {
+    fn eq(&self, other: &StateFunction) -> bool {
+        ptr::fn_addr_eq(self.0, other.0)
+    }
+}
+
+/// Custom state that lexers can keep in the tokenizer alongside their state
+/// functions, such as a heredoc's terminator or the depth of nested braces.
+/// It's captured in the checkpoints used by incremental lexing, and so must
+/// be cloneable and comparable; any such type implements this trait.
+pub trait UserState: Any + Debug {
+    /// Clones the state into a new box.
+    fn clone_box(&self) -> Box<dyn UserState>;
+
+    /// Whether or not the state is equal to another, which may be of a different type.
+    fn eq_box(&self, other: &dyn UserState) -> bool;
+
+    /// Converts the state into `Any`, so that it can be downcast.
+    fn as_any(&self) -> &dyn Any;
+
+    /// Converts the state into `Any`, so that it can be downcast and modified.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any + Clone + PartialEq + Debug> UserState for T {
+    fn clone_box(&self) -> Box<dyn UserState> {
+        Box::new(self.clone())
+    }
+
+    fn eq_box(&self, other: &dyn UserState) -> bool {
+        other.as_any().downcast_ref::<T>() == Some(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Clone for Box<dyn UserState> {
+    fn clone(&self) -> Box<dyn UserState> {
+        (**self).clone_box()
+    }
+}
+
+impl PartialEq for Box<dyn UserState> {
+    fn eq(&self, other: &Box<dyn UserState>) -> bool {
+        (**self).eq_box(&**other)
+    }
+}
+
 /// The Tokenizer type is used to produce and store tokens for lexers.
+/// It maintains a byte cursor into the data, recording token boundaries
+/// as offsets; tokens borrow their lexemes from the data, which is never copied.
 pub struct Tokenizer<'a> {
-    data: Chars<'a>,
-    current_token: String,
-    tokens: Vec<Token>,
+    data: &'a str,
+    token_start: Position,
+    position: Position,
+    lookahead: Cell<usize>,
+    tokens: Vec<BorrowedToken<'a>>,
+    diagnostics: Vec<Diagnostic>,
+    user_state: Option<Box<dyn UserState>>,
     pub states: Vec<StateFunction>,
 }
 
@@ -29,12 +99,23 @@ impl<'a> Tokenizer<'a> {
     /// ```
     /// let tokenizer = luthor::Tokenizer::new("luthor");
     /// ```
-    pub fn new(data: &str) -> Tokenizer {
+    pub fn new(data: &str) -> Tokenizer<'_> {
+        Tokenizer::resume(data, Position::default(), vec![], None)
+    }
+
+    /// Initializes a tokenizer part way through the data, at the
+    /// specified position and with the specified state stack and user state.
+    pub(crate) fn resume(data: &str, position: Position, states: Vec<StateFunction>,
+                         user_state: Option<Box<dyn UserState>>) -> Tokenizer<'_> {
         Tokenizer{
-          data: data.chars(),
-          current_token: String::new(),
+          data,
+          token_start: position,
+          position,
+          lookahead: Cell::new(position.offset),
           tokens: vec![],
-          states: vec![]
+          diagnostics: vec![],
+          user_state,
+          states
         }
     }
 
@@ -46,40 +127,98 @@ impl<'a> Tokenizer<'a> {
     /// # Examples
     ///
     /// ```
-    /// use luthor::token::{Category, Token};
+    /// use luthor::token::Category;
     ///
     /// // Set up a new tokenizer.
     /// let mut tokenizer = luthor::Tokenizer::new("luthor");
     /// tokenizer.tokenize_next(2, Category::Keyword);
     ///
-    /// assert_eq!(
-    ///     tokenizer.tokens(),
-    ///     vec![
-    ///         Token{ lexeme: "lu".to_string(), category: Category::Keyword },
-    ///         Token{ lexeme: "thor".to_string(), category: Category::Text }
-    ///     ]
-    /// );
+    /// let tokens = tokenizer.tokens();
+    /// assert_eq!(tokens[0].lexeme, "lu");
+    /// assert_eq!(tokens[0].category, Category::Keyword);
+    /// assert_eq!(tokens[1].lexeme, "thor");
+    /// assert_eq!(tokens[1].category, Category::Text);
     ///
     /// ```
     pub fn tokens(&self) -> Vec<Token> {
+        self.tokens.iter().cloned().chain(self.remainder()).map(Token::from).collect()
+    }
+
+    /// Like `tokens`, but without copying lexemes out of the data.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use luthor::token::Category;
+    ///
+    /// let mut tokenizer = luthor::Tokenizer::new("luthor");
+    /// tokenizer.tokenize_next(2, Category::Keyword);
+    ///
+    /// let tokens = tokenizer.borrowed_tokens();
+    /// assert_eq!(tokens[0].lexeme, "lu");
+    /// assert_eq!(tokens[1].lexeme, "thor");
+    /// ```
+    pub fn borrowed_tokens(&self) -> Vec<BorrowedToken<'a>> {
         let mut tokens = self.tokens.clone();
+        tokens.extend(self.remainder());
 
-        // Duplicate the tokenizer's character iterator so that we can
-        // advance it to check for equality without affecting the original.
-        let data_iter = self.data.clone();
+        tokens
+    }
 
-        // Append any remaining data to the in-progress token.
-        let mut remaining_data = self.current_token.clone();
-        for c in data_iter {
-            remaining_data.push(c);
+    /// Consumes the tokenizer, returning an iterator that lazily runs the
+    /// specified state function (and those that follow it) as tokens are
+    /// requested. Once the states are exhausted, any in-progress or
+    /// remaining data is yielded as a final text-category token, as with
+    /// `tokens`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use luthor::{StateFunction, Tokenizer};
+    /// use luthor::token::Category;
+    ///
+    /// fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+    ///     if tokenizer.current_char().is_some() {
+    ///         tokenizer.tokenize_next(1, Category::Text);
+    ///         Some(StateFunction(initial_state))
+    ///     } else {
+    ///         None
+    ///     }
+    /// }
+    ///
+    /// let tokenizer = Tokenizer::new("luthor");
+    /// let mut tokens = tokenizer.run(StateFunction(initial_state));
+    /// assert_eq!(tokens.next().unwrap().lexeme, "l");
+    /// assert_eq!(tokens.count(), 5);
+    /// ```
+    pub fn run(self, state: StateFunction) -> Tokens<'a> {
+        Tokens{
+            tokenizer: self,
+            state: Some(state),
+            buffer: VecDeque::new(),
         }
+    }
 
-        // If there was any remaining or in-progress data, add it as a text token.
-        if !remaining_data.is_empty() {
-            tokens.push(Token{ lexeme: remaining_data, category: Category::Text});
+    /// Builds a text-category token out of any in-progress or remaining data.
+    pub(crate) fn remainder(&self) -> Option<BorrowedToken<'a>> {
+        // Find where the remaining data ends, which we
+        // need to walk to determine the final line and column.
+        let mut end = self.position;
+        for c in self.remaining_data().chars() {
+            end.advance(c);
         }
 
-        tokens
+        // If there was any remaining or in-progress data, it forms a text token.
+        if end.offset == self.token_start.offset {
+            None
+        } else {
+            Some(BorrowedToken{
+                lexeme: &self.data[self.token_start.offset..end.offset],
+                category: Category::Text,
+                span: Span{ start: self.token_start, end },
+                scope: None,
+            })
+        }
     }
 
     /// Moves to the next character in the data.
@@ -101,12 +240,35 @@ impl<'a> Tokenizer<'a> {
     /// assert_eq!(tokenizer.current_char().unwrap(), 'u');
     /// ```
     pub fn advance(&mut self) {
-        match self.data.next() {
-            Some(c) => self.current_token.push(c),
-            None => ()
+        match self.remaining_data().chars().next() {
+            Some(c) => {
+                self.position.advance(c);
+                self.peeked(Some(0));
+            },
+            None => self.peeked(None),
         }
     }
 
+    /// Returns the position of the next character to be processed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// // Set up a new tokenizer.
+    /// let mut tokenizer = luthor::Tokenizer::new("l\nuthor");
+    ///
+    /// // Consume the first line.
+    /// tokenizer.advance();
+    /// tokenizer.advance();
+    ///
+    /// assert_eq!(tokenizer.position().offset, 2);
+    /// assert_eq!(tokenizer.position().line, 1);
+    /// assert_eq!(tokenizer.position().column, 0);
+    /// ```
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
     /// Returns the character at the current position,
     /// unless all of the data has been processed.
     ///
@@ -126,10 +288,10 @@ impl<'a> Tokenizer<'a> {
     /// assert_eq!(tokenizer.current_char(), None);
     /// ```
     pub fn current_char(&self) -> Option<char> {
-        match self.data.clone().peekable().peek() {
-            Some(c) => Some(c.clone()),
-            None => None
-        }
+        let c = self.remaining_data().chars().next();
+        self.peeked(c.map(|c| c.len_utf8()));
+
+        c
     }
 
     /// Returns the next non-whitespace character, without advancing the cursor.
@@ -149,11 +311,11 @@ impl<'a> Tokenizer<'a> {
     ///
     /// ```
     pub fn next_non_whitespace_char(&self) -> Option<char> {
-        // Duplicate the tokenizer's character iterator so that we can
-        // advance it to check for equality without affecting the original.
-        let mut data_iter = self.data.clone();
+        let data = self.remaining_data();
+        let index = data.find(|c| c != ' ' && c != '\n');
+        self.peeked(index.map(|index| index + 1));
 
-        data_iter.find(|&c| c != ' ' && c != '\n')
+        index.and_then(|index| data[index..].chars().next())
     }
 
     /// Whether or not the remaining data starts with the specified prefix.
@@ -167,19 +329,10 @@ impl<'a> Tokenizer<'a> {
     /// assert!(tokenizer.has_prefix("le"));
     /// ```
     pub fn has_prefix(&self, prefix: &str) -> bool {
-        // Duplicate the tokenizer's character iterator so that we can
-        // advance it to check for equality without affecting the original.
-        let mut data_iter = self.data.clone();
-
-        // Check that the subject is a prefix, character by character.
-        // This is much faster than building a string of equal length from
-        // self.data and deferring to a straight string comparison using ==.
-        prefix.chars().all(|c| {
-            match data_iter.next() {
-                Some(d) => c == d,
-                None => false
-            }
-        })
+        let data = self.remaining_data();
+        self.peeked(if prefix.len() <= data.len() { Some(prefix.len()) } else { None });
+
+        data.as_bytes().starts_with(prefix.as_bytes())
     }
 
     /// Whether or not the remaining data starts with the specified lexeme.
@@ -219,21 +372,276 @@ impl<'a> Tokenizer<'a> {
     /// assert!(tokenizer.starts_with_lexeme("rary"));
     /// ```
     pub fn starts_with_lexeme(&self, lexeme: &str) -> bool {
-        // Duplicate the tokenizer's character iterator so that we can
-        // advance it to check for equality without affecting the original.
-        let data_iter = self.data.clone();
-
-        self.current_token.is_empty() &&
-        self.has_prefix(lexeme) &&
-        match data_iter.skip(lexeme.len()).next() {
-            Some(' ') | Some('\n') | Some(',') => true,
-            None => true,
-            _ => false
+        let data = self.remaining_data().as_bytes();
+        self.peeked(if lexeme.len() < data.len() { Some(lexeme.len() + 1) } else { None });
+
+        !self.has_pending_data() &&
+        data.starts_with(lexeme.as_bytes()) &&
+        matches!(data.get(lexeme.len()), Some(b' ') | Some(b'\n') | Some(b',') | None)
+    }
+
+    /// Returns the lexer's custom state, initializing it using its default
+    /// value if it hasn't been set, or was set using a different type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut tokenizer = luthor::Tokenizer::new("luthor");
+    /// assert_eq!(*tokenizer.user_state::<usize>(), 0);
+    ///
+    /// *tokenizer.user_state::<usize>() += 1;
+    /// assert_eq!(*tokenizer.user_state::<usize>(), 1);
+    /// ```
+    pub fn user_state<T: UserState + Default>(&mut self) -> &mut T {
+        // The boxes are dereferenced explicitly, since they implement the trait too.
+        let initialized = self.user_state.as_ref()
+            .is_some_and(|state| (**state).as_any().is::<T>());
+        if !initialized {
+            self.user_state = Some(Box::new(T::default()));
+        }
+
+        self.user_state.as_mut()
+            .and_then(|state| (**state).as_any_mut().downcast_mut())
+            .expect("user state was just initialized")
+    }
+
+    /// Sets the lexer's custom state, replacing any existing state.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut tokenizer = luthor::Tokenizer::new("luthor");
+    /// tokenizer.set_user_state(String::from("EOS"));
+    ///
+    /// assert_eq!(tokenizer.user_state::<String>(), "EOS");
+    /// ```
+    pub fn set_user_state<T: UserState>(&mut self, state: T) {
+        self.user_state = Some(Box::new(state));
+    }
+
+    /// A copy of the lexer's custom state, if it has been set.
+    pub(crate) fn saved_user_state(&self) -> Option<Box<dyn UserState>> {
+        self.user_state.clone()
+    }
+
+    /// Whether or not the lexer's custom state matches the saved state.
+    pub(crate) fn has_user_state(&self, state: &Option<Box<dyn UserState>>) -> bool {
+        self.user_state == *state
+    }
+
+    /// Matches the longest of the specified literals at the current
+    /// position, returning its length in bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let tokenizer = luthor::Tokenizer::new("<=>");
+    ///
+    /// assert_eq!(tokenizer.match_literal(&["<", "<=", ">"]), Some(2));
+    /// assert_eq!(tokenizer.match_literal(&[">"]), None);
+    /// ```
+    pub fn match_literal(&self, literals: &[&str]) -> Option<usize> {
+        literals.iter()
+            .filter(|literal| !literal.is_empty() && self.has_prefix(literal))
+            .map(|literal| literal.len())
+            .max()
+    }
+
+    /// Matches the longest of the specified keywords at the current position,
+    /// returning its length in bytes. Unlike literals, keywords must not be
+    /// followed by an alphanumeric character or underscore.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let tokenizer = luthor::Tokenizer::new("format");
+    ///
+    /// assert_eq!(tokenizer.match_keyword(&["for"]), None);
+    /// assert_eq!(tokenizer.match_keyword(&["for", "format"]), Some(6));
+    /// ```
+    pub fn match_keyword(&self, keywords: &[&str]) -> Option<usize> {
+        let data = self.remaining_data();
+
+        keywords.iter()
+            .filter(|keyword| !keyword.is_empty() && self.has_prefix(keyword))
+            .filter(|keyword| {
+                let next = data[keyword.len()..].chars().next();
+                self.peeked(next.map(|c| keyword.len() + c.len_utf8()));
+
+                !next.is_some_and(|c| c.is_alphanumeric() || c == '_')
+            })
+            .map(|keyword| keyword.len())
+            .max()
+    }
+
+    /// Matches one or more consecutive characters satisfying the
+    /// predicate at the current position, returning their length in bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let tokenizer = luthor::Tokenizer::new("123abc");
+    ///
+    /// assert_eq!(tokenizer.match_chars(|c| c.is_numeric()), Some(3));
+    /// assert_eq!(tokenizer.match_chars(|c| c.is_whitespace()), None);
+    /// ```
+    pub fn match_chars<F>(&self, predicate: F) -> Option<usize> where F: Fn(char) -> bool {
+        let data = self.remaining_data();
+        let length = match data.char_indices().find(|&(_, c)| !predicate(c)) {
+            Some((index, c)) => {
+                self.peeked(Some(index + c.len_utf8()));
+                index
+            },
+            None => {
+                self.peeked(None);
+                data.len()
+            }
+        };
+
+        if length > 0 { Some(length) } else { None }
+    }
+
+    /// Matches the regex at the current position, returning the length of the
+    /// match in bytes. Empty matches are ignored, as they can't form tokens.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use luthor::regex::Regex;
+    ///
+    /// let float = Regex::new(r"[0-9]+\.[0-9]+([eE][+-]?[0-9]+)?").unwrap();
+    /// let tokenizer = luthor::Tokenizer::new("1.5e-3 + 1");
+    ///
+    /// assert_eq!(tokenizer.match_regex(&float), Some(6));
+    /// ```
+    pub fn match_regex(&self, regex: &Regex) -> Option<usize> {
+        let (length, examined) = regex.match_at(self.data, self.position.offset);
+        self.peeked(examined);
+
+        length.filter(|&length| length > 0)
+    }
+
+    /// Creates and stores a token with the given category containing the data
+    /// matched by the regex at the current position, returning whether or not
+    /// it matched. As with `tokenize_next`, any previously processed characters
+    /// are first tokenized with the generic `Category::Text` category.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use luthor::regex::Regex;
+    /// use luthor::token::Category;
+    ///
+    /// let identifier = Regex::new("[a-z_][a-z0-9_]*").unwrap();
+    /// let mut tokenizer = luthor::Tokenizer::new("lex_luthor()");
+    ///
+    /// assert!(tokenizer.tokenize_regex(&identifier, Category::Identifier));
+    /// assert!(!tokenizer.tokenize_regex(&identifier, Category::Identifier));
+    /// assert_eq!(tokenizer.tokens()[0].lexeme, "lex_luthor");
+    /// assert_eq!(tokenizer.tokens()[0].category, Category::Identifier);
+    /// ```
+    pub fn tokenize_regex(&mut self, regex: &Regex, category: Category) -> bool {
+        match self.match_regex(regex) {
+            Some(length) => {
+                self.tokenize(Category::Text);
+                self.advance_bytes(length);
+                self.tokenize(category);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Advances past the specified number of bytes, such as
+    /// the length of a match produced by one of the `match_*` methods.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length exceeds the remaining data,
+    /// or doesn't end on a character boundary.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use luthor::token::Category;
+    ///
+    /// let mut tokenizer = luthor::Tokenizer::new("éclat");
+    /// tokenizer.advance_bytes(3);
+    /// tokenizer.tokenize(Category::Text);
+    ///
+    /// assert_eq!(tokenizer.tokens()[0].lexeme, "éc");
+    /// ```
+    pub fn advance_bytes(&mut self, length: usize) {
+        for c in self.remaining_data()[..length].chars() {
+            self.position.advance(c);
+        }
+        self.peeked(Some(0));
+    }
+
+    /// The furthest byte offset examined (exclusive) to date. Data at or beyond
+    /// this offset has had no influence on the tokens produced so far; if the
+    /// end of the data has been observed, this is one greater than its length.
+    pub(crate) fn lookahead(&self) -> usize {
+        self.lookahead.get()
+    }
+
+    /// Whether or not there's any data that has been advanced over but not yet tokenized.
+    pub(crate) fn has_pending_data(&self) -> bool {
+        self.position.offset != self.token_start.offset
+    }
+
+    /// The number of tokens produced to date.
+    pub(crate) fn token_count(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// Removes and returns the tokens produced to date.
+    pub(crate) fn drain_tokens(&mut self) -> vec::Drain<'_, BorrowedToken<'a>> {
+        self.tokens.drain(..)
+    }
+
+    /// Consumes the tokenizer, returning the tokens produced to date. Unlike
+    /// `tokens`, in-progress and remaining data are not included.
+    pub(crate) fn into_tokens(self) -> Vec<BorrowedToken<'a>> {
+        self.tokens
+    }
+
+    // Creates and stores a token containing any pending data.
+    fn push_token(&mut self, category: Category, scope: Option<Cow<'static, str>>) {
+        if self.has_pending_data() {
+            let token = BorrowedToken{
+                lexeme: &self.data[self.token_start.offset..self.position.offset],
+                category,
+                span: Span{ start: self.token_start, end: self.position },
+                scope,
+            };
+            self.tokens.push(token);
+            self.token_start = self.position;
+        }
+    }
+
+    // The data following the cursor, yet to be processed.
+    fn remaining_data(&self) -> &'a str {
+        &self.data[self.position.offset..]
+    }
+
+    // Records that the data has been examined up to the specified number of
+    // bytes beyond the current position, or to its end (if `None`).
+    fn peeked(&self, length: Option<usize>) {
+        let end = match length {
+            Some(length) => self.position.offset + length,
+            None => self.data.len() + 1,
+        };
+
+        if end > self.lookahead.get() {
+            self.lookahead.set(end);
         }
     }
 
     /// Creates and stores a token with the given category containing any
     /// data processed using `advance` since the last call to this method.
+    /// The token's span covers the data from where it started to the
+    /// tokenizer's current position.
     ///
     /// # Examples
     ///
@@ -250,16 +658,30 @@ impl<'a> Tokenizer<'a> {
     ///
     /// // Ensure that we have a correctly-categorized token.
     /// assert_eq!(tokenizer.tokens()[0].lexeme, "lu");
+    /// assert_eq!(tokenizer.tokens()[0].span.range(), 0..2);
     /// ```
     pub fn tokenize(&mut self, category: Category) {
-        if !self.current_token.is_empty() {
-            let token = Token{
-                lexeme: self.current_token.clone(),
-                category: category,
-            };
-            self.tokens.push(token);
-            self.current_token = String::new();
-        }
+        self.push_token(category, None);
+    }
+
+    /// Like `tokenize`, but also assigns the token a TextMate-style scope,
+    /// refining its category (see `Token`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use luthor::token::Category;
+    ///
+    /// let mut tokenizer = luthor::Tokenizer::new("// luthor");
+    /// tokenizer.advance();
+    /// tokenizer.advance();
+    /// tokenizer.tokenize_with_scope(Category::Comment, "comment.line.double-slash.rust");
+    ///
+    /// assert_eq!(tokenizer.tokens()[0].scope.as_deref(), Some("comment.line.double-slash.rust"));
+    /// assert_eq!(tokenizer.tokens()[1].scope, None);
+    /// ```
+    pub fn tokenize_with_scope(&mut self, category: Category, scope: &'static str) {
+        self.push_token(category, Some(Cow::Borrowed(scope)));
     }
 
     /// Creates and stores a token with the given category and the
@@ -271,7 +693,6 @@ impl<'a> Tokenizer<'a> {
     ///
     /// ```
     /// use luthor::token::Category;
-    /// use luthor::token::Token;
     ///
     /// // Set up a new tokenizer.
     /// let mut tokenizer = luthor::Tokenizer::new("luthor");
@@ -281,14 +702,11 @@ impl<'a> Tokenizer<'a> {
     /// tokenizer.tokenize_next(5, Category::Keyword);
     ///
     /// // Ensure that we have two properly-categorized tokens.
-    /// assert_eq!(
-    ///     tokenizer.tokens()[0],
-    ///     Token{ lexeme: "l".to_string(), category: Category::Text }
-    /// );
-    /// assert_eq!(
-    ///     tokenizer.tokens()[1],
-    ///     Token{ lexeme: "uthor".to_string(), category: Category::Keyword }
-    /// );
+    /// let tokens = tokenizer.tokens();
+    /// assert_eq!(tokens[0].lexeme, "l");
+    /// assert_eq!(tokens[0].category, Category::Text);
+    /// assert_eq!(tokens[1].lexeme, "uthor");
+    /// assert_eq!(tokens[1].category, Category::Keyword);
     /// ```
     pub fn tokenize_next(&mut self, amount: usize, category: Category) {
         // If there's any data that has yet
@@ -302,21 +720,26 @@ impl<'a> Tokenizer<'a> {
         self.tokenize(category);
     }
 
+    /// Like `tokenize_next`, but also assigns the token a TextMate-style scope,
+    /// refining its category (see `Token`).
+    pub fn tokenize_next_with_scope(&mut self, amount: usize, category: Category, scope: &'static str) {
+        self.tokenize(Category::Text);
+        for _ in 0..amount { self.advance(); }
+        self.tokenize_with_scope(category, scope);
+    }
+
     /// Consumes consecutive whitespace characters as a single token.
     ///
     /// # Examples
     ///
     /// ```
     /// use luthor::token::Category;
-    /// use luthor::token::Token;
     ///
     /// let mut tokenizer = luthor::Tokenizer::new("  \nluthor");
     /// tokenizer.consume_whitespace();
     ///
-    /// assert_eq!(
-    ///     tokenizer.tokens()[0],
-    ///     Token{ lexeme: "  \n".to_string(), category: Category::Whitespace }
-    /// );
+    /// assert_eq!(tokenizer.tokens()[0].lexeme, "  \n");
+    /// assert_eq!(tokenizer.tokens()[0].category, Category::Whitespace);
     /// ```
     pub fn consume_whitespace(&mut self) {
         let mut found_whitespace = false;
@@ -339,12 +762,109 @@ impl<'a> Tokenizer<'a> {
             }
         }
     }
+
+    /// Reports a problem with the data processed using `advance` since the
+    /// last token was created, which should be called before tokenizing it.
+    /// Lexers use this to describe malformed input, such as unterminated
+    /// strings; unexpected characters should also be tokenized using
+    /// `Category::Error`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use luthor::diagnostic::Kind;
+    /// use luthor::token::Category;
+    ///
+    /// let mut tokenizer = luthor::Tokenizer::new("lex¿");
+    /// tokenizer.tokenize_next(3, Category::Text);
+    /// tokenizer.advance();
+    /// tokenizer.report(Kind::UnexpectedCharacter, "unexpected character");
+    /// tokenizer.tokenize(Category::Error);
+    ///
+    /// let diagnostic = &tokenizer.diagnostics()[0];
+    /// assert_eq!(diagnostic.kind, Kind::UnexpectedCharacter);
+    /// assert_eq!(diagnostic.span.range(), 3..5);
+    /// ```
+    pub fn report(&mut self, kind: Kind, message: &str) {
+        self.diagnostics.push(Diagnostic{
+            kind,
+            span: Span{ start: self.token_start, end: self.position },
+            message: message.to_string(),
+        });
+    }
+
+    /// The problems reported to date.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+}
+
+/// A lazy token iterator, produced by `Tokenizer::run`. State functions are
+/// only invoked when more tokens are needed, so consumers that stop early
+/// don't pay for lexing the remainder of the data. Tokens borrow their
+/// lexemes from the data; see `BorrowedToken::into_owned`.
+pub struct Tokens<'a> {
+    tokenizer: Tokenizer<'a>,
+    state: Option<StateFunction>,
+    buffer: VecDeque<BorrowedToken<'a>>,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = BorrowedToken<'a>;
+
+    fn next(&mut self) -> Option<BorrowedToken<'a>> {
+        loop {
+            if let Some(token) = self.buffer.pop_front() {
+                return Some(token)
+            }
+
+            self.step()?;
+        }
+    }
+}
+
+impl<'a> Tokens<'a> {
+    /// Invokes the current state function, buffering the tokens it produces.
+    /// Returns the state function that was invoked, or `None` if lexing is done.
+    pub(crate) fn step(&mut self) -> Option<StateFunction> {
+        let state = self.state?;
+        let StateFunction(actual_function) = state;
+
+        // Invoke the current state function, falling back to the state
+        // stack when it's done. Once both are exhausted, whatever is left
+        // over is the final token.
+        self.state = actual_function(&mut self.tokenizer)
+            .or_else(|| self.tokenizer.states.pop());
+        self.buffer.extend(self.tokenizer.tokens.drain(..));
+        if self.state.is_none() {
+            self.buffer.extend(self.tokenizer.remainder());
+        }
+
+        Some(state)
+    }
+
+    /// The problems reported by the lexer for the tokens produced to date.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        self.tokenizer.diagnostics()
+    }
+
+    /// Consumes the iterator, returning the problems reported by
+    /// the lexer. Typically used once all tokens have been produced.
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.tokenizer.diagnostics
+    }
+
+    /// Removes and returns the tokens buffered by `step`.
+    pub(crate) fn drain_buffer(&mut self) -> Drain<'_, BorrowedToken<'a>> {
+        self.buffer.drain(..)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use tokenizer::Tokenizer;
-    use token::{Category, Token};
+    use regex::Regex;
+    use tokenizer::{StateFunction, Tokenizer};
+    use token::{Category, Position};
 
     #[test]
     fn current_char_returns_the_char_at_head() {
@@ -373,8 +893,8 @@ mod tests {
         tokenizer.tokenize(Category::Text);
 
         let token = tokenizer.tokens.pop().unwrap();
-        let expected_token = Token{ lexeme: "él".to_string(), category: Category::Text};
-        assert_eq!(token, expected_token);
+        assert_eq!(token.lexeme, "él");
+        assert_eq!(token.category, Category::Text);
     }
 
     #[test]
@@ -395,8 +915,8 @@ mod tests {
         tokenizer.tokenize_next(1, Category::Keyword);
 
         let token = tokenizer.tokens.remove(0);
-        let expected_token = Token{ lexeme: "él".to_string(), category: Category::Text};
-        assert_eq!(token, expected_token);
+        assert_eq!(token.lexeme, "él");
+        assert_eq!(token.category, Category::Text);
     }
 
     #[test]
@@ -408,8 +928,8 @@ mod tests {
         tokenizer.tokenize_next(5, Category::Keyword);
 
         let token = tokenizer.tokens.pop().unwrap();
-        let expected_token = Token{ lexeme: "égant".to_string(), category: Category::Keyword};
-        assert_eq!(token, expected_token);
+        assert_eq!(token.lexeme, "égant");
+        assert_eq!(token.category, Category::Keyword);
     }
 
     #[test]
@@ -421,8 +941,8 @@ mod tests {
         tokenizer.tokenize_next(15, Category::Keyword);
 
         let token = tokenizer.tokens.pop().unwrap();
-        let expected_token = Token{ lexeme: "égant".to_string(), category: Category::Keyword};
-        assert_eq!(token, expected_token);
+        assert_eq!(token.lexeme, "égant");
+        assert_eq!(token.category, Category::Keyword);
     }
 
     #[test]
@@ -432,24 +952,18 @@ mod tests {
         tokenizer.advance();
         tokenizer.consume_whitespace();
 
-        assert_eq!(
-            tokenizer.tokens()[0],
-            Token{ lexeme: "e".to_string(), category: Category::Text }
-        );
-        assert_eq!(
-            tokenizer.tokens()[1],
-            Token{ lexeme: "  ".to_string(), category: Category::Whitespace }
-        );
+        assert_eq!(tokenizer.tokens()[0].lexeme, "e");
+        assert_eq!(tokenizer.tokens()[0].category, Category::Text);
+        assert_eq!(tokenizer.tokens()[1].lexeme, "  ");
+        assert_eq!(tokenizer.tokens()[1].category, Category::Whitespace);
     }
 
     #[test]
     fn tokens_returns_unprocessed_data_as_text_token() {
         let tokenizer = Tokenizer::new("luthor");
 
-        assert_eq!(
-            tokenizer.tokens()[0],
-            Token{ lexeme: "luthor".to_string(), category: Category::Text }
-        );
+        assert_eq!(tokenizer.tokens()[0].lexeme, "luthor");
+        assert_eq!(tokenizer.tokens()[0].category, Category::Text);
     }
 
     #[test]
@@ -457,10 +971,8 @@ mod tests {
         let mut tokenizer = Tokenizer::new("luthor");
         tokenizer.advance();
 
-        assert_eq!(
-            tokenizer.tokens()[0],
-            Token{ lexeme: "luthor".to_string(), category: Category::Text }
-        );
+        assert_eq!(tokenizer.tokens()[0].lexeme, "luthor");
+        assert_eq!(tokenizer.tokens()[0].category, Category::Text);
     }
 
     #[test]
@@ -470,4 +982,165 @@ mod tests {
 
         assert!(!tokenizer.starts_with_lexeme("uthor"));
     }
+
+    #[test]
+    fn starts_with_lexeme_handles_multibyte_lexemes() {
+        let tokenizer = Tokenizer::new("élan vital");
+
+        assert!(tokenizer.starts_with_lexeme("élan"));
+        assert!(!tokenizer.starts_with_lexeme("él"));
+    }
+
+    #[test]
+    fn has_prefix_returns_false_if_the_prefix_exceeds_the_data() {
+        let tokenizer = Tokenizer::new("lu");
+
+        assert!(!tokenizer.has_prefix("luthor"));
+    }
+
+    #[test]
+    fn match_literal_ignores_empty_literals() {
+        let tokenizer = Tokenizer::new("luthor");
+
+        assert_eq!(tokenizer.match_literal(&[""]), None);
+    }
+
+    #[test]
+    fn match_keyword_accepts_keywords_followed_by_punctuation_or_the_end() {
+        assert_eq!(Tokenizer::new("if(").match_keyword(&["if"]), Some(2));
+        assert_eq!(Tokenizer::new("if").match_keyword(&["if"]), Some(2));
+        assert_eq!(Tokenizer::new("if_").match_keyword(&["if"]), None);
+    }
+
+    #[test]
+    fn match_chars_records_the_character_that_ends_the_match_as_examined() {
+        let tokenizer = Tokenizer::new("12é");
+        tokenizer.match_chars(|c| c.is_numeric());
+
+        assert_eq!(tokenizer.lookahead(), 4);
+    }
+
+    #[test]
+    fn match_regex_records_the_data_examined_by_the_regex() {
+        let regex = Regex::new("abc|a").unwrap();
+        let tokenizer = Tokenizer::new("abd");
+
+        assert_eq!(tokenizer.match_regex(&regex), Some(1));
+        assert_eq!(tokenizer.lookahead(), 3);
+    }
+
+    #[test]
+    fn match_regex_ignores_empty_matches() {
+        let regex = Regex::new("a*").unwrap();
+        let tokenizer = Tokenizer::new("bcd");
+
+        assert_eq!(tokenizer.match_regex(&regex), None);
+    }
+
+    #[test]
+    fn tokenize_regex_tokenizes_previous_data_as_text() {
+        let regex = Regex::new("[0-9]+").unwrap();
+        let mut tokenizer = Tokenizer::new("x1");
+        tokenizer.advance();
+        tokenizer.tokenize_regex(&regex, Category::Integer);
+
+        let tokens = tokenizer.tokens();
+        assert_eq!(tokens[0].lexeme, "x");
+        assert_eq!(tokens[0].category, Category::Text);
+        assert_eq!(tokens[1].lexeme, "1");
+        assert_eq!(tokens[1].category, Category::Integer);
+    }
+
+    #[test]
+    fn user_state_is_replaced_by_a_default_of_a_different_type() {
+        let mut tokenizer = Tokenizer::new("luthor");
+        tokenizer.set_user_state(5usize);
+
+        assert_eq!(tokenizer.user_state::<String>(), "");
+    }
+
+    #[test]
+    fn saved_user_state_compares_values_of_the_same_type() {
+        let mut tokenizer = Tokenizer::new("luthor");
+        assert!(tokenizer.saved_user_state().is_none());
+
+        tokenizer.set_user_state(String::from("#"));
+        let saved = tokenizer.saved_user_state();
+        assert!(saved == tokenizer.saved_user_state());
+
+        tokenizer.user_state::<String>().push('#');
+        assert!(saved != tokenizer.saved_user_state());
+
+        tokenizer.set_user_state(1);
+        assert!(saved != tokenizer.saved_user_state());
+    }
+
+    #[test]
+    fn tokenize_records_token_spans() {
+        let mut tokenizer = Tokenizer::new("é\nl");
+        tokenizer.tokenize_next(2, Category::Text);
+        tokenizer.tokenize_next(1, Category::Text);
+
+        let tokens = tokenizer.tokens();
+        assert_eq!(tokens[0].span.start, Position{ offset: 0, line: 0, column: 0, utf16_column: 0 });
+        assert_eq!(tokens[0].span.end, Position{ offset: 3, line: 1, column: 0, utf16_column: 0 });
+        assert_eq!(tokens[1].span.start, Position{ offset: 3, line: 1, column: 0, utf16_column: 0 });
+        assert_eq!(tokens[1].span.end, Position{ offset: 4, line: 1, column: 1, utf16_column: 1 });
+    }
+
+    #[test]
+    fn tokenize_tracks_utf16_columns_separately() {
+        let mut tokenizer = Tokenizer::new("😀l");
+        tokenizer.tokenize_next(1, Category::Text);
+        tokenizer.tokenize_next(1, Category::Text);
+
+        let span = tokenizer.tokens()[1].span;
+        assert_eq!(span.start, Position{ offset: 4, line: 0, column: 1, utf16_column: 2 });
+        assert_eq!(span.end, Position{ offset: 5, line: 0, column: 2, utf16_column: 3 });
+    }
+
+    #[test]
+    fn run_yields_tokens_lazily() {
+        fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+            tokenizer.current_char()?;
+            assert!(tokenizer.position().offset < 2);
+            tokenizer.tokenize_next(1, Category::Text);
+            Some(StateFunction(initial_state))
+        }
+
+        let tokens: Vec<_> = Tokenizer::new("luthor").run(StateFunction(initial_state)).take(2).collect();
+        assert_eq!(tokens[1].lexeme, "u");
+        assert_eq!(tokens[1].span.range(), 1..2);
+    }
+
+    #[test]
+    fn run_yields_remaining_data_once_states_are_exhausted() {
+        fn initial_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+            tokenizer.tokenize_next(2, Category::Keyword);
+            tokenizer.states.push(StateFunction(final_state));
+            None
+        }
+
+        fn final_state(tokenizer: &mut Tokenizer) -> Option<StateFunction> {
+            tokenizer.advance();
+            None
+        }
+
+        let tokens: Vec<_> = Tokenizer::new("luthor").run(StateFunction(initial_state)).collect();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0].lexeme, "lu");
+        assert_eq!(tokens[1].lexeme, "thor");
+        assert_eq!(tokens[1].category, Category::Text);
+    }
+
+    #[test]
+    fn tokens_gives_the_remaining_data_token_a_span() {
+        let mut tokenizer = Tokenizer::new("lu\nthor");
+        tokenizer.tokenize_next(1, Category::Text);
+        tokenizer.advance();
+
+        let span = tokenizer.tokens()[1].span;
+        assert_eq!(span.range(), 1..7);
+        assert_eq!(span.end, Position{ offset: 7, line: 1, column: 4, utf16_column: 4 });
+    }
 }